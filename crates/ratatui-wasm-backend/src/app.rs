@@ -0,0 +1,52 @@
+//! A minimal interface for interactive apps, so that this crate can drive them for you.
+//!
+//! See [run](crate::run), which runs an app with timers, and [mux](crate::mux), which runs many apps at once.
+
+use std::time::Duration;
+
+use ratatui::{Frame, layout::Size};
+
+use crate::input::Sequence;
+
+/// An interactive terminal app.
+pub trait App {
+    /// Handle an event. Return [Control::Quit] to end the session.
+    fn handle_event(&mut self, event: Event) -> Control;
+
+    /// Update time-based state. (ex: a clock, a spinner, or debounced work)
+    ///
+    /// Called once the time from [App::next_tick] has arrived. `now` is from a monotonic
+    /// [Clock](crate::host::Clock).
+    fn update(&mut self, now: Duration) -> Control {
+        let _ = now;
+        Control::Unchanged
+    }
+
+    /// When the app next needs [App::update] to be called, if ever.
+    fn next_tick(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Draw the app's current state.
+    fn render(&mut self, frame: &mut Frame);
+}
+
+/// Something that happened to an app.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// Input from the user.
+    Input(Sequence),
+
+    /// The terminal changed size.
+    Resize(Size),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Control {
+    #[default]
+    Continue,
+
+    /// Continue, but nothing visible changed, so there's no need to redraw.
+    Unchanged,
+    Quit,
+}
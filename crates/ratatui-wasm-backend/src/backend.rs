@@ -1,14 +1,20 @@
-use std::{fmt::Display, io::Write as _, mem};
+use std::{fmt::Display, io::Write, mem, ops::Range};
 
-use anes::{ResetAttributes, SetAttribute, SetBackgroundColor, SetForegroundColor};
 use ratatui::{
-    backend::WindowSize, layout::Position, prelude::Backend, style::{Color, Modifier}
+    backend::{ClearType, WindowSize}, buffer::{Buffer, Cell}, layout::{Position, Rect}, prelude::Backend, style::Color
 };
-use std::io::Error as IOError;
+use std::io::ErrorKind;
 use std::io::Result as IOResult;
-use wasm_bindgen::JsValue;
 
-use crate::types::{JsWriter, JsTermSizeCallback, log_value};
+use crate::error::BackendError;
+use crate::glyphs::{self, Glyph, GlyphFallback};
+use crate::width::{self, WidthPolicy};
+use crate::host::GetSize;
+use crate::image::{GraphicsOptions, GraphicsProtocol, ImageData, ImageId, Images};
+use crate::palette::{PaletteMode, PaletteOptions, RESTORE_PALETTE};
+use crate::sgr::Sgr;
+use crate::terminfo::{BooleanCap, StringCap, Terminfo};
+use crate::types::{JsWriter, JsTermSizeCallback};
 
 pub struct AnsiBackendOptions {
     /// A way to get the terminal size from JavaScript
@@ -23,22 +29,560 @@ pub struct AnsiBackendOptions {
 ///
 /// The caller must provide a callback for fetching window size, and writing output to stdout.
 pub struct AnsiBackend {
-    get_size: JsTermSizeCallback,
+    get_size: Box<dyn GetSize>,
     pos: Option<Position>,
     buf: Vec<u8>,
-    stdout_writer: JsWriter,
+    stdout_writer: Box<dyn Write>,
+
+    /// What we think is on the screen, given what we've output so far.
+    shadow: Buffer,
+
+    /// Something else wrote to the screen, so we need to redraw it from `shadow`.
+    invalid: bool,
+    cursor_hidden: bool,
+
+    /// The colors and attributes the terminal is currently using, if we know.
+    sgr: Option<Sgr>,
+
+    palette: Option<PaletteOptions>,
+
+    /// We've reprogrammed the terminal's palette, and need to restore it.
+    palette_programmed: bool,
+
+    /// Inline images, and where they're placed.
+    images: Images,
+
+    modes: Modes,
+
+    /// Where to get sequences from, if the terminal isn't xterm-compatible.
+    terminfo: Option<Terminfo>,
+
+    glyphs: GlyphFallback,
+
+    /// The terminal is using the DEC line-drawing character set.
+    dec_graphics: bool,
+
+    /// How wide the terminal draws characters.
+    widths: WidthPolicy,
+
+    compression: Compression,
+
+    /// We've handed the terminal back with [AnsiBackend::suspend].
+    suspended: Option<Suspended>,
+
+    /// Holds log messages for us while we're in the alternate screen.
+    #[cfg(feature = "log")]
+    log_screen: Option<crate::logging::ScreenId>,
+}
+
+/// Terminal modes we've turned on, which we need to turn off again.
+#[derive(Default, Clone, Copy)]
+struct Modes {
+    alternate_screen: bool,
+    mouse_capture: bool,
+    bracketed_paste: bool,
+    focus_reporting: bool,
+}
+
+/// Shorter ways to draw runs of cells, which not every terminal understands. See [AnsiBackend::set_compression].
+///
+/// With a [terminfo](AnsiBackend::set_terminfo) entry, each also needs the entry to have the capability.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    /// Draw runs of the same character with REP. (`CSI n b`) Not every terminal has it (ex: screen, and older
+    /// versions of VTE), so this is off by default.
+    pub repeat: bool,
+
+    /// Erase runs of blank cells with ECH (`CSI n X`), and blank ends of rows with EL (`CSI K`), instead of
+    /// printing spaces.
+    pub erase: bool,
+}
+
+impl Compression {
+    /// Print every cell.
+    pub const NONE: Self = Self { repeat: false, erase: false };
+
+    pub const ALL: Self = Self { repeat: true, erase: true };
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self { repeat: false, erase: true }
+    }
+}
+
+/// What [AnsiBackend::suspend] turned off, for [AnsiBackend::resume] to turn on again.
+///
+/// Changes made while suspended go here too, instead of to the terminal.
+struct Suspended {
+    modes: Modes,
+    cursor_hidden: bool,
+    pos: Option<Position>,
+    title: Option<String>,
 }
 
 impl AnsiBackend {
     pub fn new(options: AnsiBackendOptions) -> Self {
         let AnsiBackendOptions{get_size, stdout_writer} = options;
+        Self::with_io(stdout_writer, get_size)
+    }
+
+    /// Create a backend which writes to any [Write], instead of a JavaScript callback.
+    pub fn with_io(stdout_writer: impl Write + 'static, get_size: impl GetSize + 'static) -> Self {
         Self {
-            get_size,
-            stdout_writer,
+            get_size: Box::new(get_size),
+            stdout_writer: Box::new(stdout_writer),
             pos: None,
-            buf: Vec::new()
+            buf: Vec::new(),
+            shadow: Buffer::empty(Rect::ZERO),
+            invalid: false,
+            cursor_hidden: false,
+            sgr: None,
+            palette: None,
+            palette_programmed: false,
+            images: Images::default(),
+            modes: Modes::default(),
+            terminfo: None,
+            glyphs: GlyphFallback::default(),
+            dec_graphics: false,
+            widths: WidthPolicy::default(),
+            compression: Compression::default(),
+            suspended: None,
+            #[cfg(feature = "log")]
+            log_screen: None,
+        }
+    }
+
+    /// Let the backend know that something else has written to the terminal. (ex: console.log)
+    ///
+    /// The whole screen will be redrawn on the next flush.
+    pub fn invalidate(&mut self) {
+        self.invalid = true;
+    }
+
+    /// Clear the screen and redraw everything we've drawn to it. (ex: when the user presses Ctrl-L)
+    pub fn redraw_all(&mut self) -> IOResult<()> {
+        self.invalid = false;
+        self.sgr = None;
+        let pos = self.pos;
+        let placements = self.images.placements();
+        let shadow = mem::replace(&mut self.shadow, Buffer::empty(Rect::ZERO));
+
+        self.clear()?;
+        // Diffing against an empty buffer gives us every non-blank cell, and handles wide characters for us:
+        let updates = Buffer::empty(shadow.area).diff(&shadow);
+        self.draw(updates.into_iter())?;
+        self.shadow = shadow;
+        for (id, area) in placements {
+            self.place_image(id, area)?;
+        }
+
+        if self.cursor_hidden {
+            self.hide_cursor()?;
+        } else {
+            self.show_cursor()?;
+        }
+        if let Some(pos) = pos {
+            self.set_cursor_position(pos)?;
+        }
+        self.flush()
+    }
+
+    /// Use a specific palette, instead of whatever colors the user's terminal theme uses. Pass `None` to go back.
+    ///
+    /// Takes effect for cells drawn after this. You may want to [AnsiBackend::redraw_all] afterward.
+    pub fn set_palette(&mut self, palette: Option<PaletteOptions>) -> IOResult<()> {
+        if self.palette_programmed {
+            self.push(RESTORE_PALETTE)?;
+            self.palette_programmed = false;
+        }
+        self.palette = palette;
+        self.program_palette()
+    }
+
+    fn program_palette(&mut self) -> IOResult<()> {
+        // resume() will do it.
+        if self.suspended.is_some() {
+            return Ok(());
+        }
+        let Some(palette) = &self.palette else { return Ok(()) };
+        if palette.mode != PaletteMode::Program || self.palette_programmed {
+            return Ok(());
+        }
+        let sequence = palette.program();
+        self.push(sequence)?;
+        self.palette_programmed = true;
+        Ok(())
+    }
+
+    /// Use a terminal's terminfo entry for cursor movement, clearing, styles, the alternate screen and cursor
+    /// visibility, instead of xterm's sequences. Pass `None` to go back.
+    ///
+    /// See [crate::terminfo].
+    pub fn set_terminfo(&mut self, terminfo: Option<Terminfo>) {
+        self.terminfo = terminfo;
+        // The new sequences may not line up with what we thought the terminal's state was.
+        self.sgr = None;
+        self.pos = None;
+    }
+
+    /// Draw box-drawing, block and arrow characters some other way, for terminals or fonts that can't show them.
+    ///
+    /// Takes effect for cells drawn after this. You may want to [AnsiBackend::redraw_all] afterward.
+    /// See [crate::glyphs].
+    pub fn set_glyph_fallback(&mut self, fallback: GlyphFallback) {
+        self.glyphs = fallback;
+    }
+
+    /// Ask the terminal how wide it draws characters that terminals disagree about. (ex: emoji)
+    ///
+    /// Pass the reply to [WidthPolicy::from_reply], then call [AnsiBackend::set_width_policy]. See [crate::width].
+    ///
+    /// Does nothing while [suspended](AnsiBackend::suspend).
+    pub fn probe_widths(&mut self) -> IOResult<()> {
+        if self.suspended.is_some() {
+            return Ok(());
+        }
+        let alternate = self.modes.alternate_screen;
+        self.push(width::query(alternate))?;
+        // The query overwrote the attributes, and on the alternate screen, the top row:
+        self.sgr = None;
+        if alternate {
+            let pos = self.pos;
+            let top = Rect::new(0, 0, self.shadow.area.width, 1);
+            self.repaint(top)?;
+            if let Some(pos) = pos {
+                self.set_cursor_position(pos)?;
+            }
+        }
+        self.flush()
+    }
+
+    /// Use `policy` to work out where the cursor is after drawing each character.
+    ///
+    /// You may want to [AnsiBackend::redraw_all] afterward, if you've drawn characters it affects.
+    pub fn set_width_policy(&mut self, policy: WidthPolicy) {
+        self.widths = policy;
+    }
+
+    /// How wide the terminal draws characters. Widgets can use this to measure text.
+    pub fn width_policy(&self) -> WidthPolicy {
+        self.widths
+    }
+
+    /// Choose which shorter sequences to draw runs of cells with. See [Compression].
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// The colors and attributes to draw `cell` with.
+    fn sgr_of(&self, cell: &Cell) -> Sgr {
+        let mut sgr = Sgr::of(cell);
+        if let Some(palette) = &self.palette
+            && palette.mode == PaletteMode::Rewrite
+        {
+            sgr.fg = palette.rewrite(sgr.fg);
+            sgr.bg = palette.rewrite(sgr.bg);
+        }
+        sgr
+    }
+
+    /// Drawing over an image removes it.
+    fn overwrite_images(&mut self, columns: Range<u16>, y: u16, covered: &mut Vec<Rect>) -> IOResult<()> {
+        for x in columns {
+            for (id, area) in self.images.overwrite(x, y) {
+                match self.images.unplace(id) {
+                    Some(unplace) => self.push(unplace)?,
+                    None => covered.push(area),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the terminal has `cap`. (xterm has them all)
+    fn has_cap(&self, cap: StringCap) -> bool {
+        self.terminfo.as_ref().is_none_or(|it| it.string(cap).is_some())
+    }
+
+    /// Whether erasing cells fills them with `bg`.
+    fn can_erase(&self, bg: Color) -> bool {
+        match &self.terminfo {
+            Some(terminfo) => bg == Color::Reset || terminfo.boolean(BooleanCap::BackColorErase),
+            None => true,
+        }
+    }
+
+    /// Whether the row will be blank, in `bg`, from `x` to the end. (And none of it is to be skipped.)
+    fn blank_to_end(&self, x: u16, y: u16, bg: Color) -> bool {
+        (x..self.shadow.area.width)
+            .all(|x| self.shadow.cell((x, y)).is_some_and(|it| is_blank(it) && it.bg == bg && !it.skip))
+    }
+
+    /// The sequence to print the character we just `printed` `count` more times, if it's shorter than printing
+    /// them.
+    fn repeat(&self, printed: &[u8], count: usize) -> Option<Vec<u8>> {
+        if !self.compression.repeat {
+            return None;
+        }
+        let sequence = match &self.terminfo {
+            Some(terminfo) => {
+                // rep takes the character as a single byte:
+                let &last = printed.last()?;
+                if !last.is_ascii() && !self.dec_graphics {
+                    return None;
+                }
+                terminfo.expand(StringCap::RepeatChar, &[last.into(), count as i32])?
+            }
+            None => format!("\x1b[{count}b").into_bytes(),
+        };
+        let char_len = match self.dec_graphics {
+            true => 1,
+            false => std::str::from_utf8(printed).ok()?.chars().last()?.len_utf8(),
+        };
+        (sequence.len() < count * char_len).then_some(sequence)
+    }
+
+    /// Print a cell's symbol, switching character sets if we need to.
+    fn put_symbol(&mut self, symbol: &str) -> IOResult<()> {
+        let text = match self.glyphs.map(symbol) {
+            Glyph::Text(text) => text,
+            Glyph::Dec(ch) => {
+                let ch = match &self.terminfo {
+                    Some(terminfo) => terminfo.acs_char(ch),
+                    None => Some(ch),
+                };
+                match ch {
+                    Some(ch) => {
+                        self.set_dec_graphics(true)?;
+                        self.buf.push(ch);
+                        return Ok(());
+                    }
+                    // The terminal doesn't have it.
+                    None => match GlyphFallback::Ascii.map(symbol) {
+                        Glyph::Text(text) => text,
+                        Glyph::Dec(_) => symbol,
+                    },
+                }
+            }
+        };
+
+        // Most ASCII means the same in both sets, so we can often stay in the line-drawing set. (ex: spaces)
+        if self.dec_graphics && text.bytes().any(|it| !it.is_ascii() || glyphs::dec_remaps(it)) {
+            self.set_dec_graphics(false)?;
+        }
+        self.buf.extend_from_slice(text.as_bytes());
+        Ok(())
+    }
+
+    fn set_dec_graphics(&mut self, enable: bool) -> IOResult<()> {
+        if self.dec_graphics == enable {
+            return Ok(());
+        }
+        self.dec_graphics = enable;
+        if enable {
+            // Some terminals need to be told which set to use first. (ex: vt100 uses shift out, to G1)
+            self.push_cap(StringCap::EnaAcs, &[], "")?;
+            self.push_cap(StringCap::EnterAltCharsetMode, &[], "\x1b(0")
+        } else {
+            self.push_cap(StringCap::ExitAltCharsetMode, &[], "\x1b(B")
+        }
+    }
+
+    /// Push a capability from the terminfo entry, if we have one, or else the xterm sequence.
+    ///
+    /// Pushes nothing if the terminfo entry doesn't have the capability.
+    fn push_cap(&mut self, cap: StringCap, params: &[i32], xterm: impl Display) -> IOResult<()> {
+        match &self.terminfo {
+            Some(terminfo) => {
+                let sequence = terminfo.expand(cap, params).unwrap_or_default();
+                self.buf.extend_from_slice(&sequence);
+                Ok(())
+            }
+            None => self.push(xterm),
+        }
+    }
+
+    /// Enable inline images, using the given protocol. Pass `None` to disable them.
+    ///
+    /// See [crate::image] for how to detect which protocol the terminal supports.
+    pub fn set_graphics(&mut self, options: Option<GraphicsOptions>) -> IOResult<()> {
+        if let Some(clear) = self.images.clear() {
+            self.push(clear)?;
+        }
+        self.images.options = options;
+        Ok(())
+    }
+
+    /// Register an image, so that it can be placed with [AnsiBackend::place_image].
+    ///
+    /// Replaces (and removes from the screen) any image with the same id.
+    pub fn add_image(&mut self, id: ImageId, data: ImageData) -> IOResult<()> {
+        self.remove_image(id)?;
+        self.images.add(id, data);
+        Ok(())
+    }
+
+    /// Show an image, scaled to fill `area`. Call this after each draw, while the image should be visible.
+    ///
+    /// Does nothing if the image is already shown there. If it's shown elsewhere, it's moved. Drawing any cell
+    /// within `area` removes the image again.
+    ///
+    /// Note that a sixel image touching the bottom row of the screen may scroll it. Does nothing while
+    /// [suspended](AnsiBackend::suspend).
+    pub fn place_image(&mut self, id: ImageId, area: Rect) -> IOResult<()> {
+        if self.suspended.is_some() {
+            return Ok(());
+        }
+        self.resize_shadow()?;
+        let area = area.intersection(self.shadow.area);
+        if self.images.placement(id) == Some(area) || area.is_empty() {
+            return Ok(());
+        }
+        self.take_down_image(id)?;
+
+        self.set_cursor_position(area.as_position())?;
+        let sequence = self.images.place(id, area)?;
+        self.push(sequence)?;
+        if self.images.options.is_some_and(|it| it.protocol == GraphicsProtocol::Sixel) {
+            // The terminal moves the cursor past the image:
+            self.pos = None;
+        }
+        self.flush()
+    }
+
+    /// Take an image off the screen, and forget it.
+    pub fn remove_image(&mut self, id: ImageId) -> IOResult<()> {
+        self.take_down_image(id)?;
+        if let Some(delete) = self.images.delete(id) {
+            self.push(delete)?;
+        }
+        self.images.remove(id);
+        self.flush()
+    }
+
+    /// Take an image off the screen, but keep its data for later.
+    fn take_down_image(&mut self, id: ImageId) -> IOResult<()> {
+        let Some(area) = self.images.placement(id) else { return Ok(()) };
+        match self.images.unplace(id) {
+            Some(unplace) => self.push(unplace),
+            // We can only cover up sixel images, with whatever text is underneath them:
+            None => self.repaint(area),
         }
     }
+
+    /// Draw the cells in `area` again, from the shadow buffer.
+    fn repaint(&mut self, area: Rect) -> IOResult<()> {
+        let mut cells = vec![];
+        for y in area.top()..area.bottom() {
+            let mut x = 0;
+            while x < area.right() {
+                let Some(cell) = self.shadow.cell((x, y)) else { break };
+                let width = (self.widths.width(cell.symbol()) as u16).max(1);
+                // Include wide characters that start left of the area, but cover part of it:
+                if x + width > area.left() {
+                    cells.push((x, y, cell.clone()));
+                }
+                x += width;
+            }
+        }
+        self.draw(cells.iter().map(|(x, y, cell)| (*x, *y, cell)))
+    }
+
+    /// Ask the terminal to report mouse clicks, drags and scrolling as input.
+    pub fn set_mouse_capture(&mut self, enable: bool) -> IOResult<()> {
+        self.modes_mut().mouse_capture = enable;
+        if self.suspended.is_some() {
+            return Ok(());
+        }
+        if enable {
+            self.push(anes::EnableMouseEvents)
+        } else {
+            self.push(anes::DisableMouseEvents)
+        }
+    }
+
+    /// Ask the terminal to mark the start and end of pasted text, so that it can't be mistaken for typing.
+    pub fn set_bracketed_paste(&mut self, enable: bool) -> IOResult<()> {
+        self.modes_mut().bracketed_paste = enable;
+        if self.suspended.is_some() {
+            return Ok(());
+        }
+        self.push(if enable { "\x1b[?2004h" } else { "\x1b[?2004l" })
+    }
+
+    /// Ask the terminal to report when it gains or loses focus.
+    pub fn set_focus_reporting(&mut self, enable: bool) -> IOResult<()> {
+        self.modes_mut().focus_reporting = enable;
+        if self.suspended.is_some() {
+            return Ok(());
+        }
+        self.push(if enable { "\x1b[?1004h" } else { "\x1b[?1004l" })
+    }
+
+    /// Set the terminal window's title.
+    pub fn set_title(&mut self, title: &str) -> IOResult<()> {
+        // Control characters would end the sequence early:
+        let title: String = title.chars().filter(|it| !it.is_control()).collect();
+        if let Some(suspended) = &mut self.suspended {
+            suspended.title = Some(title);
+            return Ok(());
+        }
+        self.push(format_args!("\x1b]0;{title}\x1b\\"))
+    }
+
+    /// The modes to change: the terminal's, or the ones to turn back on when we resume.
+    fn modes_mut(&mut self) -> &mut Modes {
+        match &mut self.suspended {
+            Some(suspended) => &mut suspended.modes,
+            None => &mut self.modes,
+        }
+    }
+
+    /// Turn off any modes we've turned on.
+    fn reset_modes(&mut self) -> IOResult<()> {
+        let modes = self.modes;
+        if modes.mouse_capture {
+            self.set_mouse_capture(false)?;
+        }
+        if modes.bracketed_paste {
+            self.set_bracketed_paste(false)?;
+        }
+        if modes.focus_reporting {
+            self.set_focus_reporting(false)?;
+        }
+        Ok(())
+    }
+
+    /// A copy of what's currently on the screen, as far as this backend knows.
+    pub fn snapshot(&self) -> Buffer {
+        self.shadow.clone()
+    }
+
+    /// Record that `cell` was drawn at (x, y).
+    fn remember(&mut self, x: u16, y: u16, cell: &Cell) {
+        let Some(shadow) = self.shadow.cell_mut((x, y)) else { return };
+        *shadow = cell.clone();
+
+        // Ratatui won't send us the cells covered by a wide character. On screen, they look like blanks in the
+        // same style:
+        let width = self.widths.width(cell.symbol()) as u16;
+        for covered in 1..width {
+            let Some(shadow) = self.shadow.cell_mut((x + covered, y)) else { break };
+            *shadow = cell.clone();
+            shadow.set_char(' ');
+        }
+    }
+
+    /// Make sure the shadow buffer matches the size of the terminal.
+    fn resize_shadow(&mut self) -> IOResult<()> {
+        let size = self.get_size.get_size()?;
+        let area = Rect::new(0, 0, size.width, size.height);
+        if self.shadow.area != area {
+            self.shadow.resize(area);
+        }
+        Ok(())
+    }
 }
 
 impl ratatui::backend::Backend for AnsiBackend {
@@ -46,65 +590,119 @@ impl ratatui::backend::Backend for AnsiBackend {
     where
         I: Iterator<Item = (u16, u16, &'a ratatui::buffer::Cell)>,
     {
-        let mut prev_pos: Option<Position> = None;
-        let mut prev_mod: Option<Modifier> = None;
-        let mut prev_fg: Option<Color> = None;
-        let mut prev_bg: Option<Color> = None;
+        self.resize_shadow()?;
+        if self.suspended.is_some() {
+            // The screen isn't ours right now. resume() will draw what it should look like.
+            for (x, y, cell) in content {
+                self.remember(x, y, cell);
+            }
+            return Ok(());
+        }
+        // Remember everything first, so that we can tell when the rest of a row will be blank:
+        let content: Vec<_> = content.collect();
+        for &(x, y, cell) in &content {
+            self.remember(x, y, cell);
+        }
 
-        for (x, y, cell) in content {
+        let mut covered_images = vec![];
+        let mut i = 0;
+        while i < content.len() {
+            let (x, y, cell) = content[i];
             if cell.skip {
+                i += 1;
                 continue;
             }
 
-            let mut new_pos = Position { x, y };
-            if let Some(prev_pos) = prev_pos {
-                if new_pos != prev_pos {
-                    self.set_cursor_position(new_pos)?;
+            let pos = Position { x, y };
+            self.set_cursor_position(pos)?;
+            let sgr = self.sgr_of(cell);
+
+            // Runs of blank cells can be erased, instead of printed:
+            if is_blank(cell) && self.compression.erase && self.can_erase(sgr.bg) {
+                if self.has_cap(StringCap::ClrEol) && self.blank_to_end(x, y, cell.bg) {
+                    self.overwrite_images(x..self.shadow.area.width, y, &mut covered_images)?;
+                    self.apply_style(sgr);
+                    self.push_cap(StringCap::ClrEol, &[], "\x1b[K")?;
+                    // That took care of the rest of the row:
+                    while i < content.len() && content[i].1 == y {
+                        i += 1;
+                    }
+                    continue;
+                }
+
+                let run = run_length(&content[i..], |it| is_blank(it) && it.bg == cell.bg);
+                let end = x + run as u16;
+                let erase = format!("\x1b[{run}X");
+                let next_adjacent = content.get(i + run).is_some_and(|&(x, next_y, _)| (x, next_y) == (end, y));
+                // Erasing doesn't move the cursor. If we're drawing the next cell, we have to:
+                let cost = if next_adjacent { erase.len() * 2 } else { erase.len() };
+                if self.has_cap(StringCap::EraseChars) && cost < run {
+                    self.overwrite_images(x..end, y, &mut covered_images)?;
+                    self.apply_style(sgr);
+                    self.push_cap(StringCap::EraseChars, &[run as i32], erase)?;
+                    if next_adjacent && self.has_cap(StringCap::ParmRightCursor) {
+                        self.push_cap(StringCap::ParmRightCursor, &[run as i32], format_args!("\x1b[{run}C"))?;
+                        self.pos = Some(Position { x: end, y });
+                    }
+                    i += run;
+                    continue;
                 }
-            } else {
-                self.set_cursor_position(new_pos)?;
             }
-            prev_pos = Some(new_pos);
 
-            self.apply_modifiers(&mut prev_mod, &cell.modifier)?;
+            self.apply_style(sgr);
+            let printed = self.buf.len();
+            self.put_symbol(cell.symbol())?;
+            let width = self.widths.width(cell.symbol()) as u16;
 
-            
-            // TODO: colors
-            if prev_bg != Some(cell.bg) {
-                self.push(SetBackgroundColor(ansi_color(cell.bg)))?;
-                prev_bg = Some(cell.bg);
-            }
-            
-            if prev_fg != Some(cell.fg) {
-                self.push(SetForegroundColor(ansi_color(cell.fg)))?;
-                prev_fg = Some(cell.fg);
+            // Runs of the same character can be repeated, instead of printed:
+            let mut drawn = 1;
+            if width == 1 && cell.symbol().chars().count() == 1 {
+                let run = run_length(&content[i..], |it| it == cell);
+                if let Some(repeat) = self.repeat(&self.buf[printed..], run - 1) {
+                    self.buf.extend(repeat);
+                    drawn = run;
+                }
             }
+            let end = x + width * drawn as u16;
+            self.overwrite_images(x..end.max(x + 1), y, &mut covered_images)?;
 
-
-            self.buf.extend_from_slice(cell.symbol().as_bytes());
-            // TODO: Check unicode width. (Why doesn't Ratatui give us this?)
-            let width = 1;
-            new_pos.x += width;
-            // TODO: Wrap?
-            prev_pos = Some(new_pos)
+            self.pos = Some(Position { x: end, y });
+            i += drawn;
         }
+        // Anything else we print should come out as-is.
+        self.set_dec_graphics(false)?;
         self.flush()?;
-        if let Some(pos) = prev_pos {
-            self.pos = Some(pos);
+
+        // Cover up what's left of any sixel images we drew over:
+        for area in covered_images {
+            self.repaint(area)?;
         }
 
         Ok(())
     }
 
     fn hide_cursor(&mut self) -> IOResult<()> {
-        write!(self.buf, "{}", anes::HideCursor)
+        if let Some(suspended) = &mut self.suspended {
+            suspended.cursor_hidden = true;
+            return Ok(());
+        }
+        self.cursor_hidden = true;
+        self.push_cap(StringCap::CursorInvisible, &[], anes::HideCursor)
     }
 
     fn show_cursor(&mut self) -> IOResult<()> {
-        write!(self.buf, "{}", anes::ShowCursor)
+        if let Some(suspended) = &mut self.suspended {
+            suspended.cursor_hidden = false;
+            return Ok(());
+        }
+        self.cursor_hidden = false;
+        self.push_cap(StringCap::CursorNormal, &[], anes::ShowCursor)
     }
 
     fn get_cursor_position(&mut self) -> IOResult<ratatui::prelude::Position> {
+        if let Some(suspended) = &self.suspended {
+            return Ok(suspended.pos.unwrap_or(Position::ORIGIN));
+        }
         let pos = match self.pos {
             Some(pos) => pos,
             None => {
@@ -123,25 +721,96 @@ impl ratatui::backend::Backend for AnsiBackend {
         new_pos_into: P,
     ) -> IOResult<()> {
         let new_pos: Position = new_pos_into.into();
+        if let Some(suspended) = &mut self.suspended {
+            suspended.pos = Some(new_pos);
+            return Ok(());
+        }
         if Some(new_pos) == self.pos {
             return Ok(());
         }
 
-        self.push(anes::MoveCursorTo(new_pos.x + 1, new_pos.y + 1))?;
+        let (x, y) = (new_pos.x, new_pos.y);
+        self.push_cap(StringCap::CursorAddress, &[y.into(), x.into()], anes::MoveCursorTo(x + 1, y + 1))?;
         self.pos = Some(new_pos);
         Ok(())
     }
 
     fn clear(&mut self) -> IOResult<()> {
+        self.shadow.reset();
+        self.resize_shadow()?;
+        let clear_images = self.images.clear();
+        if self.suspended.is_some() {
+            // resume() will draw the blank screen.
+            return Ok(());
+        }
+
         // If there's a remaining color it'll set the whole screen to that color. We don't want that:
-        self.push(anes::ResetAttributes)?;
+        self.apply_style(Sgr::default());
+        if let Some(clear) = clear_images {
+            self.push(clear)?;
+        }
+        if self.terminfo.is_none() {
+            return self.push(anes::ClearBuffer::All);
+        }
+        if !self.has_cap(StringCap::ClearScreen) {
+            // Without `clear`, move home and clear to the end of the screen.
+            self.set_cursor_position(Position::ORIGIN)?;
+            return self.push_cap(StringCap::ClrEos, &[], "");
+        }
+        // Terminfo's clear also moves the cursor home.
+        self.pos = Some(Position::ORIGIN);
+        self.push_cap(StringCap::ClearScreen, &[], "")
+    }
+
+    fn clear_region(&mut self, clear_type: ClearType) -> IOResult<()> {
+        if clear_type == ClearType::All {
+            return self.clear();
+        }
+        self.resize_shadow()?;
+        let pos = self.get_cursor_position()?;
+        let area = self.shadow.area;
+
+        // Which cells get erased, as (first, last) inclusive positions in reading order:
+        let (first, last) = match clear_type {
+            ClearType::AfterCursor => (pos, Position::new(area.width, area.height)),
+            ClearType::BeforeCursor => (Position::ORIGIN, pos),
+            ClearType::CurrentLine => (Position::new(0, pos.y), Position::new(area.width, pos.y)),
+            ClearType::UntilNewLine | ClearType::All => (pos, Position::new(area.width, pos.y)),
+        };
+        for cell_pos in area.positions() {
+            let index = (cell_pos.y, cell_pos.x);
+            if index >= (first.y, first.x) && index <= (last.y, last.x) {
+                self.shadow[cell_pos].reset();
+            }
+        }
+        if self.suspended.is_some() {
+            return Ok(());
+        }
 
-        self.push(anes::ClearBuffer::All)
+        self.apply_style(Sgr::default());
+        match clear_type {
+            ClearType::AfterCursor => self.push_cap(StringCap::ClrEos, &[], anes::ClearBuffer::Below),
+            ClearType::BeforeCursor if self.terminfo.is_some() => {
+                // Terminfo doesn't have "clear above", so clear each line.
+                for y in 0..pos.y {
+                    self.set_cursor_position(Position::new(0, y))?;
+                    self.push_cap(StringCap::ClrEol, &[], "")?;
+                }
+                self.set_cursor_position(pos)?;
+                self.push_cap(StringCap::ClrBol, &[], "")
+            }
+            ClearType::BeforeCursor => self.push(anes::ClearBuffer::Above),
+            ClearType::CurrentLine if self.terminfo.is_some() => {
+                self.push_cap(StringCap::ClrBol, &[], "")?;
+                self.push_cap(StringCap::ClrEol, &[], "")
+            }
+            ClearType::CurrentLine => self.push(anes::ClearLine::All),
+            ClearType::UntilNewLine | ClearType::All => self.push_cap(StringCap::ClrEol, &[], anes::ClearLine::Right),
+        }
     }
 
     fn size(&self) -> IOResult<ratatui::prelude::Size> {
-        let size = self.get_size.get().map_err(|_| io_err("Error getting the size of the terminal"))?;
-        Ok(size.into())
+        self.get_size.get_size()
     }
 
     fn window_size(&mut self) -> IOResult<ratatui::backend::WindowSize> {
@@ -152,31 +821,45 @@ impl ratatui::backend::Backend for AnsiBackend {
     }
 
     fn flush(&mut self) -> IOResult<()> {
+        if self.suspended.is_some() {
+            // The screen isn't ours. Anything we'd write now, resume() will write instead.
+            self.buf.clear();
+            return Ok(());
+        }
+        if self.invalid {
+            return self.redraw_all();
+        }
+
         if self.buf.is_empty() {
             return Ok(());
         }
+
         let bytes = mem::take(&mut self.buf);
-        self.stdout_writer
-            .call(JsValue::NULL, bytes.into_boxed_slice())
-            .map_err(|err| {
-                log_value(err);
-                io_err("Writing to stdout threw an error")
-            })?;
-        Ok(())
+        let mut written = 0;
+        while written < bytes.len() {
+            match self.stdout_writer.write(&bytes[written..]) {
+                Ok(0) => return Err(BackendError::ShortWrite { written, expected: bytes.len() }.into()),
+                Ok(count) => written += count,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        self.stdout_writer.flush()
     }
 }
 
-fn io_err<E>(message: E) -> std::io::Error 
-where E: Into<Box<dyn std::error::Error + Send + Sync>>
-{
-    use std::io::ErrorKind::Other;
-    IOError::new(Other, message)
-}
-
 impl AnsiBackend {
     /// Enable terminal "Alternate Buffer Mode"
     pub fn exclusive(&mut self) -> IOResult<()> {
-        self.push(anes::SwitchBufferToAlternate)?;
+        // While suspended, resume() switches instead.
+        if self.suspended.is_none() {
+            #[cfg(feature = "log")]
+            self.hold_log();
+
+            self.push_cap(StringCap::EnterCaMode, &[], anes::SwitchBufferToAlternate)?;
+        }
+        self.modes_mut().alternate_screen = true;
+        self.program_palette()?;
         self.set_cursor_position(Position{x: 0, y: 0})?;
         self.clear()
     }
@@ -185,90 +868,199 @@ impl AnsiBackend {
     pub fn normal(&mut self) -> IOResult<()> {
         self.set_cursor_position(Position{x: 0, y: 0})?;
         self.clear()?;
-        self.push(anes::SwitchBufferToNormal)
-    }
+        if let Some(suspended) = &mut self.suspended {
+            // suspend() already turned everything off. Just don't turn it back on when we resume.
+            suspended.modes = Modes::default();
+            return Ok(());
+        }
+        self.push_cap(StringCap::ExitCaMode, &[], anes::SwitchBufferToNormal)?;
+        self.modes.alternate_screen = false;
+        self.reset_modes()?;
+        if self.palette_programmed {
+            self.push(RESTORE_PALETTE)?;
+            self.palette_programmed = false;
+        }
 
-    pub fn beep(&mut self) -> IOResult<()> {
-        self.push('\u{7}')
+        // Now it's safe to show any log messages we held onto:
+        #[cfg(feature = "log")]
+        self.release_log()?;
+
+        Ok(())
     }
 
-    fn apply_modifiers(&mut self, old: &mut Option<Modifier>, new: &Modifier) -> IOResult<()> {
-        if let Some(prev) = old {
-            if prev == new {
-                return Ok(());
-            }
+    /// Hand the terminal back to the host for a while, without forgetting what was on it. (ex: to run an editor,
+    /// or on Ctrl-Z)
+    ///
+    /// Leaves the alternate screen, turns off the modes we've turned on, restores the palette, and shows the
+    /// cursor. Unlike [AnsiBackend::normal], this doesn't clear the screen first. Call [AnsiBackend::resume] to
+    /// take the terminal back.
+    ///
+    /// While suspended, drawing doesn't write anything. The backend just remembers it, for when it resumes.
+    pub fn suspend(&mut self) -> IOResult<()> {
+        if self.suspended.is_some() {
+            return Ok(());
         }
-
-        let prev = match old {
-            Some(prev) => prev.clone(),
-            None => {
-                // We don't know what the previous state was, so reset it to be safe:
-                self.push(ResetAttributes)?;
-                Modifier::empty()
-            }
+        let suspended = Suspended {
+            modes: self.modes,
+            cursor_hidden: self.cursor_hidden,
+            pos: self.pos,
+            title: None,
         };
 
-        let to_set = *new - prev;
-        let to_del = prev - *new;
+        self.apply_style(Sgr::default());
+        if suspended.modes.alternate_screen {
+            self.push_cap(StringCap::ExitCaMode, &[], anes::SwitchBufferToNormal)?;
+            self.modes.alternate_screen = false;
+        } else if self.shadow.area.height > 0 {
+            // Leave what we drew where it is, and give the host a fresh line below it:
+            self.set_cursor_position(Position { x: 0, y: self.shadow.area.height - 1 })?;
+            self.push("\r\n")?;
+        }
+        self.reset_modes()?;
+        if self.palette_programmed {
+            self.push(RESTORE_PALETTE)?;
+            self.palette_programmed = false;
+        }
+        self.show_cursor()?;
 
-        use SetAttribute as Set;
-        use anes::Attribute as AA;
+        #[cfg(feature = "log")]
+        self.release_log()?;
+        self.flush()?;
 
-        // Bold/faint/normal are all mutually exclusive
-        if to_set.contains(Modifier::BOLD) {
-            self.push(Set(AA::Bold))?;
-        } else if to_set.contains(Modifier::DIM) {
-            self.push(Set(AA::Faint))?;
-        } else if to_del.contains(Modifier::DIM) || to_del.contains(Modifier::BOLD) {
-            self.push(Set(AA::Normal))?;
-        }
+        // Whatever runs next will move the cursor, and change the attributes:
+        self.pos = None;
+        self.sgr = None;
+        self.suspended = Some(suspended);
+        Ok(())
+    }
+
+    /// Take the terminal back after [AnsiBackend::suspend].
+    ///
+    /// Turns the alternate screen and every mode back on, and redraws everything, since whatever ran in the
+    /// meantime may have changed the screen.
+    pub fn resume(&mut self) -> IOResult<()> {
+        let Some(suspended) = self.suspended.take() else { return Ok(()) };
+        let Suspended { modes, cursor_hidden, pos, title } = suspended;
 
-        if to_set.contains(Modifier::CROSSED_OUT) {
-            self.push(Set(AA::Crossed))?;
-        } else if to_del.contains(Modifier::CROSSED_OUT) {
-            self.push(Set(AA::CrossedOff))?;
+        if modes.alternate_screen {
+            #[cfg(feature = "log")]
+            self.hold_log();
+
+            self.push_cap(StringCap::EnterCaMode, &[], anes::SwitchBufferToAlternate)?;
+            self.modes.alternate_screen = true;
+            // Only in the alternate screen, so the host's own output keeps the user's colors:
+            self.program_palette()?;
+        }
+        if modes.mouse_capture {
+            self.set_mouse_capture(true)?;
+        }
+        if modes.bracketed_paste {
+            self.set_bracketed_paste(true)?;
+        }
+        if modes.focus_reporting {
+            self.set_focus_reporting(true)?;
+        }
+        if let Some(title) = title {
+            self.set_title(&title)?;
         }
 
-        if to_set.contains(Modifier::HIDDEN) {
-            self.push(Set(AA::Conceal))?;
-        } else if to_del.contains(Modifier::HIDDEN) {
-            self.push(Set(AA::ConcealOff))?;
+        // Whatever ran in the meantime left the cursor somewhere we don't know. Move it before drawing anything,
+        // then put it back where the app wants it.
+        self.pos = None;
+        self.redraw_all()?;
+        self.cursor_hidden = cursor_hidden;
+        if cursor_hidden {
+            self.hide_cursor()?;
+        }
+        if let Some(pos) = pos {
+            self.set_cursor_position(pos)?;
         }
+        self.flush()
+    }
 
-        if to_set.contains(Modifier::ITALIC) {
-            self.push(Set(AA::Italic))?;
-        } else if to_del.contains(Modifier::ITALIC) {
-            self.push(Set(AA::ItalicOff))?;
+    /// The terminal has been handed back with [AnsiBackend::suspend], and not [resumed](AnsiBackend::resume) yet.
+    pub fn is_suspended(&self) -> bool {
+        self.suspended.is_some()
+    }
+
+    pub fn beep(&mut self) -> IOResult<()> {
+        if self.suspended.is_some() {
+            return Ok(());
         }
+        self.push_cap(StringCap::Bell, &[], '\u{7}')
+    }
 
-        if to_set.contains(Modifier::RAPID_BLINK) || to_set.contains(Modifier::SLOW_BLINK) {
-            self.push(Set(AA::Blink))?;
-        } else if to_del.contains(Modifier::RAPID_BLINK) || to_del.contains(Modifier::SLOW_BLINK) {
-            self.push(Set(AA::BlinkOff))?;
+    /// Change the terminal's colors and attributes, if they're not already `sgr`.
+    fn apply_style(&mut self, sgr: Sgr) {
+        if let Some(terminfo) = &self.terminfo {
+            let sequence = terminfo.sgr(&sgr, self.sgr.as_ref());
+            if !sequence.is_empty() && self.dec_graphics {
+                // sgr0 often switches back to the normal character set, too. Make sure of which one we're in.
+                self.buf.extend(terminfo.expand(StringCap::ExitAltCharsetMode, &[]).unwrap_or_default());
+                self.dec_graphics = false;
+            }
+            self.buf.extend(sequence);
+        } else {
+            let mut out = String::new();
+            sgr.transition(self.sgr.as_ref(), &mut out);
+            self.buf.extend_from_slice(out.as_bytes());
         }
+        self.sgr = Some(sgr);
+    }
+
+    /// The id that log messages are held for this backend under, while it's in exclusive mode. Pass it to
+    /// [logging::scope](crate::logging::scope) to hold messages for this backend only.
+    #[cfg(feature = "log")]
+    pub fn log_screen(&self) -> Option<crate::logging::ScreenId> {
+        self.log_screen
+    }
 
-        if to_set.contains(Modifier::REVERSED) {
-            self.push(Set(AA::Reverse))?;
-        } else if to_del.contains(Modifier::REVERSED) {
-            self.push(Set(AA::ReverseOff))?;
+    /// Start holding log messages for this backend.
+    #[cfg(feature = "log")]
+    fn hold_log(&mut self) {
+        if self.log_screen.is_none() {
+            self.log_screen = Some(crate::logging::enter_exclusive());
         }
+    }
 
-        if to_set.contains(Modifier::UNDERLINED) {
-            self.push(Set(AA::Underline))?;
-        } else if to_del.contains(Modifier::UNDERLINED) {
-            self.push(Set(AA::UnderlineOff))?;
+    /// Stop holding log messages, and print the ones we held.
+    #[cfg(feature = "log")]
+    fn release_log(&mut self) -> IOResult<()> {
+        match self.log_screen.take() {
+            Some(screen) => self.push_log(crate::logging::leave_exclusive(screen)),
+            None => Ok(()),
         }
+    }
 
-        *old = Some(*new);
+    /// Print log messages. The terminal may be in raw mode, so we need to return the cursor to the start of each line.
+    #[cfg(feature = "log")]
+    fn push_log(&mut self, messages: String) -> IOResult<()> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        self.apply_style(Sgr::default());
+        self.push(messages.replace('\n', "\r\n"))?;
+        self.pos = None;
         Ok(())
     }
 
     fn push(&mut self, ansi: impl Display) -> IOResult<()> {
-        write!(self.buf, "{}", ansi)
+        // Writing to a Vec can only fail if formatting fails:
+        write!(self.buf, "{}", ansi).map_err(|err| BackendError::Encoding(err.to_string()).into())
+    }
+}
+
+#[cfg(feature = "log")]
+impl Drop for AnsiBackend {
+    fn drop(&mut self) {
+        // There's no screen left to print them on, but a sink may still want them.
+        if let Some(screen) = self.log_screen.take() {
+            crate::logging::leave_exclusive(screen);
+        }
     }
 }
 
-fn ansi_color(color: ratatui::style::Color) -> anes::Color {
+pub(crate) fn ansi_color(color: ratatui::style::Color) -> anes::Color {
     use anes::Color as AColor;
     use ratatui::style::Color as RColor;
 
@@ -281,7 +1073,7 @@ fn ansi_color(color: ratatui::style::Color) -> anes::Color {
         RColor::Blue => AColor::DarkBlue,
         RColor::Magenta => AColor::DarkMagenta,
         RColor::Cyan => AColor::DarkCyan,
-        RColor::Gray => AColor::DarkGray,
+        RColor::Gray => AColor::Gray,
         RColor::DarkGray => AColor::DarkGray,
         RColor::LightRed => AColor::Red,
         RColor::LightGreen => AColor::Green,
@@ -294,3 +1086,424 @@ fn ansi_color(color: ratatui::style::Color) -> anes::Color {
         RColor::Indexed(code) => AColor::Ansi(code),
     }
 }
+
+/// Erasing a cell makes it look like this, in whatever background color is current.
+fn is_blank(cell: &Cell) -> bool {
+    // The foreground color of a space is invisible, but it's reset too. Keep snapshots exact.
+    cell.symbol() == " " && cell.fg == Color::Reset && cell.modifier.is_empty()
+}
+
+/// How many cells at the start of `content` match `same`, side by side on the same row.
+fn run_length(content: &[(u16, u16, &Cell)], same: impl Fn(&Cell) -> bool) -> usize {
+    let (x, y, _) = content[0];
+    content
+        .iter()
+        .enumerate()
+        .take_while(|&(i, &(cell_x, cell_y, cell))| {
+            (cell_x as usize, cell_y) == (x as usize + i, y) && !cell.skip && same(cell)
+        })
+        .count()
+}
+
+#[cfg(all(test, feature = "parser"))]
+mod tests {
+    use ratatui::{
+        layout::Size,
+        style::Stylize as _,
+        text::Line,
+        widgets::{Block, BorderType, Paragraph},
+    };
+
+    use super::*;
+    use crate::{
+        palette::{Palette, Rgb},
+        testing::TestHost,
+    };
+
+    /// Draw each of `frames` with and without compression. Checks that the screens come out the same, and returns
+    /// the compressed output.
+    fn compare(frames: &[Buffer], setup: impl Fn(&mut AnsiBackend)) -> String {
+        let size = frames[0].area.as_size();
+        let mut outputs = vec![];
+        let mut screens = vec![];
+        for compression in [Compression::NONE, Compression::ALL] {
+            let mut host = TestHost::new(size);
+            let mut terminal = host.terminal().unwrap();
+            terminal.backend_mut().set_compression(compression);
+            setup(terminal.backend_mut());
+            let mut frame_screens = vec![];
+            for buffer in frames {
+                terminal.draw(|frame| frame.buffer_mut().clone_from(buffer)).unwrap();
+                frame_screens.push(host.screen().clone());
+            }
+            outputs.push(String::from_utf8(host.output()).unwrap());
+            screens.push(frame_screens);
+        }
+        assert_eq!(screens[0], screens[1]);
+        let text = |buffer: &Buffer| buffer.content.iter().map(Cell::symbol).collect::<String>();
+        for (screen, frame) in screens[1].iter().zip(frames) {
+            assert_eq!(text(screen), text(frame));
+        }
+        assert!(outputs[1].len() <= outputs[0].len(), "compressed: {:?}\nplain: {:?}", outputs[1], outputs[0]);
+        outputs.pop().unwrap()
+    }
+
+    fn render(size: Size, widget: impl ratatui::widgets::Widget) -> Buffer {
+        let area = Rect::new(0, 0, size.width, size.height);
+        let mut buffer = Buffer::empty(area);
+        widget.render(area, &mut buffer);
+        buffer
+    }
+
+    #[test]
+    fn borders() {
+        let size = Size::new(30, 6);
+        let output = compare(&[render(size, Block::bordered().title("title"))], |_| {});
+        assert!(output.contains("─\x1b[22b"), "{output:?}");
+    }
+
+    #[test]
+    fn blank_runs_with_background() {
+        let size = Size::new(30, 4);
+        let text = "left                right\nfull width background";
+        let output = compare(
+            &[
+                render(size, Paragraph::new(text).on_blue()),
+                render(size, Paragraph::new(text).on_red()),
+                render(size, Paragraph::new("x").on_red()),
+            ],
+            |_| {},
+        );
+        assert!(output.contains("\x1b[16X"), "{output:?}");
+        assert!(output.contains("\x1b[K"), "{output:?}");
+    }
+
+    #[test]
+    fn runs_after_dec_charset_switch() {
+        let size = Size::new(20, 4);
+        let output = compare(&[render(size, Block::bordered().title("a"))], |backend| {
+            backend.set_glyph_fallback(GlyphFallback::DecLineDrawing);
+        });
+        assert!(output.contains("\x1b(0l\x1b(Ba\x1b(0q\x1b[16bk"), "{output:?}");
+    }
+
+    #[test]
+    fn runs_next_to_wide_characters() {
+        let size = Size::new(20, 3);
+        let output = compare(
+            &[
+                render(size, Paragraph::new("日本──────────日\n     日     ")),
+                render(size, Paragraph::new("──日────────────────\n日")),
+            ],
+            |_| {},
+        );
+        assert!(output.contains("b"), "{output:?}");
+    }
+
+    #[test]
+    fn runs_ending_at_last_column() {
+        let size = Size::new(12, 3);
+        compare(
+            &[
+                render(size, Paragraph::new("ab==========\nab          ".on_green())),
+                render(size, Paragraph::new("ab        ==\n============")),
+            ],
+            |_| {},
+        );
+    }
+
+    #[test]
+    fn repeat_is_opt_in() {
+        let host = TestHost::new(Size::new(20, 3));
+        let mut terminal = host.terminal().unwrap();
+        terminal.draw(|frame| frame.render_widget(Block::bordered(), frame.area())).unwrap();
+        let output = String::from_utf8(host.output()).unwrap();
+        assert!(!output.contains("b"), "{output:?}");
+    }
+
+    #[test]
+    fn skipped_cells_are_not_erased() {
+        let size = Size::new(10, 1);
+        let mut host = TestHost::new(size);
+        let mut backend = host.backend();
+        backend.set_compression(Compression::ALL);
+        let mut cells = vec![Cell::new("X"); 10];
+        backend.draw(cells.iter().enumerate().map(|(x, cell)| (x as u16, 0, cell))).unwrap();
+
+        for (x, cell) in cells.iter_mut().enumerate() {
+            cell.reset();
+            cell.set_skip((4..6).contains(&x));
+        }
+        backend.draw(cells.iter().enumerate().map(|(x, cell)| (x as u16, 0, cell))).unwrap();
+        assert_eq!(host.text(), "    XX");
+    }
+
+    #[cfg(feature = "terminfo")]
+    #[test]
+    fn clear_without_clear_capability() {
+        use crate::terminfo::Terminfo;
+
+        let xterm = Terminfo::builtin("xterm").unwrap();
+        for terminfo in [xterm.clone(), xterm.without(StringCap::ClearScreen)] {
+            let mut host = TestHost::new(Size::new(5, 2));
+            let mut backend = host.backend();
+            backend.set_terminfo(Some(terminfo));
+            let cells = [Cell::new("a"), Cell::new("b")];
+            backend.draw(cells.iter().enumerate().map(|(x, cell)| (x as u16, 1, cell))).unwrap();
+            backend.clear().unwrap();
+            backend.draw([(0, 0, &Cell::new("x"))].into_iter()).unwrap();
+            backend.flush().unwrap();
+            assert_eq!(host.text(), "x\n");
+        }
+    }
+
+    #[test]
+    fn suspend_draw_resume() {
+        let mut host = TestHost::new(Size::new(8, 2));
+        let mut terminal = host.terminal().unwrap();
+        terminal.backend_mut().exclusive().unwrap();
+        terminal.backend_mut().set_mouse_capture(true).unwrap();
+        terminal.draw(|frame| frame.render_widget("hello", frame.area())).unwrap();
+        assert!(host.vt().alternate_screen());
+
+        terminal.backend_mut().suspend().unwrap();
+        assert!(!host.vt().alternate_screen());
+        host.take_output();
+        terminal.draw(|frame| frame.render_widget("world", frame.area())).unwrap();
+        assert_eq!(host.take_output(), b"");
+
+        terminal.backend_mut().resume().unwrap();
+        assert!(host.vt().alternate_screen());
+        assert_eq!(host.text(), "world\n");
+        let output = String::from_utf8(host.take_output()).unwrap();
+        assert!(output.contains("\x1b[?1000h"), "{output:?}");
+    }
+
+    #[test]
+    fn normal_while_suspended() {
+        let mut host = TestHost::new(Size::new(8, 2));
+        let mut backend = host.backend();
+        backend.exclusive().unwrap();
+        backend.set_mouse_capture(true).unwrap();
+        backend.suspend().unwrap();
+        host.take_output();
+
+        backend.normal().unwrap();
+        backend.flush().unwrap();
+        assert_eq!(host.take_output(), b"");
+        backend.resume().unwrap();
+        assert!(!host.vt().alternate_screen());
+        let output = String::from_utf8(host.take_output()).unwrap();
+        assert!(!output.contains("\x1b[?1049h") && !output.contains("\x1b[?1000h"), "{output:?}");
+    }
+
+    #[test]
+    fn exclusive_while_suspended() {
+        let mut host = TestHost::new(Size::new(8, 2));
+        let mut backend = host.backend();
+        backend.suspend().unwrap();
+        host.take_output();
+
+        backend.exclusive().unwrap();
+        backend.flush().unwrap();
+        assert_eq!(host.take_output(), b"");
+        assert!(!host.vt().alternate_screen());
+        backend.resume().unwrap();
+        assert!(host.vt().alternate_screen());
+    }
+
+    #[test]
+    fn redraw_after_junk() {
+        for invalidate in [false, true] {
+            let mut host = TestHost::new(Size::new(12, 3));
+            let mut terminal = host.terminal().unwrap();
+            terminal
+                .draw(|frame| {
+                    let text = vec![Line::from("日本語 text").red().on_blue(), Line::from("plain")];
+                    frame.render_widget(Paragraph::new(text), frame.area());
+                    frame.set_cursor_position((3, 1));
+                })
+                .unwrap();
+            let drawn = host.screen().clone();
+
+            // Something else writes to the terminal, and moves the cursor:
+            host.vt().write(b"\x1b[1;2H\x1b[44mjunk\x1b[0m\x1b[2;1Hmore junk\x1b[3;1H");
+            assert_ne!(host.screen(), &drawn);
+            let backend = terminal.backend_mut();
+            if invalidate {
+                backend.invalidate();
+                backend.flush().unwrap();
+            } else {
+                backend.redraw_all().unwrap();
+            }
+            assert_eq!(host.screen(), &drawn, "invalidate: {invalidate}");
+            assert_eq!(host.screen(), &terminal.backend().snapshot(), "invalidate: {invalidate}");
+            assert_eq!(host.vt().cursor(), Position::new(3, 1), "invalidate: {invalidate}");
+            assert!(host.vt().cursor_visible());
+        }
+    }
+
+    #[test]
+    fn rewrite_palette() {
+        let mut host = TestHost::new(Size::new(4, 1));
+        let mut terminal = host.terminal().unwrap();
+        let mut options = PaletteOptions::new(Palette::default(), PaletteMode::Rewrite);
+        options.indexed.push((200, Rgb(1, 2, 3)));
+        terminal.backend_mut().set_palette(Some(options)).unwrap();
+        terminal
+            .draw(|frame| {
+                let line = Line::from(vec!["a".red(), "b".fg(Color::Indexed(200)).on_green(), "c".into()]);
+                frame.render_widget(line, frame.area());
+            })
+            .unwrap();
+
+        let colors: Vec<_> = host.screen().content[..3].iter().map(|it| (it.fg, it.bg)).collect();
+        assert_eq!(
+            colors,
+            [
+                (Color::Rgb(0xcd, 0, 0), Color::Reset),
+                (Color::Rgb(1, 2, 3), Color::Rgb(0, 0xcd, 0)),
+                (Color::Reset, Color::Reset),
+            ]
+        );
+        assert!(!String::from_utf8(host.output()).unwrap().contains("\x1b]4"));
+    }
+
+    #[test]
+    fn program_palette() {
+        let program = || PaletteOptions::new(Palette::default(), PaletteMode::Program);
+        let mut host = TestHost::new(Size::new(4, 1));
+        let mut backend = host.backend();
+        backend.exclusive().unwrap();
+        backend.set_palette(Some(program())).unwrap();
+        let mut cell = Cell::new("a");
+        cell.set_fg(Color::Red);
+        backend.draw([(0, 0, &cell)].into_iter()).unwrap();
+        backend.flush().unwrap();
+        let output = String::from_utf8(host.take_output()).unwrap();
+        assert!(output.contains("\x1b]4;0;rgb:00/00/00;1;rgb:cd/00/00;"), "{output:?}");
+        assert!(output.contains("\x1b]10;rgb:e5/e5/e5\x1b\\\x1b]11;rgb:00/00/00\x1b\\"), "{output:?}");
+        // The terminal does the rewriting:
+        assert_eq!(host.screen()[(0, 0)].fg, Color::Red);
+
+        backend.set_palette(None).unwrap();
+        backend.flush().unwrap();
+        assert_eq!(host.take_output(), RESTORE_PALETTE.as_bytes());
+
+        backend.set_palette(Some(program())).unwrap();
+        backend.normal().unwrap();
+        backend.flush().unwrap();
+        let output = String::from_utf8(host.take_output()).unwrap();
+        assert!(output.contains("\x1b]4;0;") && output.ends_with(RESTORE_PALETTE), "{output:?}");
+    }
+
+    #[test]
+    fn resume_programs_the_palette_only_if_exclusive() {
+        for exclusive in [false, true] {
+            let mut host = TestHost::new(Size::new(4, 1));
+            let mut backend = host.backend();
+            if exclusive {
+                backend.exclusive().unwrap();
+            }
+            backend.set_palette(Some(PaletteOptions::new(Palette::default(), PaletteMode::Program))).unwrap();
+            backend.suspend().unwrap();
+            host.take_output();
+
+            backend.resume().unwrap();
+            backend.flush().unwrap();
+            let output = String::from_utf8(host.take_output()).unwrap();
+            assert_eq!(output.contains("\x1b]4;0;"), exclusive, "exclusive: {exclusive}, {output:?}");
+        }
+    }
+
+    /// A backend with image 1 (one red pixel) added.
+    fn with_image(host: &TestHost, protocol: GraphicsProtocol) -> AnsiBackend {
+        let mut backend = host.backend();
+        backend.set_graphics(Some(GraphicsOptions::new(protocol))).unwrap();
+        let pixels = vec![255, 0, 0, 255];
+        backend.add_image(1, ImageData::Rgba { width: 1, height: 1, pixels }).unwrap();
+        backend
+    }
+
+    #[test]
+    fn kitty_images() {
+        let mut host = TestHost::new(Size::new(6, 3));
+        let mut backend = with_image(&host, GraphicsProtocol::Kitty);
+        host.take_output();
+
+        backend.place_image(1, Rect::new(1, 0, 2, 2)).unwrap();
+        let output = String::from_utf8(host.take_output()).unwrap();
+        assert_eq!(output.matches("a=t,").count(), 1, "{output:?}");
+        assert!(output.contains("\x1b_Ga=p,i=1,p=1,c=2,r=2,C=1,q=2\x1b\\"), "{output:?}");
+
+        // Already there:
+        backend.place_image(1, Rect::new(1, 0, 2, 2)).unwrap();
+        assert_eq!(host.take_output(), b"");
+
+        // Moving it deletes the old placement, but doesn't upload the data again:
+        backend.place_image(1, Rect::new(3, 1, 2, 2)).unwrap();
+        let output = String::from_utf8(host.take_output()).unwrap();
+        assert!(!output.contains("a=t,"), "{output:?}");
+        assert!(output.contains("\x1b_Ga=d,d=i,i=1,p=1,q=2\x1b\\"), "{output:?}");
+        assert!(output.contains("\x1b_Ga=p,i=1,p=1,c=2,r=2,C=1,q=2\x1b\\"), "{output:?}");
+
+        // Text over any part of it takes it down:
+        backend.draw([(4, 2, &Cell::new("x"))].into_iter()).unwrap();
+        backend.flush().unwrap();
+        let output = String::from_utf8(host.take_output()).unwrap();
+        assert!(output.contains("\x1b_Ga=d,d=i,i=1,p=1,q=2\x1b\\"), "{output:?}");
+        backend.draw([(0, 0, &Cell::new("y"))].into_iter()).unwrap();
+        backend.flush().unwrap();
+        assert!(!String::from_utf8(host.take_output()).unwrap().contains("\x1b_G"));
+
+        // And placing it again still doesn't upload it:
+        backend.place_image(1, Rect::new(3, 1, 2, 2)).unwrap();
+        let output = String::from_utf8(host.take_output()).unwrap();
+        assert!(output.contains("a=p,") && !output.contains("a=t,"), "{output:?}");
+    }
+
+    #[test]
+    fn sixel_images() {
+        let mut host = TestHost::new(Size::new(6, 3));
+        let mut backend = with_image(&host, GraphicsProtocol::Sixel);
+        let cells = [Cell::new("a"), Cell::new("b"), Cell::new("c")];
+        backend.draw(cells.iter().enumerate().map(|(x, cell)| (x as u16, 1, cell))).unwrap();
+        backend.flush().unwrap();
+        host.take_output();
+
+        backend.place_image(1, Rect::new(0, 1, 2, 1)).unwrap();
+        let output = String::from_utf8(host.take_output()).unwrap();
+        assert!(output.contains("\x1bP0;1;0q"), "{output:?}");
+        backend.place_image(1, Rect::new(0, 1, 2, 1)).unwrap();
+        assert_eq!(host.take_output(), b"");
+
+        // Sixel images can only be covered up, so the rest of the image's cells are drawn again:
+        backend.draw([(0, 1, &Cell::new("x"))].into_iter()).unwrap();
+        backend.flush().unwrap();
+        let output = String::from_utf8(host.take_output()).unwrap();
+        assert!(output.contains('x') && output.contains('b') && !output.contains('c'), "{output:?}");
+        assert_eq!(host.text(), "\nxbc\n");
+    }
+
+    #[test]
+    fn dec_line_drawing() {
+        let mut host = TestHost::new(Size::new(6, 3));
+        let mut terminal = host.terminal().unwrap();
+        terminal.backend_mut().set_glyph_fallback(GlyphFallback::DecLineDrawing);
+        terminal
+            .draw(|frame| {
+                let block = Block::bordered().border_type(BorderType::Rounded).title("aB");
+                frame.render_widget(block, frame.area());
+            })
+            .unwrap();
+
+        assert_eq!(host.text(), "┌aB──┐\n│    │\n└────┘");
+        // Switched to ASCII for `a`, which means something else in the line-drawing set, but not for `B` or the
+        // blanks. Then back to ASCII at the end.
+        let output = String::from_utf8(host.output()).unwrap();
+        assert_eq!(output.matches("\x1b(0").count(), 2, "{output:?}");
+        assert_eq!(output.matches("\x1b(B").count(), 2, "{output:?}");
+        assert!(output.contains("\x1b(0l\x1b(BaB\x1b(0q"), "{output:?}");
+        assert!(output.contains("\x1b[3;1Hmqqqqj\x1b(B"), "{output:?}");
+    }
+}
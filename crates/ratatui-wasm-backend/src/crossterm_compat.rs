@@ -0,0 +1,602 @@
+//! Types and commands shaped like [crossterm]'s, so that porting a crossterm app is mostly a change of imports.
+//!
+//! ```ignore
+//! // use crossterm::{event::{Event, KeyCode}, execute, terminal::EnterAlternateScreen};
+//! use ratatui_wasm_backend::{
+//!     crossterm_compat::{event::{EnableMouseCapture, Event, EventReader, KeyCode}, terminal::EnterAlternateScreen},
+//!     execute,
+//! };
+//!
+//! execute!(terminal.backend_mut(), EnterAlternateScreen, EnableMouseCapture)?;
+//!
+//! // WASM can't block waiting for input, so instead of event::read(), push stdin bytes as they arrive:
+//! events.advance(bytes);
+//! for event in events.by_ref() {
+//!     if let Event::Key(key) = event && key.code == KeyCode::Char('q') {
+//!         quit();
+//!     }
+//! }
+//! ```
+//!
+//! Commands apply to an [AnsiBackend], instead of writing to stdout. Raw mode is up to the host. (ex: Deno's
+//! `stdin.setRaw()`)
+//!
+//! [crossterm]: https://docs.rs/crossterm
+
+use std::io::Result as IOResult;
+
+use crate::backend::AnsiBackend;
+
+/// Something you can do to the terminal. See [execute!](crate::execute).
+pub trait Command {
+    fn apply(&self, backend: &mut AnsiBackend) -> IOResult<()>;
+}
+
+/// Apply [Command]s to an [AnsiBackend], then flush it.
+///
+/// ```ignore
+/// execute!(terminal.backend_mut(), LeaveAlternateScreen, DisableMouseCapture)?;
+/// ```
+#[macro_export]
+macro_rules! execute {
+    ($backend:expr $(, $command:expr)* $(,)?) => {
+        (|backend: &mut $crate::backend::AnsiBackend| -> ::std::io::Result<()> {
+            $( $crate::crossterm_compat::Command::apply(&$command, backend)?; )*
+            $crate::ratatui::backend::Backend::flush(backend)
+        })($backend)
+    };
+}
+
+/// Like [execute!](crate::execute), but doesn't flush.
+#[macro_export]
+macro_rules! queue {
+    ($backend:expr $(, $command:expr)* $(,)?) => {
+        (|backend: &mut $crate::backend::AnsiBackend| -> ::std::io::Result<()> {
+            $( $crate::crossterm_compat::Command::apply(&$command, backend)?; )*
+            Ok(())
+        })($backend)
+    };
+}
+
+pub mod terminal {
+    use std::{fmt::Display, io::Result as IOResult};
+
+    use ratatui::backend::Backend as _;
+
+    use super::Command;
+    use crate::backend::AnsiBackend;
+
+    pub struct EnterAlternateScreen;
+
+    impl Command for EnterAlternateScreen {
+        fn apply(&self, backend: &mut AnsiBackend) -> IOResult<()> {
+            backend.exclusive()
+        }
+    }
+
+    pub struct LeaveAlternateScreen;
+
+    impl Command for LeaveAlternateScreen {
+        fn apply(&self, backend: &mut AnsiBackend) -> IOResult<()> {
+            backend.normal()
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ClearType {
+        All,
+        /// Same as [ClearType::All]. (We don't clear the scrollback.)
+        Purge,
+        FromCursorDown,
+        FromCursorUp,
+        CurrentLine,
+        UntilNewLine,
+    }
+
+    pub struct Clear(pub ClearType);
+
+    impl Command for Clear {
+        fn apply(&self, backend: &mut AnsiBackend) -> IOResult<()> {
+            use ratatui::backend::ClearType as Region;
+            let region = match self.0 {
+                ClearType::All | ClearType::Purge => Region::All,
+                ClearType::FromCursorDown => Region::AfterCursor,
+                ClearType::FromCursorUp => Region::BeforeCursor,
+                ClearType::CurrentLine => Region::CurrentLine,
+                ClearType::UntilNewLine => Region::UntilNewLine,
+            };
+            backend.clear_region(region)
+        }
+    }
+
+    pub struct SetTitle<T>(pub T);
+
+    impl<T: Display> Command for SetTitle<T> {
+        fn apply(&self, backend: &mut AnsiBackend) -> IOResult<()> {
+            backend.set_title(&self.0.to_string())
+        }
+    }
+}
+
+pub mod cursor {
+    use std::io::Result as IOResult;
+
+    use ratatui::{backend::Backend as _, layout::Position};
+
+    use super::Command;
+    use crate::backend::AnsiBackend;
+
+    pub struct Hide;
+
+    impl Command for Hide {
+        fn apply(&self, backend: &mut AnsiBackend) -> IOResult<()> {
+            backend.hide_cursor()
+        }
+    }
+
+    pub struct Show;
+
+    impl Command for Show {
+        fn apply(&self, backend: &mut AnsiBackend) -> IOResult<()> {
+            backend.show_cursor()
+        }
+    }
+
+    /// Move the cursor to (column, row), 0-based.
+    pub struct MoveTo(pub u16, pub u16);
+
+    impl Command for MoveTo {
+        fn apply(&self, backend: &mut AnsiBackend) -> IOResult<()> {
+            backend.set_cursor_position(Position::new(self.0, self.1))
+        }
+    }
+}
+
+pub mod event {
+    use std::{collections::VecDeque, io::Result as IOResult};
+
+    use bitflags::bitflags;
+
+    use super::Command;
+    use crate::{backend::AnsiBackend, ctrl::Ctrl, input};
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub enum Event {
+        FocusGained,
+        FocusLost,
+        Key(KeyEvent),
+        Mouse(MouseEvent),
+        Paste(String),
+
+        /// The new size: (columns, rows).
+        Resize(u16, u16),
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct KeyEvent {
+        pub code: KeyCode,
+        pub modifiers: KeyModifiers,
+        pub kind: KeyEventKind,
+        pub state: KeyEventState,
+    }
+
+    impl KeyEvent {
+        pub const fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+            Self {
+                code,
+                modifiers,
+                kind: KeyEventKind::Press,
+                state: KeyEventState::empty(),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum KeyCode {
+        Backspace,
+        Enter,
+        Left,
+        Right,
+        Up,
+        Down,
+        Home,
+        End,
+        PageUp,
+        PageDown,
+        Tab,
+        BackTab,
+        Delete,
+        Insert,
+        F(u8),
+        Char(char),
+        Null,
+        Esc,
+        CapsLock,
+        ScrollLock,
+        NumLock,
+        PrintScreen,
+        Pause,
+        Menu,
+        KeypadBegin,
+    }
+
+    bitflags! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct KeyModifiers: u8 {
+            const SHIFT = 0b0000_0001;
+            const CONTROL = 0b0000_0010;
+            const ALT = 0b0000_0100;
+            const SUPER = 0b0000_1000;
+            const HYPER = 0b0001_0000;
+            const META = 0b0010_0000;
+            const NONE = 0b0000_0000;
+        }
+    }
+
+    /// Terminals only report presses, unless they support the kitty keyboard protocol.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum KeyEventKind {
+        Press,
+        Repeat,
+        Release,
+    }
+
+    bitflags! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct KeyEventState: u8 {
+            const KEYPAD = 0b0000_0001;
+            const CAPS_LOCK = 0b0000_0010;
+            const NUM_LOCK = 0b0000_0100;
+            const NONE = 0b0000_0000;
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct MouseEvent {
+        pub kind: MouseEventKind,
+
+        /// 0-based.
+        pub column: u16,
+
+        /// 0-based.
+        pub row: u16,
+        pub modifiers: KeyModifiers,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum MouseEventKind {
+        Down(MouseButton),
+        Up(MouseButton),
+        Drag(MouseButton),
+        Moved,
+        ScrollDown,
+        ScrollUp,
+        ScrollLeft,
+        ScrollRight,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum MouseButton {
+        Left,
+        Right,
+        Middle,
+    }
+
+    /// Convert one input sequence, the same way crossterm would.
+    ///
+    /// Returns `None` for sequences crossterm doesn't have events for. (ex: cursor position reports)
+    ///
+    /// Ctrl-$key combos are `Char` keys with [KeyModifiers::CONTROL]. (ex: Ctrl-C is `Char('c')`) Like crossterm,
+    /// 0x1C to 0x1F (Ctrl-\ ] ^ _) are `Char('4')` to `Char('7')`. Uppercase letters have [KeyModifiers::SHIFT].
+    pub fn event(seq: &input::Sequence) -> Option<Event> {
+        match seq {
+            input::Sequence::Key(code, modifiers) => Some(Event::Key(key_event(*code, *modifiers))),
+            input::Sequence::Mouse(mouse, modifiers) => Some(Event::Mouse(mouse_event(*mouse, *modifiers))),
+            input::Sequence::CursorPosition(_, _) => None,
+            input::Sequence::Raw(bytes) => match bytes.as_slice() {
+                b"\x1b[I" => Some(Event::FocusGained),
+                b"\x1b[O" => Some(Event::FocusLost),
+                _ => None,
+            },
+        }
+    }
+
+    fn key_modifiers(modifiers: input::KeyModifiers) -> KeyModifiers {
+        let mut mods = KeyModifiers::empty();
+        for (from, to) in [
+            (input::KeyModifiers::SHIFT, KeyModifiers::SHIFT),
+            (input::KeyModifiers::CONTROL, KeyModifiers::CONTROL),
+            (input::KeyModifiers::ALT, KeyModifiers::ALT),
+            (input::KeyModifiers::META, KeyModifiers::META),
+        ] {
+            if modifiers.contains(from) {
+                mods |= to;
+            }
+        }
+        mods
+    }
+
+    fn key_event(code: input::KeyCode, modifiers: input::KeyModifiers) -> KeyEvent {
+        let mut mods = key_modifiers(modifiers);
+
+        use input::KeyCode as In;
+        let code = match code {
+            In::Char(ch @ '\x1c'..='\x1f') => {
+                mods |= KeyModifiers::CONTROL;
+                KeyCode::Char((b'4' + (ch as u8 - 0x1c)) as char)
+            }
+            In::Char(ch) if (ch as u32) < 0x20 => {
+                mods |= KeyModifiers::CONTROL;
+                let key = Ctrl::from_byte(ch as u8).map(|it| it.key()).unwrap_or(ch);
+                KeyCode::Char(key.to_ascii_lowercase())
+            }
+            In::Char(ch) => {
+                if ch.is_uppercase() {
+                    mods |= KeyModifiers::SHIFT;
+                }
+                KeyCode::Char(ch)
+            }
+            In::Null => {
+                mods |= KeyModifiers::CONTROL;
+                KeyCode::Char(' ')
+            }
+            In::BackTab => {
+                mods |= KeyModifiers::SHIFT;
+                KeyCode::BackTab
+            }
+            In::Backspace => KeyCode::Backspace,
+            In::Enter => KeyCode::Enter,
+            In::Left => KeyCode::Left,
+            In::Right => KeyCode::Right,
+            In::Up => KeyCode::Up,
+            In::Down => KeyCode::Down,
+            In::Home => KeyCode::Home,
+            In::End => KeyCode::End,
+            In::PageUp => KeyCode::PageUp,
+            In::PageDown => KeyCode::PageDown,
+            In::Tab => KeyCode::Tab,
+            In::Delete => KeyCode::Delete,
+            In::Insert => KeyCode::Insert,
+            In::F(n) => KeyCode::F(n),
+            In::Esc => KeyCode::Esc,
+        };
+        KeyEvent::new(code, mods)
+    }
+
+    fn mouse_event(mouse: input::Mouse, modifiers: input::KeyModifiers) -> MouseEvent {
+        use input::{Mouse, MouseButton as In};
+        let button = |button: In| match button {
+            In::Left | In::Any => MouseButton::Left,
+            In::Right => MouseButton::Right,
+            In::Middle => MouseButton::Middle,
+        };
+        let (kind, x, y) = match mouse {
+            Mouse::Down(which, x, y) => (MouseEventKind::Down(button(which)), x, y),
+            Mouse::Up(which, x, y) => (MouseEventKind::Up(button(which)), x, y),
+            Mouse::Drag(In::Any, x, y) => (MouseEventKind::Moved, x, y),
+            Mouse::Drag(which, x, y) => (MouseEventKind::Drag(button(which)), x, y),
+            Mouse::ScrollUp(x, y) => (MouseEventKind::ScrollUp, x, y),
+            Mouse::ScrollDown(x, y) => (MouseEventKind::ScrollDown, x, y),
+        };
+        MouseEvent {
+            kind,
+            column: x.saturating_sub(1),
+            row: y.saturating_sub(1),
+            modifiers: key_modifiers(modifiers),
+        }
+    }
+
+    /// Bracketed paste: the terminal sends these around pasted text.
+    const PASTE_START: &[u8] = b"\x1b[200~";
+    const PASTE_END: &[u8] = b"\x1b[201~";
+
+    /// Turns terminal input into [Event]s. Use it in place of crossterm's `event::read()`.
+    #[derive(Debug, Default)]
+    pub struct EventReader {
+        parser: input::Parser,
+        events: VecDeque<Event>,
+
+        /// Input we haven't handled yet: the start of a paste marker, or a paste waiting for its end.
+        pending: Vec<u8>,
+
+        /// Whether we're in a bracketed paste.
+        pasting: bool,
+    }
+
+    impl EventReader {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Handle input from the terminal.
+        ///
+        /// Pasted text is passed along as it was received, without being parsed as keys.
+        pub fn advance(&mut self, bytes: &[u8]) {
+            self.pending.extend_from_slice(bytes);
+            loop {
+                if self.pasting {
+                    let Some(end) = find(&self.pending, PASTE_END) else { return };
+                    let text = String::from_utf8_lossy(&self.pending[..end]).into_owned();
+                    self.events.push_back(Event::Paste(text));
+                    self.pending.drain(..end + PASTE_END.len());
+                    self.pasting = false;
+                    continue;
+                }
+
+                let (input, rest) = match find(&self.pending, PASTE_START) {
+                    Some(start) => (start, start + PASTE_START.len()),
+                    None => {
+                        // Hold onto what may be the start of a paste marker. But not a lone ESC (or Alt-[), which
+                        // is probably a keypress.
+                        let partial = (3..PASTE_START.len())
+                            .rev()
+                            .find(|len| self.pending.ends_with(&PASTE_START[..*len]))
+                            .unwrap_or(0);
+                        let end = self.pending.len() - partial;
+                        (end, end)
+                    }
+                };
+                self.parser.advance(&self.pending[..input], false);
+                for seq in self.parser.by_ref() {
+                    self.events.extend(event(&seq));
+                }
+                let found = rest > input;
+                self.pending.drain(..rest);
+                if !found {
+                    return;
+                }
+                self.pasting = true;
+            }
+        }
+
+        /// Let the reader know that the terminal has been resized. (The host has to tell you about that.)
+        pub fn resize(&mut self, columns: u16, rows: u16) {
+            self.events.push_back(Event::Resize(columns, rows));
+        }
+    }
+
+    impl Iterator for EventReader {
+        type Item = Event;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.events.pop_front()
+        }
+    }
+
+    fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack.windows(needle.len()).position(|it| it == needle)
+    }
+
+    pub struct EnableMouseCapture;
+
+    impl Command for EnableMouseCapture {
+        fn apply(&self, backend: &mut AnsiBackend) -> IOResult<()> {
+            backend.set_mouse_capture(true)
+        }
+    }
+
+    pub struct DisableMouseCapture;
+
+    impl Command for DisableMouseCapture {
+        fn apply(&self, backend: &mut AnsiBackend) -> IOResult<()> {
+            backend.set_mouse_capture(false)
+        }
+    }
+
+    pub struct EnableBracketedPaste;
+
+    impl Command for EnableBracketedPaste {
+        fn apply(&self, backend: &mut AnsiBackend) -> IOResult<()> {
+            backend.set_bracketed_paste(true)
+        }
+    }
+
+    pub struct DisableBracketedPaste;
+
+    impl Command for DisableBracketedPaste {
+        fn apply(&self, backend: &mut AnsiBackend) -> IOResult<()> {
+            backend.set_bracketed_paste(false)
+        }
+    }
+
+    pub struct EnableFocusChange;
+
+    impl Command for EnableFocusChange {
+        fn apply(&self, backend: &mut AnsiBackend) -> IOResult<()> {
+            backend.set_focus_reporting(true)
+        }
+    }
+
+    pub struct DisableFocusChange;
+
+    impl Command for DisableFocusChange {
+        fn apply(&self, backend: &mut AnsiBackend) -> IOResult<()> {
+            backend.set_focus_reporting(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::event::*;
+
+    fn events(chunks: &[&[u8]]) -> Vec<Event> {
+        let mut reader = EventReader::new();
+        let mut events = vec![];
+        for chunk in chunks {
+            reader.advance(chunk);
+            events.extend(reader.by_ref());
+        }
+        events
+    }
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Event {
+        Event::Key(KeyEvent::new(code, modifiers))
+    }
+
+    #[test]
+    fn paste() {
+        let input = "a\x1b[200~x\x1b[Ay\r\n\x1b\tz é\x1b[201~b".as_bytes();
+        let expected = [
+            key(KeyCode::Char('a'), KeyModifiers::NONE),
+            Event::Paste("x\x1b[Ay\r\n\x1b\tz é".into()),
+            key(KeyCode::Char('b'), KeyModifiers::NONE),
+        ];
+        assert_eq!(events(&[input]), expected);
+
+        // Either marker may be split across reads. Except right after the start's ESC or `ESC [`, which look like
+        // Esc or Alt-[:
+        for split in (0..=input.len()).filter(|it| ![2, 3].contains(it)) {
+            let (head, tail) = input.split_at(split);
+            assert_eq!(events(&[head, tail]), expected, "split at {split}");
+        }
+
+        // An unfinished paste waits for the rest:
+        assert_eq!(events(&[b"\x1b[200~abc"]), []);
+    }
+
+    #[test]
+    fn lone_esc() {
+        assert_eq!(events(&[b"\x1b"]), [key(KeyCode::Esc, KeyModifiers::NONE)]);
+        assert_eq!(events(&[b"\x1b[2", b"00"]), []);
+        assert_eq!(events(&[b"\x1b[2", b"A"]), [key(KeyCode::Up, KeyModifiers::NONE)]);
+    }
+
+    #[test]
+    fn ctrl_keys() {
+        let events = events(&[b"\x03\x1c\x1d\x1e\x1f\x00A"]);
+        assert_eq!(
+            events,
+            [
+                key(KeyCode::Char('c'), KeyModifiers::CONTROL),
+                key(KeyCode::Char('4'), KeyModifiers::CONTROL),
+                key(KeyCode::Char('5'), KeyModifiers::CONTROL),
+                key(KeyCode::Char('6'), KeyModifiers::CONTROL),
+                key(KeyCode::Char('7'), KeyModifiers::CONTROL),
+                key(KeyCode::Char(' '), KeyModifiers::CONTROL),
+                key(KeyCode::Char('A'), KeyModifiers::SHIFT),
+            ]
+        );
+    }
+
+    #[test]
+    fn mouse_focus_and_resize() {
+        let mut reader = EventReader::new();
+        reader.advance(b"\x1b[<0;10;20M\x1b[<0;10;20m\x1b[<35;1;2M\x1b[<34;3;4M\x1b[<81;5;6M\x1b[I\x1b[O\x1b[1;1R");
+        reader.resize(100, 30);
+        let mouse = |kind, column, row, modifiers| Event::Mouse(MouseEvent { kind, column, row, modifiers });
+        assert_eq!(
+            reader.collect::<Vec<_>>(),
+            [
+                mouse(MouseEventKind::Down(MouseButton::Left), 9, 19, KeyModifiers::NONE),
+                mouse(MouseEventKind::Up(MouseButton::Left), 9, 19, KeyModifiers::NONE),
+                mouse(MouseEventKind::Moved, 0, 1, KeyModifiers::NONE),
+                mouse(MouseEventKind::Drag(MouseButton::Right), 2, 3, KeyModifiers::NONE),
+                mouse(MouseEventKind::ScrollDown, 4, 5, KeyModifiers::CONTROL),
+                Event::FocusGained,
+                Event::FocusLost,
+                Event::Resize(100, 30),
+            ]
+        );
+    }
+}
@@ -1,41 +1,52 @@
 //! Utilities for handling control characters
 //! 
-//! See: <https://github.com/qwandor/anes-rs/issues/41>
-//! 
 //! In the terminal, Ctrl-$key is not transmitted as a separate modifier and keycode,
 //! they are transmitted as ASCII control characters, which may have meaning. (ex: Ctrl-m is carriage-return/enter).
 //! 
-//! Anes just returns these as KeyCodes, but you may need need to detect them and handle them separately.
+//! The [Parser](crate::input::Parser) just returns these as KeyCodes, but you may need need to detect them and
+//! handle them separately.
 //! 
 
-use anes::parser::{KeyCode, Sequence};
+use crate::input::{KeyCode, Sequence};
 
 pub trait GetCtrl {
-    fn ctrl(&self) -> Option<Ctrl>;
+    /// The Ctrl-$key combo that was pressed, if any, assuming the terminal's erase character is DEL.
+    fn ctrl(&self) -> Option<Ctrl> {
+        self.ctrl_with(Erase::default())
+    }
+
+    /// Like [GetCtrl::ctrl], but for a terminal with the given erase character.
+    fn ctrl_with(&self, erase: Erase) -> Option<Ctrl>;
 }
 
 impl GetCtrl for Sequence {
-    fn ctrl(&self) -> Option<Ctrl> {
+    fn ctrl_with(&self, erase: Erase) -> Option<Ctrl> {
         match self {
-            Sequence::Key(key_code, _) => key_code.ctrl(),
+            Sequence::Key(key_code, _) => key_code.ctrl_with(erase),
             Sequence::Mouse(_, _) => None,
             Sequence::CursorPosition(_, _) => None,
+            Sequence::Raw(_) => None,
         }
     }
 }
 
 impl GetCtrl for KeyCode {
-    fn ctrl(&self) -> Option<Ctrl> {
+    fn ctrl_with(&self, erase: Erase) -> Option<Ctrl> {
         let ch =match self {
             KeyCode::Enter => {
-                // Anes maps both Ctrl-J and Ctrl-M to Enter.
+                // The parser maps both Ctrl-J and Ctrl-M to Enter.
                 // So I'd rather return None here than choose one:
                 return None;
             },
             KeyCode::Tab => return Some(Ctrl::I),
 
-            // May technically be control codes. TODO: check?
+            // Ctrl-[ sends the same byte, but this is almost always the Esc key. See [Ctrl::LeftBracket].
             KeyCode::Esc => return None,
+
+            // NUL is only ever sent for Ctrl-@ (or Ctrl-Space).
+            KeyCode::Null => return Some(Ctrl::At),
+
+            // DEL. Either the Backspace key, or (if that sends Ctrl-H) Ctrl-?, which isn't a Ctrl-$key combo.
             KeyCode::Backspace
             | KeyCode::Left
             | KeyCode::Right
@@ -47,46 +58,50 @@ impl GetCtrl for KeyCode {
             | KeyCode::PageDown
             | KeyCode::BackTab
             | KeyCode::Delete
-            | KeyCode::Insert 
-            | KeyCode::Null
+            | KeyCode::Insert
             | KeyCode::F(_)
             => {
                 return None
             },
-            KeyCode::Char(ch) => ch
+            KeyCode::Char(ch) => *ch
         };
 
-        let ctrl = match ch {
-            '\u{1}' => Ctrl::A,
-            '\u{2}' => Ctrl::B,
-            '\u{3}' => Ctrl::C,
-            '\u{4}' => Ctrl::D,
-            '\u{5}' => Ctrl::E,
-            '\u{6}' => Ctrl::F,
-            '\u{7}' => Ctrl::G,
-            '\u{8}' => Ctrl::H,
-            '\u{9}' => Ctrl::I,
-            '\u{a}' => Ctrl::J,
-            '\u{b}' => Ctrl::K,
-            '\u{c}' => Ctrl::L,
-            '\u{d}' => Ctrl::M,
-            '\u{e}' => Ctrl::N,
-            '\u{f}' => Ctrl::O,
-            '\u{10}' => Ctrl::P,
-            '\u{11}' => Ctrl::Q,
-            '\u{12}' => Ctrl::R,
-            '\u{13}' => Ctrl::S,
-            '\u{14}' => Ctrl::T,
-            '\u{15}' => Ctrl::U,
-            '\u{16}' => Ctrl::V,
-            '\u{17}' => Ctrl::W,
-            '\u{18}' => Ctrl::X,
-            '\u{19}' => Ctrl::Y,
-            '\u{1a}' => Ctrl::Z,
-            _ => return None,
-        };
+        if erase == Erase::CtrlH && ch == '\u{8}' {
+            // That's the Backspace key.
+            return None;
+        }
+        u8::try_from(ch).ok().and_then(Ctrl::from_byte)
+    }
+}
 
-        Some(ctrl)
+/// The character that the terminal sends when you press Backspace. (See `stty erase`)
+///
+/// Most terminals send DEL. If yours sends Ctrl-H instead, you can't tell Ctrl-H and Backspace apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Erase {
+    /// Backspace sends DEL (0x7F), so 0x08 is Ctrl-H.
+    #[default]
+    Del,
+
+    /// Backspace sends Ctrl-H (0x08).
+    CtrlH,
+}
+
+impl Erase {
+    /// Whether `key` is the Backspace key.
+    pub fn is_backspace(&self, key: &KeyCode) -> bool {
+        match self {
+            Erase::Del => *key == KeyCode::Backspace,
+            Erase::CtrlH => *key == KeyCode::Char('\u{8}'),
+        }
+    }
+
+    /// The byte that the Backspace key sends.
+    pub fn byte(&self) -> u8 {
+        match self {
+            Erase::Del => 0x7f,
+            Erase::CtrlH => 0x08,
+        }
     }
 }
 
@@ -96,8 +111,12 @@ impl GetCtrl for KeyCode {
 /// 
 /// Note: ASCII and Unicode have many more "control codes" than what [CtrlKey] handles. We're specifically
 /// limiting its scope to ctrl-$key combos.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
 pub enum Ctrl {
+    /// AKA: NUL. Most terminals also send this for Ctrl-Space.
+    At = 0x00,
+
     A,
     B,
     C,
@@ -105,6 +124,8 @@ pub enum Ctrl {
     E,
     F,
     G,
+
+    /// May also be the Backspace key. See [Erase].
     H,
 
     /// AKA: "Tab". Some terminals (ex: Ghostty v1.1.3) may not transmit a ctrl-i keypress.
@@ -134,5 +155,139 @@ pub enum Ctrl {
     X,
     Y,
     Z,
+
+    /// Ctrl-[, AKA: "Escape". You can't tell it apart from the Esc key.
+    ///
+    /// So [GetCtrl::ctrl] treats it as the Esc key, and returns `None`. (`Ctrl::from_byte(0x1b)` still maps it.)
+    LeftBracket,
+
+    /// Ctrl-\
+    Backslash,
+
+    /// Ctrl-]
+    RightBracket,
+
+    /// Ctrl-^. On US keyboards, you may need to press Ctrl-Shift-6.
+    Caret,
+
+    /// Ctrl-_. On US keyboards, you may need to press Ctrl-Shift-- (minus).
+    Underscore,
 }
 
+impl Ctrl {
+    /// Every [Ctrl], in byte order.
+    pub const ALL: [Ctrl; 32] = [
+        Ctrl::At,
+        Ctrl::A,
+        Ctrl::B,
+        Ctrl::C,
+        Ctrl::D,
+        Ctrl::E,
+        Ctrl::F,
+        Ctrl::G,
+        Ctrl::H,
+        Ctrl::I,
+        Ctrl::J,
+        Ctrl::K,
+        Ctrl::L,
+        Ctrl::M,
+        Ctrl::N,
+        Ctrl::O,
+        Ctrl::P,
+        Ctrl::Q,
+        Ctrl::R,
+        Ctrl::S,
+        Ctrl::T,
+        Ctrl::U,
+        Ctrl::V,
+        Ctrl::W,
+        Ctrl::X,
+        Ctrl::Y,
+        Ctrl::Z,
+        Ctrl::LeftBracket,
+        Ctrl::Backslash,
+        Ctrl::RightBracket,
+        Ctrl::Caret,
+        Ctrl::Underscore,
+    ];
+
+    /// The control character (0x00-0x1F) that the terminal sends for this combo.
+    pub fn byte(&self) -> u8 {
+        *self as u8
+    }
+
+    /// The combo for a control character, if it is one.
+    pub fn from_byte(byte: u8) -> Option<Ctrl> {
+        Self::ALL.get(byte as usize).copied()
+    }
+
+    /// The key pressed along with Ctrl. (ex: 'C' for Ctrl-C, '\\' for Ctrl-\\)
+    pub fn key(&self) -> char {
+        (self.byte() ^ 0x40) as char
+    }
+
+    /// The inverse of [Ctrl::key]. Letters may be either case, and ' ' is the same as '@'.
+    pub fn from_key(key: char) -> Option<Ctrl> {
+        match key {
+            ' ' => Some(Ctrl::At),
+            '@'..='_' => Self::from_byte(key as u8 ^ 0x40),
+            'a'..='z' => Self::from_byte(key as u8 ^ 0x60),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        for (i, ctrl) in Ctrl::ALL.iter().enumerate() {
+            assert_eq!(ctrl.byte(), i as u8);
+            assert_eq!(Ctrl::from_byte(ctrl.byte()), Some(*ctrl));
+            assert_eq!(Ctrl::from_key(ctrl.key()), Some(*ctrl));
+            assert_eq!(Ctrl::from_key(ctrl.key().to_ascii_lowercase()), Some(*ctrl));
+        }
+        assert_eq!(Ctrl::from_byte(0x20), None);
+        assert_eq!(Ctrl::from_byte(0x7f), None);
+        assert_eq!(Ctrl::C.key(), 'C');
+        assert_eq!(Ctrl::Backslash.key(), '\\');
+        assert_eq!(Ctrl::from_key(' '), Some(Ctrl::At));
+        assert_eq!(Ctrl::from_key('?'), None);
+        assert_eq!(Ctrl::from_key('{'), None);
+        assert_eq!(Ctrl::from_key('é'), None);
+    }
+
+    #[test]
+    fn key_codes() {
+        assert_eq!(KeyCode::Char('\u{3}').ctrl(), Some(Ctrl::C));
+        assert_eq!(KeyCode::Char('\u{1c}').ctrl(), Some(Ctrl::Backslash));
+        assert_eq!(KeyCode::Char('\u{1f}').ctrl(), Some(Ctrl::Underscore));
+        assert_eq!(KeyCode::Char('c').ctrl(), None);
+        assert_eq!(KeyCode::Tab.ctrl(), Some(Ctrl::I));
+        assert_eq!(KeyCode::Null.ctrl(), Some(Ctrl::At));
+        assert_eq!(KeyCode::Esc.ctrl(), None);
+        assert_eq!(KeyCode::Enter.ctrl(), None);
+        assert_eq!(KeyCode::Up.ctrl(), None);
+
+        let key = |code| Sequence::Key(code, crate::input::KeyModifiers::empty());
+        assert_eq!(key(KeyCode::Char('\u{1}')).ctrl(), Some(Ctrl::A));
+        assert_eq!(Sequence::Raw(vec![1]).ctrl(), None);
+    }
+
+    #[test]
+    fn erase() {
+        let ctrl_h = KeyCode::Char('\u{8}');
+        assert_eq!(ctrl_h.ctrl_with(Erase::Del), Some(Ctrl::H));
+        assert_eq!(ctrl_h.ctrl_with(Erase::CtrlH), None);
+        assert_eq!(KeyCode::Backspace.ctrl_with(Erase::Del), None);
+        assert_eq!(KeyCode::Backspace.ctrl_with(Erase::CtrlH), None);
+
+        assert!(Erase::Del.is_backspace(&KeyCode::Backspace));
+        assert!(!Erase::Del.is_backspace(&ctrl_h));
+        assert!(Erase::CtrlH.is_backspace(&ctrl_h));
+        assert!(!Erase::CtrlH.is_backspace(&KeyCode::Backspace));
+        assert_eq!((Erase::Del.byte(), Erase::CtrlH.byte()), (0x7f, 0x08));
+    }
+}
@@ -0,0 +1,106 @@
+//! Errors from the backend.
+//!
+//! Ratatui's [Backend](ratatui::backend::Backend) trait only lets us return [std::io::Error], so a
+//! [BackendError] gets wrapped in one. You can get it back out to see what actually went wrong:
+//!
+//! ```ignore
+//! if let Err(err) = terminal.draw(render) {
+//!     match BackendError::from_io(&err) {
+//!         Some(err) if err.is_broken_pipe() => return Ok(()), // stdout was closed. Exit quietly.
+//!         Some(BackendError::WriterThrew(exception)) => log(exception.stack.as_deref().unwrap_or_default()),
+//!         _ => return Err(err),
+//!     }
+//! }
+//! ```
+
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{Error as IOError, ErrorKind},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackendError {
+    /// The stdout writer callback threw an exception.
+    WriterThrew(JsException),
+
+    /// The terminal size callback threw an exception.
+    SizeCallbackThrew(JsException),
+
+    /// The timer callback threw an exception.
+    TimerThrew(JsException),
+
+    /// The writer stopped accepting bytes before we'd written everything.
+    ShortWrite { written: usize, expected: usize },
+
+    /// Couldn't encode some output. (ex: a [Display] implementation returned an error)
+    Encoding(String),
+}
+
+/// The details of an exception thrown by JavaScript, copied out into Rust strings.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct JsException {
+    /// ex: "TypeError", or "BrokenPipe" (from Deno)
+    pub name: String,
+    pub message: String,
+    pub stack: Option<String>,
+}
+
+impl BackendError {
+    /// Get the [BackendError] out of an [std::io::Error] returned by the backend, if there is one.
+    pub fn from_io(err: &IOError) -> Option<&BackendError> {
+        err.get_ref()?.downcast_ref()
+    }
+
+    /// The exception thrown by JavaScript, if that's what caused this error.
+    pub fn exception(&self) -> Option<&JsException> {
+        match self {
+            BackendError::WriterThrew(exception) => Some(exception),
+            BackendError::SizeCallbackThrew(exception) => Some(exception),
+            BackendError::TimerThrew(exception) => Some(exception),
+            BackendError::ShortWrite { .. } => None,
+            BackendError::Encoding(_) => None,
+        }
+    }
+
+    /// True if stdout has been closed. (ex: piping output to `head`)
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, BackendError::WriterThrew(exception) if exception.name == "BrokenPipe")
+    }
+}
+
+impl Display for BackendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendError::WriterThrew(exception) => write!(f, "writing to stdout threw {exception}"),
+            BackendError::SizeCallbackThrew(exception) => {
+                write!(f, "getting the size of the terminal threw {exception}")
+            }
+            BackendError::TimerThrew(exception) => write!(f, "setting a timeout threw {exception}"),
+            BackendError::ShortWrite { written, expected } => {
+                write!(f, "only wrote {written} of {expected} bytes to stdout")
+            }
+            BackendError::Encoding(message) => write!(f, "couldn't encode output: {message}"),
+        }
+    }
+}
+
+impl Error for BackendError {}
+
+impl Display for JsException {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.name, self.message)
+    }
+}
+
+impl From<BackendError> for IOError {
+    fn from(err: BackendError) -> Self {
+        let kind = match &err {
+            err if err.is_broken_pipe() => ErrorKind::BrokenPipe,
+            BackendError::ShortWrite { .. } => ErrorKind::WriteZero,
+            BackendError::Encoding(_) => ErrorKind::InvalidData,
+            _ => ErrorKind::Other,
+        };
+        IOError::new(kind, err)
+    }
+}
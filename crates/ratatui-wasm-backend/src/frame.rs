@@ -0,0 +1,233 @@
+//! Limit how often you draw, no matter how fast events arrive.
+//!
+//! Drawing after every chunk of input is wasteful when input arrives faster than the terminal can show it.
+//! (ex: a fast typist, or a big paste) Instead, mark the UI dirty on each event, and let a [FrameScheduler]
+//! decide when to draw:
+//!
+//! ```ignore
+//! fn push_stdin_bytes(&mut self, bytes: &[u8]) -> IOResult<()> {
+//!     self.app.handle(bytes);
+//!     self.frames.mark_dirty();
+//!     self.draw_if_due()
+//! }
+//!
+//! // Called by the host when the timer is up. (ex: `(ms) => setTimeout(() => main.tick(), ms)`)
+//! fn tick(&mut self) -> IOResult<()> {
+//!     self.frames.timeout();
+//!     self.draw_if_due()
+//! }
+//!
+//! fn draw_if_due(&mut self) -> IOResult<()> {
+//!     if self.frames.poll(JsClock.now(), &self.timer)? {
+//!         self.terminal.draw(|frame| self.app.render(frame))?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! The first change after a quiet period is drawn right away. Changes that come in before the next frame is
+//! due are drawn together, once it is. The last state is always drawn: if it's too soon, the scheduler asks
+//! the [Timer] to call back when it's not.
+
+use std::{io::Result as IOResult, time::Duration};
+
+use crate::host::Timer;
+
+/// The default frame rate limit.
+pub const DEFAULT_MAX_FPS: u32 = 60;
+
+/// Decides when to draw. See the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct FrameScheduler {
+    /// The least time between frames.
+    interval: Duration,
+
+    /// Something changed since the last frame.
+    dirty: bool,
+
+    /// When we last told the caller to draw.
+    last_frame: Option<Duration>,
+
+    /// When the timeout we asked for is due, if it hasn't fired yet.
+    scheduled: Option<Duration>,
+}
+
+impl Default for FrameScheduler {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FPS)
+    }
+}
+
+impl FrameScheduler {
+    /// Draw at most `max_fps` frames per second. 0 means no limit.
+    pub fn new(max_fps: u32) -> Self {
+        Self {
+            interval: interval(max_fps),
+            dirty: false,
+            last_frame: None,
+            scheduled: None,
+        }
+    }
+
+    pub fn set_max_fps(&mut self, max_fps: u32) {
+        self.interval = interval(max_fps);
+    }
+
+    /// The least time between frames.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Note that the UI has changed, and needs to be drawn.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Let the scheduler know that the timeout it asked for is up. Then [poll](Self::poll) again.
+    pub fn timeout(&mut self) {
+        // Timers may fire a bit early, so don't trust the time. poll() will ask again if it's too soon.
+        self.scheduled = None;
+    }
+
+    /// Note that you drew the UI without asking. (ex: when the host asked you to)
+    pub fn drew(&mut self, now: Duration) {
+        self.dirty = false;
+        self.last_frame = Some(now);
+        // Keep `scheduled` until our timeout fires, even though it'll find nothing to do. Otherwise we'd ask for
+        // another, and they'd pile up.
+    }
+
+    /// Whether to draw now. `now` is from a monotonic [Clock](crate::host::Clock).
+    ///
+    /// If this returns true, the scheduler assumes you draw right away, and the UI is clean again. If the UI is
+    /// dirty, but it's too soon for another frame, it asks `timer` to call back when the next frame is due.
+    pub fn poll(&mut self, now: Duration, timer: &dyn Timer) -> IOResult<bool> {
+        if !self.dirty {
+            return Ok(false);
+        }
+
+        let due = match self.last_frame {
+            Some(last_frame) => last_frame + self.interval,
+            None => now,
+        };
+        if now >= due {
+            self.drew(now);
+            return Ok(true);
+        }
+
+        if self.scheduled.is_none() {
+            timer.set_timeout(due - now)?;
+            self.scheduled = Some(due);
+        }
+        Ok(false)
+    }
+}
+
+fn interval(max_fps: u32) -> Duration {
+    match max_fps {
+        0 => Duration::ZERO,
+        fps => Duration::from_secs(1) / fps,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::{Clock as _, FakeClock};
+
+    const MS: Duration = Duration::from_millis(1);
+
+    /// Fire the next timeout, like the host would, and poll.
+    fn fire(frames: &mut FrameScheduler, clock: &FakeClock) -> bool {
+        let due = clock.next_timeout().expect("a timeout");
+        clock.set(due);
+        assert_eq!(clock.take_due(), 1);
+        frames.timeout();
+        frames.poll(clock.now(), clock).unwrap()
+    }
+
+    #[test]
+    fn burst_draws_once() {
+        let clock = FakeClock::new();
+        let mut frames = FrameScheduler::new(10);
+        let mut drawn = 0;
+        for _ in 0..50 {
+            frames.mark_dirty();
+            if frames.poll(clock.now(), &clock).unwrap() {
+                drawn += 1;
+            }
+            clock.advance(MS);
+        }
+        // The first event, right away. The rest wait for the next frame:
+        assert_eq!(drawn, 1);
+        assert!(frames.is_dirty());
+        assert_eq!(clock.next_timeout(), Some(100 * MS));
+        assert_eq!(clock.take_due(), 0);
+
+        assert!(fire(&mut frames, &clock));
+        assert!(!frames.is_dirty());
+        assert_eq!(clock.next_timeout(), None);
+    }
+
+    #[test]
+    fn last_state_drawn_after_timeout() {
+        let clock = FakeClock::new();
+        let mut frames = FrameScheduler::new(10);
+        frames.mark_dirty();
+        assert!(frames.poll(clock.now(), &clock).unwrap());
+
+        clock.advance(30 * MS);
+        frames.mark_dirty();
+        assert!(!frames.poll(clock.now(), &clock).unwrap());
+        assert!(fire(&mut frames, &clock));
+        assert_eq!(clock.now(), 100 * MS);
+
+        // Nothing changed since, so the next timeout wouldn't draw:
+        assert!(!frames.poll(clock.now(), &clock).unwrap());
+        assert_eq!(clock.next_timeout(), None);
+    }
+
+    #[test]
+    fn timeouts_dont_pile_up() {
+        let clock = FakeClock::new();
+        let mut frames = FrameScheduler::new(10);
+        frames.mark_dirty();
+        assert!(frames.poll(clock.now(), &clock).unwrap());
+        frames.mark_dirty();
+        assert!(!frames.poll(clock.now(), &clock).unwrap());
+
+        // The host draws without asking, before our timeout fires:
+        clock.advance(50 * MS);
+        frames.drew(clock.now());
+        for _ in 0..10 {
+            frames.mark_dirty();
+            assert!(!frames.poll(clock.now(), &clock).unwrap());
+            clock.advance(MS);
+        }
+        clock.set(Duration::from_secs(1));
+        assert_eq!(clock.take_due(), 1);
+    }
+
+    #[test]
+    fn unlimited_never_schedules() {
+        let clock = FakeClock::new();
+        let mut frames = FrameScheduler::new(0);
+        for _ in 0..10 {
+            frames.mark_dirty();
+            assert!(frames.poll(clock.now(), &clock).unwrap());
+        }
+        assert_eq!(clock.next_timeout(), None);
+    }
+
+    #[test]
+    fn clean_never_draws() {
+        let clock = FakeClock::new();
+        let mut frames = FrameScheduler::default();
+        assert!(!frames.poll(clock.now(), &clock).unwrap());
+        assert_eq!(clock.next_timeout(), None);
+    }
+}
@@ -0,0 +1,138 @@
+//! Fallbacks for box-drawing, block and arrow characters, for terminals or fonts that can't show them.
+//!
+//! Ratatui's borders, gauges and scrollbars use Unicode characters that come out as mojibake on terminals
+//! without good Unicode coverage. [AnsiBackend::set_glyph_fallback](crate::backend::AnsiBackend::set_glyph_fallback)
+//! maps them to something those terminals can show:
+//!
+//!  * [GlyphFallback::DecLineDrawing] uses the DEC Special Graphics character set (`ESC ( 0`), which VT100s and
+//!    nearly every terminal since can draw lines with. Characters it doesn't have fall back to ASCII.
+//!  * [GlyphFallback::Ascii] uses ASCII approximations. (`+`, `-`, `|`, `#`, `<`, `>`, …)
+
+/// How to draw box-drawing, block and arrow characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum GlyphFallback {
+    /// Draw them as they are.
+    #[default]
+    Unicode,
+
+    /// Use the DEC line-drawing character set, or ASCII for characters it doesn't have.
+    DecLineDrawing,
+
+    /// Use ASCII approximations.
+    Ascii,
+}
+
+/// How to draw a cell's symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Glyph<'a> {
+    Text(&'a str),
+
+    /// A character in the DEC Special Graphics set. (ex: `q` for `─`)
+    Dec(u8),
+}
+
+impl GlyphFallback {
+    pub(crate) fn map(self, symbol: &str) -> Glyph<'_> {
+        if self == GlyphFallback::Unicode || symbol.is_ascii() {
+            return Glyph::Text(symbol);
+        }
+        let mut chars = symbol.chars();
+        let (Some(ch), None) = (chars.next(), chars.next()) else { return Glyph::Text(symbol) };
+        let Some((dec, ascii)) = fallback(ch) else { return Glyph::Text(symbol) };
+        match (self, dec) {
+            (GlyphFallback::DecLineDrawing, Some(dec)) => Glyph::Dec(dec),
+            _ => Glyph::Text(ascii),
+        }
+    }
+}
+
+/// Whether a byte means something else in the DEC Special Graphics set.
+pub(crate) fn dec_remaps(byte: u8) -> bool {
+    (0x5f..=0x7e).contains(&byte)
+}
+
+/// The DEC Special Graphics character (if there is one), and the ASCII approximation for `ch`.
+fn fallback(ch: char) -> Option<(Option<u8>, &'static str)> {
+    let (dec, ascii) = match ch {
+        // Lines: light, heavy, double, and dashed.
+        '─' | '━' | '┄' | '┅' | '┈' | '┉' | '╌' | '╍' | '╴' | '╶' | '╸' | '╺' => (Some(b'q'), "-"),
+        '═' => (Some(b'q'), "="),
+        '│' | '┃' | '║' | '┆' | '┇' | '┊' | '┋' | '╎' | '╏' | '╵' | '╷' | '╹' | '╻' => (Some(b'x'), "|"),
+
+        // Corners, including rounded ones.
+        '┌' | '┏' | '╔' | '╭' | '┍' | '┎' | '╒' | '╓' => (Some(b'l'), "+"),
+        '┐' | '┓' | '╗' | '╮' | '┑' | '┒' | '╕' | '╖' => (Some(b'k'), "+"),
+        '└' | '┗' | '╚' | '╰' | '┕' | '┖' | '╘' | '╙' => (Some(b'm'), "+"),
+        '┘' | '┛' | '╝' | '╯' | '┙' | '┚' | '╛' | '╜' => (Some(b'j'), "+"),
+
+        // Tees and crosses.
+        '├' | '┣' | '╠' | '┝' | '┠' | '╞' | '╟' => (Some(b't'), "+"),
+        '┤' | '┫' | '╣' | '┥' | '┨' | '╡' | '╢' => (Some(b'u'), "+"),
+        '┬' | '┳' | '╦' | '┯' | '┰' | '╤' | '╥' => (Some(b'w'), "+"),
+        '┴' | '┻' | '╩' | '┷' | '┸' | '╧' | '╨' => (Some(b'v'), "+"),
+        '┼' | '╋' | '╬' | '┿' | '╂' | '╪' | '╫' => (Some(b'n'), "+"),
+
+        // Blocks and shades.
+        '█' | '▓' | '▒' | '░' => (Some(b'a'), "#"),
+        '▔' => (Some(b'o'), "-"),
+        '▁' => (Some(b's'), "_"),
+        '▀' | '▂' | '▃' | '▄' | '▅' | '▆' | '▇' | '▉' | '▊' | '▋' | '▌' | '▍' | '▎' | '▏' | '▐' | '▕' => {
+            (None, "#")
+        }
+        '▖' | '▗' | '▘' | '▙' | '▚' | '▛' | '▜' | '▝' | '▞' | '▟' => (None, "#"),
+
+        // Arrows and triangles.
+        '←' | '◄' | '◀' | '◂' | '⇐' => (None, "<"),
+        '→' | '►' | '▶' | '▸' | '⇒' => (None, ">"),
+        '↑' | '▲' | '▴' | '⇑' => (None, "^"),
+        '↓' | '▼' | '▾' | '⇓' => (None, "v"),
+        '↔' => (None, "-"),
+        '↕' => (None, "|"),
+
+        // Other symbols in the DEC set.
+        '◆' | '♦' => (Some(b'`'), "*"),
+        '°' => (Some(b'f'), "o"),
+        '±' => (Some(b'g'), "+"),
+        '≤' => (Some(b'y'), "<"),
+        '≥' => (Some(b'z'), ">"),
+        'π' => (Some(b'{'), "n"),
+        '≠' => (Some(b'|'), "#"),
+        '£' => (Some(b'}'), "L"),
+        '·' => (Some(b'~'), "."),
+        '•' | '∙' => (Some(b'~'), "*"),
+        _ => return None,
+    };
+    Some((dec, ascii))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map() {
+        use GlyphFallback::*;
+        for (symbol, dec, ascii) in [
+            ("─", Glyph::Dec(b'q'), Glyph::Text("-")),
+            ("╭", Glyph::Dec(b'l'), Glyph::Text("+")),
+            ("┼", Glyph::Dec(b'n'), Glyph::Text("+")),
+            ("█", Glyph::Dec(b'a'), Glyph::Text("#")),
+            ("▌", Glyph::Text("#"), Glyph::Text("#")),
+            ("→", Glyph::Text(">"), Glyph::Text(">")),
+            ("π", Glyph::Dec(b'{'), Glyph::Text("n")),
+            ("x", Glyph::Text("x"), Glyph::Text("x")),
+            ("日", Glyph::Text("日"), Glyph::Text("日")),
+            ("─\u{301}", Glyph::Text("─\u{301}"), Glyph::Text("─\u{301}")),
+        ] {
+            assert_eq!(Unicode.map(symbol), Glyph::Text(symbol), "{symbol}");
+            assert_eq!(DecLineDrawing.map(symbol), dec, "{symbol}");
+            assert_eq!(Ascii.map(symbol), ascii, "{symbol}");
+        }
+    }
+
+    #[test]
+    fn remapped_bytes() {
+        let remapped: String = (0x20..0x7f).filter(|it| dec_remaps(*it)).map(char::from).collect();
+        assert_eq!(remapped, "_`abcdefghijklmnopqrstuvwxyz{|}~");
+    }
+}
@@ -0,0 +1,116 @@
+//! Things the host environment provides to the backend.
+//!
+//! [AnsiBackend](crate::backend::AnsiBackend) writes its output to any [std::io::Write], and gets the
+//! terminal size from a [GetSize]. In the browser or Deno, those are JavaScript callbacks (see
+//! [types](crate::types)), but they can be anything. (ex: an in-memory buffer.)
+
+use std::{
+    cell::{Cell, RefCell},
+    io::Result as IOResult,
+    rc::Rc,
+    time::Duration,
+};
+
+use ratatui::layout::Size;
+
+/// Gets the current size of the terminal.
+pub trait GetSize {
+    fn get_size(&self) -> IOResult<Size>;
+}
+
+/// A terminal which never changes size.
+impl GetSize for Size {
+    fn get_size(&self) -> IOResult<Size> {
+        Ok(*self)
+    }
+}
+
+/// A size that you can change from elsewhere. (ex: when the host tells you about a resize.)
+impl GetSize for Rc<Cell<Size>> {
+    fn get_size(&self) -> IOResult<Size> {
+        Ok(self.get())
+    }
+}
+
+impl<T: GetSize + ?Sized> GetSize for Box<T> {
+    fn get_size(&self) -> IOResult<Size> {
+        self.as_ref().get_size()
+    }
+}
+
+/// A monotonic clock.
+pub trait Clock {
+    /// Time elapsed since some fixed point. (ex: page load)
+    fn now(&self) -> Duration;
+}
+
+impl<T: Clock + ?Sized> Clock for Box<T> {
+    fn now(&self) -> Duration {
+        self.as_ref().now()
+    }
+}
+
+/// Asks the host to call us back later. (ex: JavaScript's `setTimeout()`)
+///
+/// When the time is up, the host should call whatever scheduled it. (ex: [Runner::tick](crate::run::Runner::tick))
+pub trait Timer {
+    fn set_timeout(&self, delay: Duration) -> IOResult<()>;
+}
+
+impl<T: Timer + ?Sized> Timer for Box<T> {
+    fn set_timeout(&self, delay: Duration) -> IOResult<()> {
+        self.as_ref().set_timeout(delay)
+    }
+}
+
+/// A [Clock] and [Timer] for tests, which only moves when you tell it to.
+///
+/// Clones share the same time, so you can give one to the code under test and keep one to control it.
+#[derive(Debug, Clone, Default)]
+pub struct FakeClock {
+    now: Rc<Cell<Duration>>,
+
+    /// When each timeout that hasn't fired yet is due.
+    timeouts: Rc<RefCell<Vec<Duration>>>,
+}
+
+impl FakeClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, now: Duration) {
+        self.now.set(now);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+
+    /// When the next timeout is due.
+    pub fn next_timeout(&self) -> Option<Duration> {
+        self.timeouts.borrow().iter().min().copied()
+    }
+
+    /// Fire every timeout that's due. Returns how many there were.
+    pub fn take_due(&self) -> usize {
+        let now = self.now.get();
+        let mut timeouts = self.timeouts.borrow_mut();
+        let before = timeouts.len();
+        timeouts.retain(|due| *due > now);
+        before - timeouts.len()
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+}
+
+impl Timer for FakeClock {
+    fn set_timeout(&self, delay: Duration) -> IOResult<()> {
+        self.timeouts.borrow_mut().push(self.now.get() + delay);
+        Ok(())
+    }
+}
@@ -0,0 +1,515 @@
+//! A Ratatui `Backend` that renders to HTML instead of a terminal.
+//!
+//! [AnsiBackend](crate::backend::AnsiBackend) needs a full terminal emulator (ex: xterm.js) to display
+//! in the browser. [HtmlBackend] instead keeps its own grid of cells. After each `Terminal::draw()`, call
+//! [HtmlBackend::take_patches] to get just the rows that changed, and hand them to a small JavaScript
+//! renderer. (See the [TypeScript type](TSRowPatch) for what that data looks like.)
+
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::io::Result as IOResult;
+
+use ratatui::{
+    backend::{Backend, ClearType, WindowSize},
+    buffer::{Buffer, Cell},
+    layout::{Position, Rect, Size},
+    style::{Color, Modifier},
+};
+use unicode_width::UnicodeWidthStr as _;
+use wasm_bindgen::prelude::*;
+
+use crate::json;
+
+/// Renders Ratatui frames into a grid of styled spans.
+///
+/// Doesn't write anywhere on its own. Pull the changes out with [HtmlBackend::take_patches].
+pub struct HtmlBackend {
+    grid: Buffer,
+
+    /// Rows that have changed since the last call to [HtmlBackend::take_patches].
+    dirty: BTreeSet<u16>,
+
+    cursor: Position,
+    cursor_visible: bool,
+}
+
+impl HtmlBackend {
+    pub fn new(size: Size) -> Self {
+        let grid = Buffer::empty(Rect::new(0, 0, size.width, size.height));
+        Self {
+            dirty: (0..size.height).collect(),
+            grid,
+            cursor: Position::ORIGIN,
+            cursor_visible: true,
+        }
+    }
+
+    /// Change the size of the grid. Every row will be included in the next patches. The cursor stays inside it.
+    ///
+    /// Unlike a terminal, there's no window for us to ask. So it's up to the caller to resize us
+    /// (ex: when the containing element resizes) and then call `Terminal::autoresize()`.
+    pub fn resize(&mut self, size: Size) {
+        self.grid.resize(Rect::new(0, 0, size.width, size.height));
+        self.grid.reset();
+        self.cursor.x = self.cursor.x.min(size.width.saturating_sub(1));
+        self.cursor.y = self.cursor.y.min(size.height.saturating_sub(1));
+        self.invalidate();
+    }
+
+    /// Mark every row as changed, ex: if the JavaScript side has lost its DOM.
+    pub fn invalidate(&mut self) {
+        self.dirty = (0..self.grid.area.height).collect();
+    }
+
+    /// The cells as they are now.
+    pub fn buffer(&self) -> &Buffer {
+        &self.grid
+    }
+
+    /// Where the cursor should be shown, if it's visible.
+    pub fn cursor(&self) -> Option<Position> {
+        self.cursor_visible.then_some(self.cursor)
+    }
+
+    /// Returns the rows that have changed since the last call.
+    pub fn take_patches(&mut self) -> Vec<RowPatch> {
+        let dirty = std::mem::take(&mut self.dirty);
+        dirty.into_iter().map(|row| self.row_patch(row)).collect()
+    }
+
+    /// Like [HtmlBackend::take_patches], but serialized as JSON for JavaScript.
+    pub fn take_patches_json(&mut self) -> String {
+        patches_to_json(&self.take_patches())
+    }
+
+    /// Build the full contents of a single row.
+    pub fn row_patch(&self, row: u16) -> RowPatch {
+        RowPatch {
+            row,
+            spans: row_runs(&self.grid, row),
+        }
+    }
+
+    fn mark_dirty(&mut self, row: u16) {
+        if row < self.grid.area.height {
+            self.dirty.insert(row);
+        }
+    }
+}
+
+impl Backend for HtmlBackend {
+    fn draw<'a, I>(&mut self, content: I) -> IOResult<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>,
+    {
+        for (x, y, cell) in content {
+            let Some(target) = self.grid.cell_mut((x, y)) else {
+                continue;
+            };
+            if target != cell {
+                *target = cell.clone();
+                self.mark_dirty(y);
+            }
+        }
+        Ok(())
+    }
+
+    fn hide_cursor(&mut self) -> IOResult<()> {
+        self.cursor_visible = false;
+        Ok(())
+    }
+
+    fn show_cursor(&mut self) -> IOResult<()> {
+        self.cursor_visible = true;
+        Ok(())
+    }
+
+    fn get_cursor_position(&mut self) -> IOResult<Position> {
+        Ok(self.cursor)
+    }
+
+    fn set_cursor_position<P: Into<Position>>(&mut self, position: P) -> IOResult<()> {
+        self.cursor = position.into();
+        Ok(())
+    }
+
+    fn clear(&mut self) -> IOResult<()> {
+        self.grid.reset();
+        self.invalidate();
+        Ok(())
+    }
+
+    fn clear_region(&mut self, clear_type: ClearType) -> IOResult<()> {
+        let Rect { width, height, .. } = self.grid.area;
+        let Position { x, y } = self.cursor;
+        let (start, end) = match clear_type {
+            ClearType::All => return self.clear(),
+            ClearType::AfterCursor => ((x, y), (width, height.saturating_sub(1))),
+            // Like a terminal's, this includes the cursor's cell.
+            ClearType::BeforeCursor => ((0, 0), (x.saturating_add(1), y)),
+            ClearType::CurrentLine => ((0, y), (width, y)),
+            ClearType::UntilNewLine => ((x, y), (width, y)),
+        };
+
+        for row in start.1..=end.1.min(height.saturating_sub(1)) {
+            let from = if row == start.1 { start.0 } else { 0 };
+            let to = if row == end.1 { end.0 } else { width };
+            for col in from..to.min(width) {
+                self.grid[(col, row)].reset();
+            }
+            self.mark_dirty(row);
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> IOResult<Size> {
+        Ok(self.grid.area.as_size())
+    }
+
+    fn window_size(&mut self) -> IOResult<WindowSize> {
+        Ok(WindowSize {
+            columns_rows: self.size()?,
+            pixels: Default::default(),
+        })
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        // Nothing to do. Callers pull patches out with take_patches().
+        Ok(())
+    }
+}
+
+/// Replaces the full contents of one row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowPatch {
+    pub row: u16,
+    pub spans: Vec<SpanRun>,
+}
+
+/// A run of adjacent cells which share a style.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanRun {
+    pub text: String,
+    pub fg: Color,
+    pub bg: Color,
+    pub modifier: Modifier,
+}
+
+impl SpanRun {
+    fn same_style(&self, cell: &Cell) -> bool {
+        self.fg == cell.fg && self.bg == cell.bg && self.modifier == cell.modifier
+    }
+
+    /// Render as an HTML `<span>`.
+    ///
+    /// Colors are output as CSS variables (ex: `var(--rt-light-red)`), so that your stylesheet can pick the palette.
+    /// The default colors are `--rt-reset-fg` and `--rt-reset-bg`.
+    pub fn to_html(&self) -> String {
+        let reversed = self.modifier.contains(Modifier::REVERSED);
+        let (fg, bg) = if reversed {
+            // Swapping keeps the defaults visible, ex: the default background as the text color.
+            (css_color(self.bg, "bg"), css_color(self.fg, "fg"))
+        } else {
+            (css_color(self.fg, "fg"), css_color(self.bg, "bg"))
+        };
+
+        let mut style = String::new();
+        if self.fg != Color::Reset || reversed {
+            let _ = write!(style, "color:{fg};");
+        }
+        if self.bg != Color::Reset || reversed {
+            let _ = write!(style, "background-color:{bg};");
+        }
+
+        let classes = modifier_names(self.modifier).collect::<Vec<_>>().join(" ");
+
+        let mut html = String::from("<span");
+        if !classes.is_empty() {
+            let _ = write!(html, " class=\"{classes}\"");
+        }
+        if !style.is_empty() {
+            let _ = write!(html, " style=\"{style}\"");
+        }
+        html.push('>');
+        push_html_escaped(&mut html, &self.text);
+        html.push_str("</span>");
+        html
+    }
+
+    fn push_json(&self, out: &mut String) {
+        out.push_str("{\"text\":");
+        json::push_str(out, &self.text);
+        out.push_str(",\"fg\":");
+        push_json_color(out, self.fg);
+        out.push_str(",\"bg\":");
+        push_json_color(out, self.bg);
+        out.push_str(",\"modifiers\":[");
+        for (i, name) in modifier_names(self.modifier).enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            json::push_str(out, &name);
+        }
+        out.push_str("]}");
+    }
+}
+
+impl RowPatch {
+    /// Render all of the row's spans as HTML. See [SpanRun::to_html].
+    pub fn to_html(&self) -> String {
+        self.spans.iter().map(SpanRun::to_html).collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.push_json(&mut out);
+        out
+    }
+
+    fn push_json(&self, out: &mut String) {
+        let _ = write!(out, "{{\"row\":{},\"spans\":[", self.row);
+        for (i, span) in self.spans.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            span.push_json(out);
+        }
+        out.push_str("]}");
+    }
+}
+
+/// Split a row of a buffer into runs of cells that share a style.
+pub(crate) fn row_runs(buffer: &Buffer, row: u16) -> Vec<SpanRun> {
+    let mut spans: Vec<SpanRun> = vec![];
+    let mut to_skip = 0;
+    for x in buffer.area.left()..buffer.area.right() {
+        let cell = &buffer[(x, row)];
+
+        // Cells "under" a wide character were reset by Ratatui, but aren't visible.
+        if to_skip > 0 {
+            to_skip -= 1;
+            continue;
+        }
+        to_skip = cell.symbol().width().saturating_sub(1);
+
+        match spans.last_mut() {
+            Some(span) if span.same_style(cell) => span.text.push_str(cell.symbol()),
+            _ => spans.push(SpanRun {
+                text: cell.symbol().to_string(),
+                fg: cell.fg,
+                bg: cell.bg,
+                modifier: cell.modifier,
+            }),
+        }
+    }
+    spans
+}
+
+/// Serialize patches into a JSON array of [TSRowPatch].
+pub fn patches_to_json(patches: &[RowPatch]) -> String {
+    let mut out = String::from("[");
+    for (i, patch) in patches.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        patch.push_json(&mut out);
+    }
+    out.push(']');
+    out
+}
+
+#[wasm_bindgen(typescript_custom_section)]
+const TSRowPatch: &'static str = r#"
+/**
+ * Replaces the contents of a single row of an HtmlBackend's grid.
+ *
+ * Colors are: null (the default color), a name (ex: "light-red"), hex RGB (ex: '#ff8800'), or a 256-color index.
+ * Modifiers are lower-case Ratatui modifier names. (ex: "bold", "crossed_out")
+ */
+type RowPatch = {
+    row: number,
+    spans: {
+        text: string,
+        fg: string | number | null,
+        bg: string | number | null,
+        modifiers: string[],
+    }[]
+}
+"#;
+
+/// Kebab-case name for named colors, `#rrggbb` for RGB.
+fn color_name(color: Color) -> Option<String> {
+    let name = match color {
+        Color::Reset => return None,
+        Color::Black => "black",
+        Color::Red => "red",
+        Color::Green => "green",
+        Color::Yellow => "yellow",
+        Color::Blue => "blue",
+        Color::Magenta => "magenta",
+        Color::Cyan => "cyan",
+        Color::Gray => "gray",
+        Color::DarkGray => "dark-gray",
+        Color::LightRed => "light-red",
+        Color::LightGreen => "light-green",
+        Color::LightYellow => "light-yellow",
+        Color::LightBlue => "light-blue",
+        Color::LightMagenta => "light-magenta",
+        Color::LightCyan => "light-cyan",
+        Color::White => "white",
+        Color::Rgb(r, g, b) => return Some(format!("#{r:02x}{g:02x}{b:02x}")),
+        Color::Indexed(index) => return Some(index.to_string()),
+    };
+    Some(name.into())
+}
+
+fn css_color(color: Color, default: &str) -> String {
+    match (color, color_name(color)) {
+        (Color::Reset, _) | (_, None) => format!("var(--rt-reset-{default})"),
+        (Color::Rgb(..), Some(name)) => name,
+        (_, Some(name)) => format!("var(--rt-{name})"),
+    }
+}
+
+fn push_json_color(out: &mut String, color: Color) {
+    match color {
+        Color::Reset => out.push_str("null"),
+        Color::Indexed(index) => {
+            let _ = write!(out, "{index}");
+        }
+        color => json::push_str(out, &color_name(color).unwrap_or_default()),
+    }
+}
+
+fn modifier_names(modifier: Modifier) -> impl Iterator<Item = String> {
+    modifier.iter_names().map(|(name, _)| name.to_lowercase())
+}
+
+pub(crate) fn push_html_escaped(out: &mut String, text: &str) {
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            ch => out.push(ch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{
+        Terminal,
+        style::{Style, Stylize as _},
+        text::Line,
+        widgets::Paragraph,
+    };
+
+    use super::*;
+
+    fn text(backend: &HtmlBackend) -> Vec<String> {
+        let buffer = backend.buffer();
+        let rows = buffer.content.chunks(buffer.area.width as usize);
+        rows.map(|row| row.iter().map(Cell::symbol).collect()).collect()
+    }
+
+    fn filled() -> HtmlBackend {
+        let mut backend = HtmlBackend::new(Size::new(3, 3));
+        let cell = Cell::new("x");
+        let cells: Vec<_> = Rect::new(0, 0, 3, 3).positions().map(|it| (it.x, it.y, &cell)).collect();
+        backend.draw(cells.into_iter()).unwrap();
+        backend.set_cursor_position(Position::new(1, 1)).unwrap();
+        backend
+    }
+
+    #[test]
+    fn clear_region() {
+        for (clear_type, expected) in [
+            (ClearType::BeforeCursor, ["   ", "  x", "xxx"]),
+            (ClearType::AfterCursor, ["xxx", "x  ", "   "]),
+            (ClearType::CurrentLine, ["xxx", "   ", "xxx"]),
+            (ClearType::UntilNewLine, ["xxx", "x  ", "xxx"]),
+        ] {
+            let mut backend = filled();
+            backend.clear_region(clear_type).unwrap();
+            assert_eq!(text(&backend), expected, "{clear_type:?}");
+        }
+
+        // At the end of a row:
+        let mut backend = filled();
+        backend.set_cursor_position(Position::new(2, 0)).unwrap();
+        backend.clear_region(ClearType::BeforeCursor).unwrap();
+        assert_eq!(text(&backend), ["   ", "xxx", "xxx"]);
+    }
+
+    fn span(text: &str, fg: Color, modifier: Modifier) -> SpanRun {
+        SpanRun { text: text.into(), fg, bg: Color::Reset, modifier }
+    }
+
+    fn plain(text: &str) -> SpanRun {
+        span(text, Color::Reset, Modifier::empty())
+    }
+
+    #[test]
+    fn patches() {
+        let mut terminal = Terminal::new(HtmlBackend::new(Size::new(6, 3))).unwrap();
+        terminal
+            .draw(|frame| {
+                let lines = vec![Line::from(vec!["ab".red(), "cd".into()]), Line::from("ef").bold()];
+                frame.render_widget(Paragraph::new(lines), frame.area());
+            })
+            .unwrap();
+
+        // The first frame has every row:
+        let patches = terminal.backend_mut().take_patches();
+        assert_eq!(
+            patches,
+            [
+                RowPatch { row: 0, spans: vec![span("ab", Color::Red, Modifier::empty()), plain("cd  ")] },
+                RowPatch { row: 1, spans: vec![span("ef", Color::Reset, Modifier::BOLD), plain("    ")] },
+                RowPatch { row: 2, spans: vec![plain("      ")] },
+            ]
+        );
+        assert_eq!(terminal.backend_mut().take_patches(), []);
+
+        // Then only the rows that changed:
+        terminal.draw(|frame| frame.render_widget("ab", frame.area())).unwrap();
+        assert_eq!(terminal.backend_mut().take_patches().iter().map(|it| it.row).collect::<Vec<_>>(), [0, 1]);
+        terminal.draw(|frame| frame.render_widget("xb", frame.area())).unwrap();
+        assert_eq!(terminal.backend_mut().take_patches(), [RowPatch { row: 0, spans: vec![plain("xb    ")] }]);
+    }
+
+    #[test]
+    fn escaping() {
+        let patch = RowPatch { row: 0, spans: vec![span("<&\">", Color::Rgb(255, 136, 0), Modifier::ITALIC)] };
+        assert_eq!(
+            patch.to_html(),
+            "<span class=\"italic\" style=\"color:#ff8800;\">&lt;&amp;&quot;&gt;</span>"
+        );
+        assert_eq!(
+            patch.to_json(),
+            r##"{"row":0,"spans":[{"text":"<&\">","fg":"#ff8800","bg":null,"modifiers":["italic"]}]}"##
+        );
+    }
+
+    #[test]
+    fn wide_characters() {
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 8, 1));
+        buffer.set_string(0, 0, "日本", Style::new().blue());
+        buffer.set_string(4, 0, "語x", Style::new());
+        assert_eq!(
+            row_runs(&buffer, 0),
+            [span("日本", Color::Blue, Modifier::empty()), plain("語x ")]
+        );
+    }
+
+    #[test]
+    fn resize_keeps_the_cursor_inside() {
+        let mut backend = filled();
+        backend.set_cursor_position(Position::new(2, 2)).unwrap();
+        backend.resize(Size::new(2, 1));
+        assert_eq!(backend.cursor(), Some(Position::new(1, 0)));
+        assert_eq!(backend.take_patches().len(), 1);
+    }
+}
@@ -0,0 +1,412 @@
+//! Inline images, with the [kitty graphics protocol] or [sixel].
+//!
+//! Register an image with [AnsiBackend::add_image](crate::backend::AnsiBackend::add_image), then place it over
+//! a rectangle of cells after each draw with [AnsiBackend::place_image](crate::backend::AnsiBackend::place_image).
+//! The backend keeps track of where images are, so placing an image where it already is costs nothing, and
+//! drawing text over any part of an image removes it.
+//!
+//! To find out what the terminal supports, write [PROTOCOL_QUERY] and pass the reply to [detect_protocol].
+//!
+//! Sixel only understands PNG images with the `png` feature enabled. Kitty understands them natively.
+//!
+//! [kitty graphics protocol]: https://sw.kovidgoyal.net/kitty/graphics-protocol/
+//! [sixel]: https://vt100.net/docs/vt3xx-gp/chapter14.html
+
+use std::{borrow::Cow, collections::HashMap, fmt::Write as _};
+
+use ratatui::layout::{Rect, Size};
+
+use crate::error::BackendError;
+
+pub type ImageId = u32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageData {
+    /// 8-bit RGBA pixels, row by row.
+    Rgba { width: u32, height: u32, pixels: Vec<u8> },
+
+    /// An encoded PNG file.
+    Png(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsProtocol {
+    Kitty,
+    Sixel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GraphicsOptions {
+    pub protocol: GraphicsProtocol,
+
+    /// The size of one cell, in pixels. Sixel images are scaled to fit their rectangle using this.
+    ///
+    /// (Kitty scales images itself.)
+    pub cell_size: Size,
+}
+
+impl GraphicsOptions {
+    pub fn new(protocol: GraphicsProtocol) -> Self {
+        Self {
+            protocol,
+            cell_size: Size::new(10, 20),
+        }
+    }
+}
+
+/// Asks the terminal whether it supports kitty graphics, then for its Primary Device Attributes (which
+/// include sixel support).
+///
+/// Every terminal answers the second query, so you know when you've got the whole reply.
+pub const PROTOCOL_QUERY: &str = "\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\\x1b[c";
+
+/// Work out which protocol to use from the terminal's reply to [PROTOCOL_QUERY].
+pub fn detect_protocol(reply: &[u8]) -> Option<GraphicsProtocol> {
+    let reply = String::from_utf8_lossy(reply);
+    if reply.contains("\x1b_Gi=31;OK") {
+        return Some(GraphicsProtocol::Kitty);
+    }
+
+    // Primary Device Attributes: CSI ? 62 ; 4 ; ... c. Attribute 4 means sixel.
+    let start = reply.find("\x1b[?")? + 3;
+    let end = start + reply[start..].find('c')?;
+    reply[start..end].split(';').any(|it| it == "4").then_some(GraphicsProtocol::Sixel)
+}
+
+/// The images an [AnsiBackend](crate::backend::AnsiBackend) knows about, and where they are on screen.
+#[derive(Default)]
+pub(crate) struct Images {
+    pub(crate) options: Option<GraphicsOptions>,
+    images: HashMap<ImageId, Image>,
+}
+
+struct Image {
+    data: ImageData,
+
+    /// We've sent the data to the terminal. (kitty only)
+    uploaded: bool,
+    placement: Option<Rect>,
+}
+
+impl Images {
+    pub(crate) fn add(&mut self, id: ImageId, data: ImageData) {
+        self.images.insert(id, Image { data, uploaded: false, placement: None });
+    }
+
+    pub(crate) fn remove(&mut self, id: ImageId) {
+        self.images.remove(&id);
+    }
+
+    pub(crate) fn placement(&self, id: ImageId) -> Option<Rect> {
+        self.images.get(&id).and_then(|it| it.placement)
+    }
+
+    /// Every image that's on screen, and where.
+    pub(crate) fn placements(&self) -> Vec<(ImageId, Rect)> {
+        let mut placed: Vec<_> = self.images.iter().filter_map(|(id, it)| Some((*id, it.placement?))).collect();
+        placed.sort_by_key(|(id, _)| *id);
+        placed
+    }
+
+    /// Forget every placement, because the screen is being cleared.
+    ///
+    /// Returns the sequence to clear kitty images, which aren't affected by clearing the text.
+    pub(crate) fn clear(&mut self) -> Option<String> {
+        let mut any = false;
+        for image in self.images.values_mut() {
+            any |= image.placement.take().is_some();
+        }
+        match self.options?.protocol {
+            GraphicsProtocol::Kitty if any => Some("\x1b_Ga=d,d=a,q=2\x1b\\".into()),
+            _ => None,
+        }
+    }
+
+    /// Forget placements that overlap the cell at (x, y), which is about to be overwritten.
+    pub(crate) fn overwrite(&mut self, x: u16, y: u16) -> Vec<(ImageId, Rect)> {
+        let mut removed = vec![];
+        for (id, image) in &mut self.images {
+            if let Some(area) = image.placement
+                && area.contains((x, y).into())
+            {
+                image.placement = None;
+                removed.push((*id, area));
+            }
+        }
+        removed
+    }
+
+    /// The sequence to show image `id` over `area`, with the cursor at its top-left corner.
+    pub(crate) fn place(&mut self, id: ImageId, area: Rect) -> Result<String, BackendError> {
+        let Some(options) = self.options else {
+            return Err(BackendError::Encoding("no graphics protocol has been set".into()));
+        };
+        let Some(image) = self.images.get_mut(&id) else {
+            return Err(BackendError::Encoding(format!("no image with id {id}")));
+        };
+
+        let out = match options.protocol {
+            GraphicsProtocol::Kitty => {
+                let mut out = String::new();
+                if !image.uploaded {
+                    kitty_transmit(id, &image.data, &mut out);
+                    image.uploaded = true;
+                }
+                // C=1: Don't move the cursor. q=2: Don't reply.
+                let _ = write!(out, "\x1b_Ga=p,i={id},p=1,c={},r={},C=1,q=2\x1b\\", area.width, area.height);
+                out
+            }
+            GraphicsProtocol::Sixel => {
+                let (width, height, pixels) = rgba(&image.data)?;
+                let target_width = area.width as u32 * options.cell_size.width as u32;
+                let target_height = area.height as u32 * options.cell_size.height as u32;
+                sixel(width, height, &pixels, target_width, target_height)
+            }
+        };
+        image.placement = Some(area);
+        Ok(out)
+    }
+
+    /// The sequence to take image `id` off the screen. Sixel images can only be covered up, so returns `None`.
+    pub(crate) fn unplace(&self, id: ImageId) -> Option<String> {
+        match self.options?.protocol {
+            GraphicsProtocol::Kitty => Some(format!("\x1b_Ga=d,d=i,i={id},p=1,q=2\x1b\\")),
+            GraphicsProtocol::Sixel => None,
+        }
+    }
+
+    /// The sequence to delete image `id`'s data from the terminal, if we sent it.
+    pub(crate) fn delete(&self, id: ImageId) -> Option<String> {
+        if !self.images.get(&id)?.uploaded {
+            return None;
+        }
+        match self.options?.protocol {
+            GraphicsProtocol::Kitty => Some(format!("\x1b_Ga=d,d=I,i={id},q=2\x1b\\")),
+            GraphicsProtocol::Sixel => None,
+        }
+    }
+}
+
+/// Send image data to a kitty terminal, in chunks of up to 4096 bytes of base64.
+fn kitty_transmit(id: ImageId, data: &ImageData, out: &mut String) {
+    let (format, bytes) = match data {
+        ImageData::Rgba { width, height, pixels } => (format!("f=32,s={width},v={height}"), pixels),
+        ImageData::Png(bytes) => ("f=100".to_string(), bytes),
+    };
+    let encoded = base64(bytes);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(4096).collect();
+    if chunks.is_empty() {
+        let _ = write!(out, "\x1b_Ga=t,{format},i={id},q=2;\x1b\\");
+        return;
+    }
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = (index + 1 < chunks.len()) as u8;
+        // Only the first chunk has the control data:
+        let _ = if index == 0 {
+            write!(out, "\x1b_Ga=t,{format},i={id},q=2,m={more};")
+        } else {
+            write!(out, "\x1b_Gm={more};")
+        };
+        // Infallible: base64 is ASCII.
+        out.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        out.push_str("\x1b\\");
+    }
+}
+
+/// Get RGBA pixels for an image, decoding it if necessary.
+fn rgba(data: &ImageData) -> Result<(u32, u32, Cow<'_, [u8]>), BackendError> {
+    match data {
+        ImageData::Rgba { width, height, pixels } => {
+            if pixels.len() as u64 != *width as u64 * *height as u64 * 4 {
+                return Err(BackendError::Encoding(format!(
+                    "expected {width}x{height}x4 bytes of RGBA, got {}",
+                    pixels.len()
+                )));
+            }
+            Ok((*width, *height, Cow::Borrowed(pixels)))
+        }
+        ImageData::Png(bytes) => decode_png(bytes),
+    }
+}
+
+#[cfg(feature = "png")]
+fn decode_png(bytes: &[u8]) -> Result<(u32, u32, Cow<'static, [u8]>), BackendError> {
+    let err = |err: png::DecodingError| BackendError::Encoding(format!("couldn't decode PNG: {err}"));
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(err)?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(err)?;
+    buf.truncate(info.buffer_size());
+
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf.chunks(3).flat_map(|it| [it[0], it[1], it[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => buf.chunks(2).flat_map(|it| [it[0], it[0], it[0], it[1]]).collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|it| [*it, *it, *it, 255]).collect(),
+        png::ColorType::Indexed => {
+            return Err(BackendError::Encoding("couldn't expand indexed PNG".into()));
+        }
+    };
+    Ok((info.width, info.height, Cow::Owned(pixels)))
+}
+
+#[cfg(not(feature = "png"))]
+fn decode_png(_bytes: &[u8]) -> Result<(u32, u32, Cow<'static, [u8]>), BackendError> {
+    Err(BackendError::Encoding("sixel images from PNG need the \"png\" feature".into()))
+}
+
+/// Encode RGBA pixels as sixel, scaled (nearest neighbor) to the target size.
+///
+/// Colors are reduced to a 6x6x6 cube. Pixels that are mostly transparent are left unpainted.
+fn sixel(width: u32, height: u32, pixels: &[u8], target_width: u32, target_height: u32) -> String {
+    // Palette register for each target pixel, or None if transparent:
+    // (In u64: Large images or targets would overflow u32.)
+    let mut registers = Vec::with_capacity((target_width as u64 * target_height as u64) as usize);
+    for y in 0..target_height {
+        let source_y = y as u64 * height as u64 / target_height.max(1) as u64;
+        for x in 0..target_width {
+            let source_x = x as u64 * width as u64 / target_width.max(1) as u64;
+            let offset = ((source_y * width as u64 + source_x) * 4) as usize;
+            let register = match pixels.get(offset..offset + 4) {
+                Some([r, g, b, a]) if *a >= 128 => {
+                    let level = |it: u8| (it as u16 * 5 + 127) / 255;
+                    Some((level(*r) * 36 + level(*g) * 6 + level(*b)) as u8)
+                }
+                _ => None,
+            };
+            registers.push(register);
+        }
+    }
+
+    // P2=1: Leave unpainted pixels transparent. Raster attributes: 1:1 aspect ratio, then the size.
+    let mut out = format!("\x1bP0;1;0q\"1;1;{target_width};{target_height}");
+    let mut used = [false; 216];
+    registers.iter().flatten().for_each(|it| used[*it as usize] = true);
+    for (register, _) in used.iter().enumerate().filter(|(_, used)| **used) {
+        let percent = |level: usize| level * 100 / 5;
+        let (r, g, b) = (register / 36, (register / 6) % 6, register % 6);
+        let _ = write!(out, "#{register};2;{};{};{}", percent(r), percent(g), percent(b));
+    }
+
+    for band in (0..target_height).step_by(6) {
+        let mut first = true;
+        for register in (0..216u8).filter(|it| used[*it as usize]) {
+            let column = |x: u32| -> u8 {
+                let mut bits = 0;
+                for row in 0..6 {
+                    let y = band + row;
+                    if y < target_height && registers[(y as u64 * target_width as u64 + x as u64) as usize] == Some(register) {
+                        bits |= 1 << row;
+                    }
+                }
+                bits
+            };
+            if (0..target_width).all(|x| column(x) == 0) {
+                continue;
+            }
+            if !first {
+                // Carriage return, to paint the next color over the same band.
+                out.push('$');
+            }
+            first = false;
+            let _ = write!(out, "#{register}");
+
+            let mut x = 0;
+            while x < target_width {
+                let bits = column(x);
+                let mut run = 1;
+                while x + run < target_width && column(x + run) == bits {
+                    run += 1;
+                }
+                let ch = (63 + bits) as char;
+                if run >= 4 {
+                    let _ = write!(out, "!{run}{ch}");
+                } else {
+                    (0..run).for_each(|_| out.push(ch));
+                }
+                x += run;
+            }
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, byte)| n | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sixel_scales() {
+        // 2x1: red, then transparent.
+        let pixels = [255, 0, 0, 255, 0, 0, 0, 0];
+        let out = sixel(2, 1, &pixels, 4, 2);
+        assert_eq!(out, "\x1bP0;1;0q\"1;1;4;2#180;2;100;0;0#180BB??-\x1b\\");
+    }
+
+    #[test]
+    fn huge_sources_dont_overflow() {
+        // Offsets past u32::MAX. (There are no pixels, so nothing is painted.)
+        let out = sixel(100_000, 100_000, &[], 3, 3);
+        assert_eq!(out, "\x1bP0;1;0q\"1;1;3;3-\x1b\\");
+    }
+
+    #[test]
+    fn detect() {
+        let kitty = b"\x1b_Gi=31;OK\x1b\\\x1b[?62;22c";
+        assert_eq!(detect_protocol(kitty), Some(GraphicsProtocol::Kitty));
+        assert_eq!(detect_protocol(b"\x1b[?62;4;22c"), Some(GraphicsProtocol::Sixel));
+        assert_eq!(detect_protocol(b"\x1b[?4c"), Some(GraphicsProtocol::Sixel));
+        assert_eq!(detect_protocol(b"\x1b[?62;14;22c"), None);
+        assert_eq!(detect_protocol(b"\x1b_Gi=31;ENOTSUPPORTED\x1b\\\x1b[?1;2c"), None);
+        assert_eq!(detect_protocol(b"\x1b[?62;4"), None);
+        assert_eq!(detect_protocol(b""), None);
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(base64(&[0xff, 0xfe, 0xfd]), "//79");
+    }
+
+    #[test]
+    fn kitty_chunks() {
+        let transmit = |len: usize| {
+            let mut out = String::new();
+            kitty_transmit(7, &ImageData::Png(vec![0; len]), &mut out);
+            out
+        };
+
+        // 3072 bytes is exactly 4096 bytes of base64:
+        let out = transmit(3072);
+        assert_eq!(out, format!("\x1b_Ga=t,f=100,i=7,q=2,m=0;{}\x1b\\", "A".repeat(4096)));
+
+        let out = transmit(3073);
+        let expected = format!("\x1b_Ga=t,f=100,i=7,q=2,m=1;{}\x1b\\\x1b_Gm=0;AA==\x1b\\", "A".repeat(4096));
+        assert_eq!(out, expected);
+
+        assert_eq!(transmit(0), "\x1b_Ga=t,f=100,i=7,q=2;\x1b\\");
+    }
+}
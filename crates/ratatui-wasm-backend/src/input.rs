@@ -0,0 +1,681 @@
+//! Parse terminal input: key presses, mouse events, and replies to queries.
+//!
+//! Feed the bytes you read from stdin to a [Parser], then iterate over it to get the [Sequence]s they contain:
+//!
+//! ```ignore
+//! parser.advance(bytes, false);
+//! for seq in parser.by_ref() {
+//!     app.handle(seq);
+//! }
+//! ```
+//!
+//! The types mirror those of `anes::parser`, which this replaces, plus [Sequence::Raw] for anything we don't
+//! understand. Sequences split across reads (including UTF-8 characters) are held until the rest arrives.
+//!
+//! Some input is ambiguous:
+//!  * A lone ESC may be the Esc key, or the start of a sequence. Pass `more: false` to [Parser::advance] when
+//!    there's no more input waiting, and it's treated as the Esc key. Likewise, a trailing `ESC [` is Alt-[,
+//!    and so on.
+//!  * `CSI 1;5R` may be Ctrl-F3, or a cursor position report. We always treat it as a cursor position.
+//!  * 0x08 may be Ctrl-H or Backspace. We return `Char('\u{8}')`. See [Erase](crate::ctrl::Erase).
+
+use std::collections::VecDeque;
+
+use bitflags::bitflags;
+
+/// A parsed input sequence.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub enum Sequence {
+    Key(KeyCode, KeyModifiers),
+    Mouse(Mouse, KeyModifiers),
+
+    /// A reply to a cursor position request (DSR 6): `x` and `y`, 1-based.
+    CursorPosition(u16, u16),
+
+    /// Anything we didn't recognize (ex: replies to other queries), as received.
+    Raw(Vec<u8>),
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct KeyModifiers: u8 {
+        const SHIFT = 0b0000_0001;
+        const CONTROL = 0b0000_0010;
+        const ALT = 0b0000_0100;
+        const META = 0b0000_1000;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum KeyCode {
+    Backspace,
+    Enter,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Tab,
+    BackTab,
+    Delete,
+    Insert,
+    F(u8),
+    Char(char),
+    Null,
+    Esc,
+}
+
+/// A mouse event, at (x, y), 1-based.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Mouse {
+    Down(MouseButton, u16, u16),
+    Up(MouseButton, u16, u16),
+    Drag(MouseButton, u16, u16),
+    ScrollUp(u16, u16),
+    ScrollDown(u16, u16),
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+
+    /// The terminal didn't say. (ex: X10 mouse releases, or motion with no buttons held)
+    Any,
+}
+
+/// Longest escape sequence we'll wait for, before giving up and passing it through raw.
+const MAX_SEQUENCE: usize = 64 * 1024;
+
+/// Parses terminal input into [Sequence]s.
+#[derive(Debug, Default)]
+pub struct Parser {
+    /// Bytes we haven't parsed yet, because they're the start of an incomplete sequence.
+    pending: Vec<u8>,
+    seqs: VecDeque<Sequence>,
+}
+
+impl Parser {
+    /// Parse more input. `more` says whether you know there's more input waiting to be read. (Usually `false`.)
+    pub fn advance(&mut self, bytes: &[u8], more: bool) {
+        self.pending.extend_from_slice(bytes);
+        let mut start = 0;
+        while start < self.pending.len() {
+            let Some((seq, len)) = parse(&self.pending[start..], more) else { break };
+            self.seqs.push_back(seq);
+            start += len;
+        }
+        self.pending.drain(..start);
+    }
+}
+
+impl Iterator for Parser {
+    type Item = Sequence;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.seqs.pop_front()
+    }
+}
+
+fn key(code: KeyCode) -> Sequence {
+    Sequence::Key(code, KeyModifiers::empty())
+}
+
+/// Parse one sequence from the start of `buf`, and say how many bytes it used.
+///
+/// Returns `None` if `buf` is the start of a sequence that we need more bytes for.
+fn parse(buf: &[u8], more: bool) -> Option<(Sequence, usize)> {
+    let seq = match buf[0] {
+        0x1b => return parse_escape(buf, more),
+        0x00 => key(KeyCode::Null),
+        b'\t' => key(KeyCode::Tab),
+        b'\r' | b'\n' => key(KeyCode::Enter),
+        0x7f => key(KeyCode::Backspace),
+        byte if byte < 0x80 => key(KeyCode::Char(byte as char)),
+        byte => {
+            let len = match byte {
+                0xc2..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf4 => 4,
+                _ => return Some((Sequence::Raw(vec![byte]), 1)),
+            };
+            let Some(bytes) = buf.get(..len) else {
+                // Wait for the rest of the character, unless what we have is already invalid:
+                return match std::str::from_utf8(buf) {
+                    Err(err) if err.error_len().is_some() => Some((Sequence::Raw(vec![byte]), 1)),
+                    _ => None,
+                };
+            };
+            return match std::str::from_utf8(bytes).ok().and_then(|it| it.chars().next()) {
+                Some(ch) => Some((key(KeyCode::Char(ch)), len)),
+                None => Some((Sequence::Raw(vec![byte]), 1)),
+            };
+        }
+    };
+    Some((seq, 1))
+}
+
+fn parse_escape(buf: &[u8], more: bool) -> Option<(Sequence, usize)> {
+    let Some(&next) = buf.get(1) else {
+        return if more { None } else { Some((key(KeyCode::Esc), 1)) };
+    };
+    match next {
+        b'[' if buf.len() > 2 => parse_csi(buf),
+        b'O' if buf.len() > 2 => parse_ss3(buf),
+        // Device Control String, Operating System Command, Application Program Command, etc. (ex: replies to
+        // queries) run until a String Terminator. Unless that's all there is, and it's Alt-P, Alt-], etc.
+        b'P' | b']' | b'_' | b'^' | b'X' if buf.len() > 2 => parse_string(buf),
+        b'[' | b'O' | b'P' | b']' | b'_' | b'^' | b'X' if more => None,
+        // Not Alt-Esc: the second ESC starts a sequence of its own.
+        0x1b => Some((key(KeyCode::Esc), 1)),
+        _ => {
+            // Alt-$key:
+            match parse(&buf[1..], more) {
+                Some((Sequence::Key(code, modifiers), len)) => {
+                    Some((Sequence::Key(code, modifiers | KeyModifiers::ALT), len + 1))
+                }
+                Some(_) => Some((key(KeyCode::Esc), 1)),
+                None => None,
+            }
+        }
+    }
+}
+
+/// `ESC O` sequences, used for F1-F4, and for arrows in "application cursor" mode.
+fn parse_ss3(buf: &[u8]) -> Option<(Sequence, usize)> {
+    // Some terminals put the modifier between O and the key. (ex: ESC O 5 P)
+    let digits = buf[2..].iter().take_while(|it| it.is_ascii_digit()).count();
+    if digits > 5 {
+        return Some((Sequence::Raw(buf[..2 + digits].to_vec()), 2 + digits));
+    }
+    let &last = buf.get(2 + digits)?;
+    let len = 3 + digits;
+    let modifiers = std::str::from_utf8(&buf[2..2 + digits]).ok().and_then(|it| it.parse().ok());
+    let code = match last {
+        b'P'..=b'S' => KeyCode::F(last - b'P' + 1),
+        b'A' => KeyCode::Up,
+        b'B' => KeyCode::Down,
+        b'C' => KeyCode::Right,
+        b'D' => KeyCode::Left,
+        b'H' => KeyCode::Home,
+        b'F' => KeyCode::End,
+        b'M' => KeyCode::Enter,
+        _ => return Some((Sequence::Raw(buf[..len].to_vec()), len)),
+    };
+    Some((Sequence::Key(code, key_modifiers(modifiers)), len))
+}
+
+/// Strings like OSC, DCS and APC, which end with ST (`ESC \`), or (for OSC) BEL.
+fn parse_string(buf: &[u8]) -> Option<(Sequence, usize)> {
+    let end = buf[2..].iter().enumerate().find_map(|(i, byte)| match byte {
+        0x07 if buf[1] == b']' => Some(2 + i + 1),
+        0x1b if buf.get(2 + i + 1) == Some(&b'\\') => Some(2 + i + 2),
+        _ => None,
+    });
+    match end {
+        Some(end) => Some((Sequence::Raw(buf[..end].to_vec()), end)),
+        None if buf.len() >= MAX_SEQUENCE => Some((Sequence::Raw(buf.to_vec()), buf.len())),
+        None => None,
+    }
+}
+
+/// Control Sequence Introducer: `ESC [`, then parameters, then a final byte.
+fn parse_csi(buf: &[u8]) -> Option<(Sequence, usize)> {
+    if buf[2] == b'M' {
+        return parse_x10_mouse(buf);
+    }
+
+    // Parameter bytes, then intermediate bytes, then the final byte:
+    let mut end = 2;
+    while buf.get(end).is_some_and(|it| (0x30..=0x3f).contains(it)) {
+        end += 1;
+    }
+    let params_end = end;
+    while buf.get(end).is_some_and(|it| (0x20..=0x2f).contains(it)) {
+        end += 1;
+    }
+    let Some(&last) = buf.get(end) else {
+        if buf.len() >= MAX_SEQUENCE {
+            return Some((Sequence::Raw(buf.to_vec()), buf.len()));
+        }
+        return None;
+    };
+    if !(0x40..=0x7e).contains(&last) {
+        // Malformed. Pass through what we have, and parse the rest separately.
+        return Some((Sequence::Raw(buf[..end].to_vec()), end));
+    }
+    let len = end + 1;
+    let raw = || Some((Sequence::Raw(buf[..len].to_vec()), len));
+    if end != params_end {
+        return raw();
+    }
+
+    let (private, params) = match buf[2] {
+        prefix @ (b'<' | b'=' | b'>' | b'?') => (Some(prefix), &buf[3..params_end]),
+        _ => (None, &buf[2..params_end]),
+    };
+    let Some(params) = parse_params(params) else { return raw() };
+    let param = |i: usize| params.get(i).copied().flatten();
+
+    let seq = match (private, last, params.len()) {
+        (None, b'A', _) => Sequence::Key(KeyCode::Up, key_modifiers(param(1))),
+        (None, b'B', _) => Sequence::Key(KeyCode::Down, key_modifiers(param(1))),
+        (None, b'C', _) => Sequence::Key(KeyCode::Right, key_modifiers(param(1))),
+        (None, b'D', _) => Sequence::Key(KeyCode::Left, key_modifiers(param(1))),
+        (None, b'H', _) => Sequence::Key(KeyCode::Home, key_modifiers(param(1))),
+        (None, b'F', _) => Sequence::Key(KeyCode::End, key_modifiers(param(1))),
+        (None, b'Z', _) => Sequence::Key(KeyCode::BackTab, key_modifiers(param(1))),
+        (None, b'R', 2) => Sequence::CursorPosition(param(1).unwrap_or(1), param(0).unwrap_or(1)),
+        (None, b'P'..=b'S', _) => Sequence::Key(KeyCode::F(last - b'P' + 1), key_modifiers(param(1))),
+        (None, b'~', _) => {
+            let code = match param(0) {
+                Some(1 | 7) => KeyCode::Home,
+                Some(2) => KeyCode::Insert,
+                Some(3) => KeyCode::Delete,
+                Some(4 | 8) => KeyCode::End,
+                Some(5) => KeyCode::PageUp,
+                Some(6) => KeyCode::PageDown,
+                Some(n @ 11..=15) => KeyCode::F(n as u8 - 10),
+                Some(n @ 17..=21) => KeyCode::F(n as u8 - 11),
+                Some(n @ 23..=26) => KeyCode::F(n as u8 - 12),
+                Some(n @ 28..=29) => KeyCode::F(n as u8 - 13),
+                Some(n @ 31..=34) => KeyCode::F(n as u8 - 14),
+                _ => return raw(),
+            };
+            Sequence::Key(code, key_modifiers(param(1)))
+        }
+        // "fixterms", or kitty's keyboard protocol: CSI codepoint ; modifiers u
+        (None, b'u', _) => {
+            let code = match param(0) {
+                Some(9) => KeyCode::Tab,
+                Some(13) => KeyCode::Enter,
+                Some(27) => KeyCode::Esc,
+                Some(127) => KeyCode::Backspace,
+                Some(n) => match char::from_u32(n as u32) {
+                    Some(ch) => KeyCode::Char(ch),
+                    None => return raw(),
+                },
+                None => return raw(),
+            };
+            Sequence::Key(code, key_modifiers(param(1)))
+        }
+        // SGR mouse: CSI < button ; x ; y M (press) or m (release)
+        (Some(b'<'), b'M' | b'm', 3) => {
+            let (Some(button), Some(x), Some(y)) = (param(0), param(1), param(2)) else { return raw() };
+            match mouse(button, x, y, last == b'm') {
+                Some(seq) => seq,
+                None => return raw(),
+            }
+        }
+        // urxvt mouse: CSI button ; x ; y M
+        (None, b'M', 3) => {
+            let (Some(button), Some(x), Some(y)) = (param(0), param(1), param(2)) else { return raw() };
+            match button.checked_sub(32).and_then(|button| mouse(button, x, y, false)) {
+                Some(seq) => seq,
+                None => return raw(),
+            }
+        }
+        _ => return raw(),
+    };
+    Some((seq, len))
+}
+
+/// Parse `;`-separated numbers. Empty parameters are `None`. Sub-parameters (after `:`) are ignored.
+fn parse_params(params: &[u8]) -> Option<Vec<Option<u16>>> {
+    if params.is_empty() {
+        return Some(vec![]);
+    }
+    let params = std::str::from_utf8(params).ok()?;
+    params
+        .split(';')
+        .map(|param| {
+            let param = param.split(':').next().unwrap_or_default();
+            if param.is_empty() {
+                return Some(None);
+            }
+            // Saturate huge numbers, but reject anything else that's not a number:
+            param.bytes().all(|it| it.is_ascii_digit()).then(|| Some(param.parse().unwrap_or(u16::MAX)))
+        })
+        .collect()
+}
+
+/// X10 mouse: `CSI M`, then three bytes, each offset by 32: button, x, y.
+fn parse_x10_mouse(buf: &[u8]) -> Option<(Sequence, usize)> {
+    let bytes = buf.get(3..6)?;
+    let [button, x, y] = [bytes[0], bytes[1], bytes[2]].map(|it| (it as u16).checked_sub(32));
+    let seq = match (button, x, y) {
+        (Some(button), Some(x), Some(y)) => mouse(button, x, y, false),
+        _ => None,
+    };
+    Some((seq.unwrap_or_else(|| Sequence::Raw(buf[..6].to_vec())), 6))
+}
+
+/// Decode a mouse button byte.
+fn mouse(button: u16, x: u16, y: u16, release: bool) -> Option<Sequence> {
+    let mut modifiers = KeyModifiers::empty();
+    if button & 0b0000_0100 != 0 {
+        modifiers |= KeyModifiers::SHIFT;
+    }
+    if button & 0b0000_1000 != 0 {
+        modifiers |= KeyModifiers::ALT;
+    }
+    if button & 0b0001_0000 != 0 {
+        modifiers |= KeyModifiers::CONTROL;
+    }
+
+    let mouse = if button & 0b0100_0000 != 0 {
+        match button & 0b0000_0011 {
+            0 => Mouse::ScrollUp(x, y),
+            1 => Mouse::ScrollDown(x, y),
+            // Horizontal scrolling:
+            _ => return None,
+        }
+    } else {
+        let which = match button & 0b0000_0011 {
+            0 => MouseButton::Left,
+            1 => MouseButton::Middle,
+            2 => MouseButton::Right,
+            _ => MouseButton::Any,
+        };
+        let drag = button & 0b0010_0000 != 0;
+        match (release, drag, which) {
+            (true, _, _) => Mouse::Up(which, x, y),
+            (false, true, _) => Mouse::Drag(which, x, y),
+            // X10 mouse reports every release as button 3.
+            (false, false, MouseButton::Any) => Mouse::Up(which, x, y),
+            (false, false, _) => Mouse::Down(which, x, y),
+        }
+    };
+    Some(Sequence::Mouse(mouse, modifiers))
+}
+
+/// Modifiers are sent as 1 + a bitmask.
+fn key_modifiers(param: Option<u16>) -> KeyModifiers {
+    let bits = param.unwrap_or(1).saturating_sub(1);
+    let mut modifiers = KeyModifiers::empty();
+    for (bit, modifier) in [
+        (1, KeyModifiers::SHIFT),
+        (2, KeyModifiers::ALT),
+        (4, KeyModifiers::CONTROL),
+        (8, KeyModifiers::META),
+    ] {
+        if bits & bit != 0 {
+            modifiers |= modifier;
+        }
+    }
+    modifiers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pieces to build input from: whole sequences, the starts of sequences, and bytes that aren't valid anything.
+    const PIECES: &[&[u8]] = &[
+        b"a",
+        b"Z",
+        b"\r",
+        b"\t",
+        b"\x00",
+        b"\x7f",
+        b"\x1b",
+        b"\x1b[",
+        b"\x1bO",
+        b"\x1b]",
+        b"\x1bP",
+        b"\x1b[A",
+        b"\x1b[1;5C",
+        b"\x1b[3~",
+        b"\x1b[15;2~",
+        b"\x1b[97;5u",
+        b"\x1b[12;34R",
+        b"\x1b[<0;10;20M",
+        b"\x1b[<0;10;20m",
+        b"\x1b[<64;1;1M",
+        b"\x1b[M !!",
+        b"\x1b[32;5;5M",
+        b"\x1bOP",
+        b"\x1bO5Q",
+        b"\x1b]11;rgb:0000/0000/0000\x07",
+        b"\x1bP>|xterm\x1b\\",
+        b"\x1b[?1;2c",
+        b"\x1b[1;",
+        b"\x1b[99999999;1H",
+        b"\x1b[1\x01",
+        "é".as_bytes(),
+        "日".as_bytes(),
+        "🦀".as_bytes(),
+        b"\xe6\x97",
+        b"\xff",
+        b"\x80",
+        b"\xf0\x9f",
+    ];
+
+    /// A small, deterministic random number generator. (xorshift)
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn input(&mut self) -> Vec<u8> {
+            let mut input = vec![];
+            for _ in 0..self.below(12) {
+                if self.below(4) == 0 {
+                    input.push(self.next() as u8);
+                } else {
+                    input.extend_from_slice(PIECES[self.below(PIECES.len())]);
+                }
+            }
+            input
+        }
+    }
+
+    /// Feed `chunks` to a parser, saying there's more to come until the end. Returns what it parsed, and
+    /// what it's still waiting on.
+    fn feed<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> (Vec<Sequence>, Vec<u8>) {
+        let mut parser = Parser::default();
+        let mut seqs = vec![];
+        for chunk in chunks {
+            parser.advance(chunk, true);
+            seqs.extend(parser.by_ref());
+        }
+        parser.advance(&[], false);
+        seqs.extend(parser.by_ref());
+        (seqs, parser.pending)
+    }
+
+    /// Checks that every byte of `input` was used by exactly one sequence, or is still pending.
+    fn check_no_lost_bytes(input: &[u8], seqs: &[Sequence], pending: &[u8]) {
+        let mut start = 0;
+        let mut expected = vec![];
+        while start < input.len() {
+            let Some((seq, len)) = parse(&input[start..], false) else { break };
+            assert!(len > 0 && start + len <= input.len(), "bad length {len} at {start} of {input:?}");
+            expected.push(seq);
+            start += len;
+        }
+        assert_eq!(pending, &input[start..], "pending bytes of {input:?}");
+        assert_eq!(seqs, expected, "sequences of {input:?}");
+        assert!(!seqs.contains(&Sequence::Raw(vec![])), "empty raw sequence from {input:?}");
+    }
+
+    fn check(input: &[u8]) {
+        let (seqs, pending) = feed([input]);
+        check_no_lost_bytes(input, &seqs, &pending);
+
+        let (by_byte, by_byte_pending) = feed(input.chunks(1));
+        assert_eq!(by_byte, seqs, "byte by byte: {input:?}");
+        assert_eq!(by_byte_pending, pending, "byte by byte: {input:?}");
+
+        for split in 0..=input.len() {
+            let (head, tail) = input.split_at(split);
+            assert_eq!(feed([head, tail]), (seqs.clone(), pending.clone()), "split at {split}: {input:?}");
+        }
+    }
+
+    #[test]
+    fn every_piece() {
+        for piece in PIECES {
+            check(piece);
+        }
+        for a in PIECES {
+            for b in PIECES {
+                check(&[*a, *b].concat());
+            }
+        }
+    }
+
+    #[test]
+    fn random_input() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..2000 {
+            check(&rng.input());
+        }
+    }
+
+    #[test]
+    fn random_bytes() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..2000 {
+            let len = rng.below(24);
+            let input: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+            check(&input);
+        }
+    }
+
+    #[test]
+    fn truncated_input() {
+        let mut rng = Rng(0x0123_4567_89ab_cdef);
+        for _ in 0..200 {
+            let input = rng.input();
+            for end in 0..=input.len() {
+                check(&input[..end]);
+            }
+        }
+    }
+
+    #[test]
+    fn examples() {
+        let (seqs, pending) = feed([b"a\x1b[1;5C\x1b".as_slice()]);
+        assert_eq!(
+            seqs,
+            [
+                key(KeyCode::Char('a')),
+                Sequence::Key(KeyCode::Right, KeyModifiers::CONTROL),
+                key(KeyCode::Esc),
+            ]
+        );
+        assert!(pending.is_empty());
+
+        // Incomplete sequences wait for the rest:
+        let (seqs, pending) = feed([b"\x1b[1;".as_slice()]);
+        assert!(seqs.is_empty());
+        assert_eq!(pending, b"\x1b[1;");
+    }
+
+    #[test]
+    fn mouse() {
+        let (seqs, _) = feed([b"\x1b[<0;10;20M\x1b[<0;10;20m\x1b[<34;3;4M\x1b[<65;1;2M\x1b[<16;5;6M".as_slice()]);
+        assert_eq!(
+            seqs,
+            [
+                Sequence::Mouse(Mouse::Down(MouseButton::Left, 10, 20), KeyModifiers::empty()),
+                Sequence::Mouse(Mouse::Up(MouseButton::Left, 10, 20), KeyModifiers::empty()),
+                Sequence::Mouse(Mouse::Drag(MouseButton::Right, 3, 4), KeyModifiers::empty()),
+                Sequence::Mouse(Mouse::ScrollDown(1, 2), KeyModifiers::empty()),
+                Sequence::Mouse(Mouse::Down(MouseButton::Left, 5, 6), KeyModifiers::CONTROL),
+            ]
+        );
+
+        // X10: button, x and y are offset by 32. Releases don't say which button.
+        let (seqs, _) = feed([b"\x1b[M!*+\x1b[M#*+\x1b[M`!!".as_slice()]);
+        assert_eq!(
+            seqs,
+            [
+                Sequence::Mouse(Mouse::Down(MouseButton::Middle, 10, 11), KeyModifiers::empty()),
+                Sequence::Mouse(Mouse::Up(MouseButton::Any, 10, 11), KeyModifiers::empty()),
+                Sequence::Mouse(Mouse::ScrollUp(1, 1), KeyModifiers::empty()),
+            ]
+        );
+    }
+
+    #[test]
+    fn replies() {
+        let (seqs, _) = feed([b"\x1b[12;34R\x1b[R\x1b[?1;2c\x1b]11;rgb:0000/0000/0000\x07".as_slice()]);
+        assert_eq!(
+            seqs,
+            [
+                Sequence::CursorPosition(34, 12),
+                // Not a cursor position: that always has both numbers.
+                key(KeyCode::F(3)),
+                Sequence::Raw(b"\x1b[?1;2c".to_vec()),
+                Sequence::Raw(b"\x1b]11;rgb:0000/0000/0000\x07".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn ss3() {
+        let (seqs, _) = feed([b"\x1bOP\x1bO5Q\x1bO2A\x1bOM\x1bOx".as_slice()]);
+        assert_eq!(
+            seqs,
+            [
+                key(KeyCode::F(1)),
+                Sequence::Key(KeyCode::F(2), KeyModifiers::CONTROL),
+                Sequence::Key(KeyCode::Up, KeyModifiers::SHIFT),
+                key(KeyCode::Enter),
+                Sequence::Raw(b"\x1bOx".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn alt() {
+        let (seqs, _) = feed([b"\x1ba\x1b\x7f\x1b\x1b[A\x1b\x1bb".as_slice()]);
+        assert_eq!(
+            seqs,
+            [
+                Sequence::Key(KeyCode::Char('a'), KeyModifiers::ALT),
+                Sequence::Key(KeyCode::Backspace, KeyModifiers::ALT),
+                key(KeyCode::Esc),
+                key(KeyCode::Up),
+                key(KeyCode::Esc),
+                Sequence::Key(KeyCode::Char('b'), KeyModifiers::ALT),
+            ]
+        );
+
+        // A long run of ESCs is one Esc each, without recursing.
+        let mut parser = Parser::default();
+        parser.advance(&[0x1b; 100_000], false);
+        assert_eq!(parser.by_ref().count(), 100_000);
+    }
+
+    #[test]
+    fn split_utf8() {
+        let crab = "🦀".as_bytes();
+        let mut parser = Parser::default();
+        parser.advance(&crab[..1], true);
+        parser.advance(&crab[1..3], true);
+        assert_eq!(parser.next(), None);
+        parser.advance(&crab[3..], false);
+        assert_eq!(parser.next(), Some(key(KeyCode::Char('🦀'))));
+
+        // Unless what we have already can't be UTF-8:
+        let (seqs, pending) = feed([b"\xe6\x97".as_slice(), b"a"]);
+        assert_eq!(seqs, [Sequence::Raw(vec![0xe6]), Sequence::Raw(vec![0x97]), key(KeyCode::Char('a'))]);
+        assert!(pending.is_empty());
+    }
+}
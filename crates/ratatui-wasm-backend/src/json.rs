@@ -0,0 +1,269 @@
+//! Just enough JSON to talk to JavaScript and read/write asciicast files, without pulling in serde.
+
+use std::fmt::Write as _;
+
+/// Appends `value` to `out` as a quoted JSON string.
+pub(crate) fn push_str(out: &mut String, value: &str) {
+    out.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 || ch == '\u{7f}' => {
+                // Infallible: writing to a String.
+                let _ = write!(out, "\\u{:04x}", ch as u32);
+            }
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+/// A parsed JSON value.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a single JSON value. Returns a (short) description of the problem on failure.
+pub(crate) fn parse(input: &str) -> Result<Value, String> {
+    let mut parser = Parser { chars: input.char_indices().peekable(), input, depth: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.next() {
+        None => Ok(value),
+        Some((index, _)) => Err(format!("unexpected trailing data at {index}")),
+    }
+}
+
+/// How deeply arrays and objects may nest. Nothing we read comes close; the limit keeps hostile input from
+/// overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    input: &'a str,
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, ch)| ch.is_ascii_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some((_, ch)) if ch == expected => Ok(()),
+            Some((index, ch)) => Err(format!("expected '{expected}' at {index}, found '{ch}'")),
+            None => Err(format!("expected '{expected}', found end of input")),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        let Some(&(index, ch)) = self.chars.peek() else {
+            return Err("unexpected end of input".into());
+        };
+        if matches!(ch, '[' | '{') {
+            if self.depth == MAX_DEPTH {
+                return Err(format!("nested too deeply at {index}"));
+            }
+            self.depth += 1;
+            let value = self.container(index, ch);
+            self.depth -= 1;
+            return value;
+        }
+        match ch {
+            '"' => Ok(Value::String(self.string()?)),
+            't' => self.literal("true", Value::Bool(true)),
+            'f' => self.literal("false", Value::Bool(false)),
+            'n' => self.literal("null", Value::Null),
+            '-' | '0'..='9' => {
+                let mut end = index;
+                while let Some((i, ch)) = self.chars.next_if(|(_, ch)| "+-.eE".contains(*ch) || ch.is_ascii_digit()) {
+                    end = i + ch.len_utf8();
+                }
+                self.input[index..end]
+                    .parse()
+                    .map(Value::Number)
+                    .map_err(|_| format!("invalid number at {index}"))
+            }
+            ch => Err(format!("unexpected '{ch}' at {index}")),
+        }
+    }
+
+    fn container(&mut self, index: usize, open: char) -> Result<Value, String> {
+        match open {
+            '[' => {
+                self.chars.next();
+                let mut items = vec![];
+                self.skip_whitespace();
+                if self.chars.next_if(|(_, ch)| *ch == ']').is_some() {
+                    return Ok(Value::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.chars.next() {
+                        Some((_, ',')) => continue,
+                        Some((_, ']')) => return Ok(Value::Array(items)),
+                        _ => return Err(format!("unterminated array starting at {index}")),
+                    }
+                }
+            }
+            '{' => {
+                self.chars.next();
+                let mut entries = vec![];
+                self.skip_whitespace();
+                if self.chars.next_if(|(_, ch)| *ch == '}').is_some() {
+                    return Ok(Value::Object(entries));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    entries.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.chars.next() {
+                        Some((_, ',')) => continue,
+                        Some((_, '}')) => return Ok(Value::Object(entries)),
+                        _ => return Err(format!("unterminated object starting at {index}")),
+                    }
+                }
+            }
+            _ => unreachable!("container called with '{open}'"),
+        }
+    }
+
+    fn literal(&mut self, literal: &str, value: Value) -> Result<Value, String> {
+        for expected in literal.chars() {
+            match self.chars.next() {
+                Some((_, ch)) if ch == expected => {}
+                _ => return Err(format!("expected {literal}")),
+            }
+        }
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let start = match self.chars.next() {
+            Some((index, '"')) => index,
+            Some((index, ch)) => return Err(format!("expected string at {index}, found '{ch}'")),
+            None => return Err("expected string, found end of input".into()),
+        };
+
+        let mut out = String::new();
+        loop {
+            match self.chars.next() {
+                None => return Err(format!("unterminated string starting at {start}")),
+                Some((_, '"')) => return Ok(out),
+                Some((_, '\\')) => match self.chars.next().map(|(_, ch)| ch) {
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some('/') => out.push('/'),
+                    Some('b') => out.push('\u{8}'),
+                    Some('f') => out.push('\u{c}'),
+                    Some('n') => out.push('\n'),
+                    Some('r') => out.push('\r'),
+                    Some('t') => out.push('\t'),
+                    Some('u') => {
+                        let high = self.hex4()?;
+                        let code = if (0xd800..0xdc00).contains(&high) {
+                            // Surrogate pair:
+                            self.literal("\\u", Value::Null)?;
+                            let low = self.hex4()?;
+                            0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+                        } else {
+                            high
+                        };
+                        out.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    _ => return Err(format!("invalid escape in string starting at {start}")),
+                },
+                Some((_, ch)) => out.push(ch),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .chars
+                .next()
+                .and_then(|(_, ch)| ch.to_digit(16))
+                .ok_or("invalid \\u escape")?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let value = parse(r#" {"a": [1, -2.5e1, true, null], "b": "x\u00e9\ud83d\ude00"} "#).unwrap();
+        assert_eq!(
+            value.get("a"),
+            Some(&Value::Array(vec![
+                Value::Number(1.0),
+                Value::Number(-25.0),
+                Value::Bool(true),
+                Value::Null
+            ]))
+        );
+        assert_eq!(value.get("b").and_then(Value::as_str), Some("xé😀"));
+        assert!(parse("[1,").is_err());
+        assert!(parse("[1] 2").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut out = String::new();
+        push_str(&mut out, "a\"b\\c\n\u{1}\u{7f}");
+        assert_eq!(parse(&out), Ok(Value::String("a\"b\\c\n\u{1}\u{7f}".into())));
+    }
+
+    #[test]
+    fn depth() {
+        let nested = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(parse(&nested(MAX_DEPTH + 1)), Err(format!("nested too deeply at {MAX_DEPTH}")));
+        assert!(parse(&"[{\"a\":".repeat(100_000)).is_err());
+    }
+}
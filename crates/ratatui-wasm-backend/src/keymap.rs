@@ -0,0 +1,581 @@
+//! Declarative key bindings, instead of a big `match` on input.
+//!
+//! Bindings use Vim-like syntax. Keys are separated by spaces (or not: `"gg"` is two keys), and special keys or
+//! combos go in angle brackets:
+//!
+//! ```ignore
+//! let mut keymap = Keymap::new();
+//! keymap.bind("<C-x> <C-s>", Action::Save)?;
+//! keymap.bind("<A-d>", Action::ToggleDebug)?;
+//! keymap.bind("<F1>", Action::Help)?;
+//! keymap.bind("gg", Action::Top)?;
+//!
+//! for seq in parser.by_ref() {
+//!     for matched in keymap.feed(&seq, clock.now()) {
+//!         match matched {
+//!             Matched::Action(action) => app.run(action),
+//!             Matched::Pending => {} // Waiting for the rest of a chord.
+//!             Matched::Unmatched(keys) if keys.is_empty() => app.handle(&seq),
+//!             Matched::Unmatched(keys) => app.type_keys(&keys),
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Modifiers are `C-` (Ctrl), `A-` (Alt), `M-` (Meta), and `S-` (Shift). Named keys are `Space`, `Tab`, `CR` (or
+//! `Enter`), `Esc`, `BS` (or `Backspace`), `Del`, `Ins`, `Home`, `End`, `PageUp`, `PageDown`, `Up`, `Down`,
+//! `Left`, `Right`, `F1`…`F12`, and `lt`/`gt` for `<` and `>`.
+//!
+//! Terminals send Ctrl-$key as control characters (see [ctrl](crate::ctrl)), so some bindings are the same key:
+//! `<C-i>` is `<Tab>`, `<C-m>` is `<CR>`, and `<C-[>` is `<Esc>`.
+
+use std::{error::Error, fmt::Display, str::FromStr, time::Duration};
+
+use crate::{
+    ctrl::{Ctrl, Erase},
+    input::{KeyCode, KeyModifiers, Sequence},
+};
+
+/// A key press, normalized so that the same key always compares equal, however it was written or parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let without_ctrl = modifiers - KeyModifiers::CONTROL;
+        let (code, modifiers) = match code {
+            KeyCode::Null => (KeyCode::Char('@'), modifiers | KeyModifiers::CONTROL),
+            KeyCode::Char('\t') => (KeyCode::Tab, modifiers),
+            KeyCode::Char('\r' | '\n') => (KeyCode::Enter, modifiers),
+            KeyCode::Char('\x1b') => (KeyCode::Esc, modifiers),
+            KeyCode::Char(ch) if (ch as u32) < 0x20 => {
+                let key = Ctrl::from_byte(ch as u8).map(|it| it.key()).unwrap_or(ch);
+                (KeyCode::Char(key.to_ascii_lowercase()), modifiers | KeyModifiers::CONTROL)
+            }
+            KeyCode::Char(ch) if modifiers.contains(KeyModifiers::CONTROL) => match Ctrl::from_key(ch) {
+                Some(Ctrl::I) => (KeyCode::Tab, without_ctrl),
+                Some(Ctrl::J | Ctrl::M) => (KeyCode::Enter, without_ctrl),
+                Some(Ctrl::LeftBracket) => (KeyCode::Esc, without_ctrl),
+                Some(ctrl) => (KeyCode::Char(ctrl.key().to_ascii_lowercase()), modifiers),
+                None => (code, modifiers),
+            },
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => (KeyCode::BackTab, modifiers),
+            _ => (code, modifiers),
+        };
+
+        // Shift is already part of the character. (ex: 'A')
+        let modifiers = match code {
+            KeyCode::Char(_) | KeyCode::BackTab => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        Self { code, modifiers }
+    }
+
+    /// The key for an input sequence, if it is one.
+    ///
+    /// `erase` is the terminal's erase character, so that we know whether 0x08 is Ctrl-H or Backspace.
+    pub fn from_sequence(seq: &Sequence, erase: Erase) -> Option<Self> {
+        let Sequence::Key(code, modifiers) = seq else { return None };
+        if erase.is_backspace(code) {
+            return Some(Self::new(KeyCode::Backspace, *modifiers));
+        }
+        Some(Self::new(*code, *modifiers))
+    }
+
+    /// The bytes a terminal sends for this key, in the usual xterm encoding. (ex: to script input for tests)
+    ///
+    /// `erase` is what the Backspace key sends. Combos that have no traditional encoding (ex: `<C-CR>`) use
+    /// the "fixterms" encoding, `CSI codepoint ; modifiers u`, which the [Parser](crate::input::Parser)
+    /// understands. Keys that terminals can't send at all (ex: F21) are empty.
+    pub fn to_bytes(&self, erase: Erase) -> Vec<u8> {
+        let key = Key::new(self.code, self.modifiers);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        let others = key.modifiers - KeyModifiers::ALT;
+
+        // Keys that are sent as-is, or with an ESC prefix for Alt:
+        let plain: Option<Vec<u8>> = match key.code {
+            KeyCode::Char(ch) if others.is_empty() => Some(ch.to_string().into_bytes()),
+            KeyCode::Char(ch) if others == KeyModifiers::CONTROL => Ctrl::from_key(ch).map(|ctrl| vec![ctrl.byte()]),
+            KeyCode::Enter if others.is_empty() => Some(b"\r".to_vec()),
+            KeyCode::Tab if others.is_empty() => Some(b"\t".to_vec()),
+            // ESC then another ESC is parsed as two Escs. So with Alt, these use a modifier parameter instead.
+            KeyCode::Esc if key.modifiers.is_empty() => Some(b"\x1b".to_vec()),
+            KeyCode::Backspace if others.is_empty() => Some(vec![erase.byte()]),
+            KeyCode::BackTab if key.modifiers.is_empty() => Some(b"\x1b[Z".to_vec()),
+            _ => None,
+        };
+        if let Some(bytes) = plain {
+            return if alt { [b"\x1b".as_slice(), &bytes].concat() } else { bytes };
+        }
+
+        // 1, plus a bit for each modifier:
+        let param = 1 + [KeyModifiers::SHIFT, KeyModifiers::ALT, KeyModifiers::CONTROL, KeyModifiers::META]
+            .iter()
+            .enumerate()
+            .filter(|(_, modifier)| key.modifiers.contains(**modifier))
+            .map(|(bit, _)| 1 << bit)
+            .sum::<u8>();
+        let letter = |letter: char| match param {
+            1 => format!("\x1b[{letter}"),
+            _ => format!("\x1b[1;{param}{letter}"),
+        };
+        let tilde = |code: u8| match param {
+            1 => format!("\x1b[{code}~"),
+            _ => format!("\x1b[{code};{param}~"),
+        };
+        let codepoint = |codepoint: u32| format!("\x1b[{codepoint};{param}u");
+        let seq = match key.code {
+            KeyCode::Up => letter('A'),
+            KeyCode::Down => letter('B'),
+            KeyCode::Right => letter('C'),
+            KeyCode::Left => letter('D'),
+            KeyCode::Home => letter('H'),
+            KeyCode::End => letter('F'),
+            KeyCode::BackTab => letter('Z'),
+            KeyCode::Insert => tilde(2),
+            KeyCode::Delete => tilde(3),
+            KeyCode::PageUp => tilde(5),
+            KeyCode::PageDown => tilde(6),
+            KeyCode::F(n @ 1..=4) if param == 1 => format!("\x1bO{}", (b'P' + n - 1) as char),
+            // CSI 1 ; modifiers R is also a cursor position report. Use the VT220 code instead.
+            KeyCode::F(3) => tilde(13),
+            KeyCode::F(n @ 1..=4) => letter((b'P' + n - 1) as char),
+            KeyCode::F(n) => match n {
+                5 => tilde(15),
+                6..=10 => tilde(n + 11),
+                11..=14 => tilde(n + 12),
+                15..=16 => tilde(n + 13),
+                17..=20 => tilde(n + 14),
+                _ => String::new(),
+            },
+            KeyCode::Char(ch) => codepoint(ch as u32),
+            KeyCode::Enter => codepoint(13),
+            KeyCode::Tab => codepoint(9),
+            KeyCode::Esc => codepoint(27),
+            KeyCode::Backspace => codepoint(127),
+            // Key::new() turns this into C-@.
+            KeyCode::Null => String::new(),
+        };
+        seq.into_bytes()
+    }
+}
+
+/// Named keys. When more than one name has the same key, we render the first.
+const NAMES: [(&str, KeyCode); 19] = [
+    ("Space", KeyCode::Char(' ')),
+    ("lt", KeyCode::Char('<')),
+    ("gt", KeyCode::Char('>')),
+    ("Tab", KeyCode::Tab),
+    ("CR", KeyCode::Enter),
+    ("Enter", KeyCode::Enter),
+    ("Esc", KeyCode::Esc),
+    ("BS", KeyCode::Backspace),
+    ("Backspace", KeyCode::Backspace),
+    ("Del", KeyCode::Delete),
+    ("Ins", KeyCode::Insert),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+];
+
+const MODIFIERS: [(KeyModifiers, char); 4] = [
+    (KeyModifiers::CONTROL, 'C'),
+    (KeyModifiers::ALT, 'A'),
+    (KeyModifiers::META, 'M'),
+    (KeyModifiers::SHIFT, 'S'),
+];
+
+impl Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let KeyCode::Char(ch) = self.code
+            && self.modifiers.is_empty()
+            && ch != ' '
+            && ch != '<'
+        {
+            return write!(f, "{ch}");
+        }
+
+        let mut modifiers = self.modifiers;
+        let name = match self.code {
+            KeyCode::BackTab => {
+                modifiers |= KeyModifiers::SHIFT;
+                "Tab".to_string()
+            }
+            KeyCode::Char('@') if modifiers.contains(KeyModifiers::CONTROL) => "Space".to_string(),
+            KeyCode::F(n) => format!("F{n}"),
+            code => match NAMES.iter().find(|(_, it)| *it == code) {
+                Some((name, _)) => name.to_string(),
+                None => match code {
+                    KeyCode::Char(ch) => ch.to_string(),
+                    _ => format!("{code:?}"),
+                },
+            },
+        };
+
+        write!(f, "<")?;
+        for (modifier, prefix) in MODIFIERS {
+            if modifiers.contains(modifier) {
+                write!(f, "{prefix}-")?;
+            }
+        }
+        write!(f, "{name}>")
+    }
+}
+
+impl FromStr for Key {
+    type Err = KeyParseError;
+
+    /// Parse exactly one key.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse_keys(s)?.as_slice() {
+            [key] => Ok(*key),
+            _ => Err(KeyParseError {
+                binding: s.to_string(),
+                offset: 0,
+                message: "expected exactly one key".into(),
+            }),
+        }
+    }
+}
+
+/// Parse a binding, like `"<C-x> <C-s>"`, into its keys.
+pub fn parse_keys(binding: &str) -> Result<Vec<Key>, KeyParseError> {
+    let err = |offset: usize, message: String| KeyParseError {
+        binding: binding.to_string(),
+        offset,
+        message,
+    };
+
+    let mut keys = vec![];
+    let mut offset = 0;
+    while let Some(ch) = binding[offset..].chars().next() {
+        if ch.is_whitespace() {
+            offset += ch.len_utf8();
+            continue;
+        }
+        if ch != '<' {
+            keys.push(Key::new(KeyCode::Char(ch), KeyModifiers::empty()));
+            offset += ch.len_utf8();
+            continue;
+        }
+
+        let start = offset + 1;
+        let Some(len) = binding[start..].find('>') else {
+            return Err(err(offset, "expected '>' after '<'. (Use <lt> for '<'.)".into()));
+        };
+        let key = parse_bracketed(&binding[start..start + len]).map_err(|message| err(offset, message))?;
+        keys.push(key);
+        offset = start + len + 1;
+    }
+
+    if keys.is_empty() {
+        return Err(err(0, "no keys".into()));
+    }
+    Ok(keys)
+}
+
+/// Parse what's inside `<...>`.
+fn parse_bracketed(inner: &str) -> Result<Key, String> {
+    let mut modifiers = KeyModifiers::empty();
+    let mut name = inner;
+    while let [prefix, b'-', _, ..] = name.as_bytes() {
+        let Some((modifier, _)) = MODIFIERS.iter().find(|(_, it)| it.eq_ignore_ascii_case(&(*prefix as char))) else {
+            return Err(format!("unknown modifier in <{inner}>"));
+        };
+        modifiers |= *modifier;
+        name = &name[2..];
+    }
+
+    let mut chars = name.chars();
+    let code = match (chars.next(), chars.next()) {
+        (None, _) => return Err("missing key name".into()),
+        (Some(ch), None) if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::Char(ch.to_ascii_uppercase()),
+        (Some(ch), None) => KeyCode::Char(ch),
+        _ => {
+            let named = NAMES.iter().find(|(it, _)| it.eq_ignore_ascii_case(name)).map(|(_, code)| *code);
+            let function = name
+                .strip_prefix(['F', 'f'])
+                .and_then(|it| it.parse::<u8>().ok())
+                .filter(|it| *it > 0)
+                .map(KeyCode::F);
+            named.or(function).ok_or_else(|| format!("unknown key <{inner}>"))?
+        }
+    };
+    Ok(Key::new(code, modifiers))
+}
+
+/// Render keys as a binding string. (ex: for a help footer)
+pub fn keys_to_string(keys: &[Key]) -> String {
+    keys.iter().map(|it| it.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyParseError {
+    pub binding: String,
+
+    /// Byte offset of the key we couldn't parse.
+    pub offset: usize,
+    pub message: String,
+}
+
+impl Display for KeyParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "key binding {:?}, at byte {}: {}", self.binding, self.offset, self.message)
+    }
+}
+
+impl Error for KeyParseError {}
+
+/// What a key press meant, given the bindings in a [Keymap].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Matched<A> {
+    /// The keys so far completed a binding.
+    Action(A),
+
+    /// The keys so far are the start of a longer binding. Wait for more, or for [Keymap::expire].
+    Pending,
+
+    /// These keys don't match any binding. (Empty if the input wasn't a key press.)
+    Unmatched(Vec<Key>),
+}
+
+/// Maps keys (and multi-key chords) to actions of your choosing.
+pub struct Keymap<A> {
+    bindings: Vec<(Vec<Key>, A)>,
+
+    /// Keys typed so far in a chord.
+    pending: Vec<Key>,
+    last_key_at: Duration,
+    timeout: Duration,
+    erase: Erase,
+}
+
+impl<A: Clone> Default for Keymap<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Clone> Keymap<A> {
+    pub fn new() -> Self {
+        Self {
+            bindings: vec![],
+            pending: vec![],
+            last_key_at: Duration::ZERO,
+            timeout: Duration::from_secs(1),
+            erase: Erase::default(),
+        }
+    }
+
+    /// Bind keys to an action, replacing any existing binding for the same keys.
+    pub fn bind(&mut self, binding: &str, action: A) -> Result<(), KeyParseError> {
+        let keys = parse_keys(binding)?;
+        self.bindings.retain(|(it, _)| *it != keys);
+        self.bindings.push((keys, action));
+        Ok(())
+    }
+
+    /// How long to wait for the next key in a chord. (Default: 1 second)
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Set the terminal's erase character, so that we can tell Backspace from Ctrl-H.
+    pub fn set_erase(&mut self, erase: Erase) {
+        self.erase = erase;
+    }
+
+    /// Handle the next input sequence. `now` is the time from a monotonic [Clock](crate::host::Clock).
+    ///
+    /// Usually returns one result. When a key breaks a chord, the keys before it come out first, as the longest
+    /// binding they start with (or unmatched), and the keys after that are tried again from the start. (ex: with
+    /// `<C-x> <C-s>` and `a` bound, `<C-x> a` is `[Unmatched([<C-x>]), Action(a)]`) [Matched::Pending] is always last.
+    ///
+    /// Sequences that aren't key presses (ex: mouse events) are always unmatched, and don't interrupt a chord.
+    pub fn feed(&mut self, seq: &Sequence, now: Duration) -> Vec<Matched<A>> {
+        let Some(key) = Key::from_sequence(seq, self.erase) else {
+            return vec![Matched::Unmatched(vec![])];
+        };
+        // Too slow. Finish the old chord before starting a new one.
+        let mut results = self.expire(now);
+        self.last_key_at = now;
+        self.push(key, &mut results);
+        if !self.pending.is_empty() {
+            results.push(Matched::Pending);
+        }
+        results
+    }
+
+    /// When a pending chord times out, if there is one. Call [Keymap::expire] then.
+    pub fn deadline(&self) -> Option<Duration> {
+        (!self.pending.is_empty()).then(|| self.last_key_at + self.timeout)
+    }
+
+    /// Give up waiting for the rest of a chord, if it has timed out. Empty if it hasn't.
+    ///
+    /// If the keys so far are a binding on their own (ex: `<C-x>`, when `<C-x> <C-s>` is also bound), returns its
+    /// action.
+    pub fn expire(&mut self, now: Duration) -> Vec<Matched<A>> {
+        let mut results = vec![];
+        // Keys after the first binding are tried again, and may be pending again:
+        while self.deadline().is_some_and(|it| now >= it) {
+            self.resolve(&mut results);
+        }
+        results
+    }
+
+    fn push(&mut self, key: Key, results: &mut Vec<Matched<A>>) {
+        self.pending.push(key);
+        let pending = &self.pending;
+        let longer = self.bindings.iter().any(|(keys, _)| keys.len() > pending.len() && keys.starts_with(pending));
+        if !longer {
+            self.resolve(results);
+        }
+    }
+
+    /// Resolve the pending keys, without waiting for more. Then try the leftover keys again.
+    fn resolve(&mut self, results: &mut Vec<Matched<A>>) {
+        let keys = std::mem::take(&mut self.pending);
+        let bound = (1..=keys.len()).rev().find_map(|len| {
+            let (_, action) = self.bindings.iter().find(|(it, _)| *it == keys[..len])?;
+            Some((len, action.clone()))
+        });
+        let rest = match bound {
+            Some((len, action)) => {
+                results.push(Matched::Action(action));
+                &keys[len..]
+            }
+            None => {
+                match results.last_mut() {
+                    Some(Matched::Unmatched(unmatched)) if !unmatched.is_empty() => unmatched.push(keys[0]),
+                    _ => results.push(Matched::Unmatched(vec![keys[0]])),
+                }
+                &keys[1..]
+            }
+        };
+        for key in rest {
+            self.push(*key, results);
+        }
+    }
+
+    /// The keys bound to an action, rendered for display. (ex: "<C-x> <C-s>")
+    pub fn keys_for(&self, action: &A) -> Option<String>
+    where
+        A: PartialEq,
+    {
+        let (keys, _) = self.bindings.iter().find(|(_, it)| it == action)?;
+        Some(keys_to_string(keys))
+    }
+
+    /// Every binding, in the order they were bound.
+    pub fn bindings(&self) -> impl Iterator<Item = (&[Key], &A)> {
+        self.bindings.iter().map(|(keys, action)| (keys.as_slice(), action))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Matched::{Action, Pending, Unmatched};
+
+    fn keymap(bindings: &[&'static str]) -> Keymap<&'static str> {
+        let mut keymap = Keymap::new();
+        for binding in bindings {
+            keymap.bind(binding, *binding).unwrap();
+        }
+        keymap
+    }
+
+    /// Feed each key in `binding`, at `now`, and collect the results.
+    fn feed(keymap: &mut Keymap<&'static str>, binding: &str, now: Duration) -> Vec<Matched<&'static str>> {
+        let keys = parse_keys(binding).unwrap();
+        keys.iter().flat_map(|key| keymap.feed(&Sequence::Key(key.code, key.modifiers), now)).collect()
+    }
+
+    fn keys(binding: &str) -> Vec<Key> {
+        parse_keys(binding).unwrap()
+    }
+
+    #[test]
+    fn chords() {
+        let mut keymap = keymap(&["<C-x> <C-s>", "a"]);
+        assert_eq!(feed(&mut keymap, "<C-x>", Duration::ZERO), [Pending]);
+        assert_eq!(feed(&mut keymap, "<C-s>", Duration::ZERO), [Action("<C-x> <C-s>")]);
+        assert_eq!(feed(&mut keymap, "ab", Duration::ZERO), [Action("a"), Unmatched(keys("b"))]);
+        assert_eq!(keymap.deadline(), None);
+    }
+
+    #[test]
+    fn broken_chord_retries_the_rest() {
+        let mut keymap = keymap(&["<C-x> <C-s>", "a", "gg"]);
+        let now = Duration::ZERO;
+        assert_eq!(feed(&mut keymap, "<C-x> a", now), [Pending, Unmatched(keys("<C-x>")), Action("a")]);
+        // The retried key can start a chord of its own:
+        keymap.feed(&Sequence::Key(KeyCode::Char('x'), KeyModifiers::CONTROL), now);
+        assert_eq!(feed(&mut keymap, "g", now), [Unmatched(keys("<C-x>")), Pending]);
+        assert_eq!(feed(&mut keymap, "g", now), [Action("gg")]);
+        // Nothing bound at all:
+        assert_eq!(feed(&mut keymap, "<C-x> b", now), [Pending, Unmatched(keys("<C-x> b"))]);
+    }
+
+    #[test]
+    fn broken_chord_uses_the_longest_binding() {
+        let mut keymap = keymap(&["a", "abc", "bd"]);
+        let now = Duration::ZERO;
+        assert_eq!(feed(&mut keymap, "ab", now), [Pending, Pending]);
+        assert_eq!(feed(&mut keymap, "x", now), [Action("a"), Unmatched(keys("bx"))]);
+        assert_eq!(feed(&mut keymap, "abd", now), [Pending, Pending, Action("a"), Action("bd")]);
+    }
+
+    #[test]
+    fn non_keys_dont_interrupt() {
+        let mut keymap = keymap(&["<C-x> <C-s>"]);
+        assert_eq!(feed(&mut keymap, "<C-x>", Duration::ZERO), [Pending]);
+        assert_eq!(keymap.feed(&Sequence::Raw(b"\x1b[?1u".to_vec()), Duration::ZERO), [Unmatched(vec![])]);
+        assert_eq!(feed(&mut keymap, "<C-s>", Duration::ZERO), [Action("<C-x> <C-s>")]);
+    }
+
+    #[test]
+    fn expire() {
+        let second = Duration::from_secs(1);
+        let mut keymap = keymap(&["<C-x>", "<C-x> <C-s>", "a"]);
+        assert_eq!(keymap.expire(second), []);
+        assert_eq!(feed(&mut keymap, "<C-x>", second), [Pending]);
+        assert_eq!(keymap.deadline(), Some(2 * second));
+        assert_eq!(keymap.expire(second * 3 / 2), []);
+        assert_eq!(keymap.expire(2 * second), [Action("<C-x>")]);
+        assert_eq!(keymap.deadline(), None);
+        assert_eq!(keymap.expire(3 * second), []);
+
+        // Unbound, and not expired yet:
+        keymap.set_timeout(second / 2);
+        assert_eq!(feed(&mut keymap, "<C-x>", 3 * second), [Pending]);
+        assert_eq!(feed(&mut keymap, "<C-s>", 3 * second + second / 4), [Action("<C-x> <C-s>")]);
+    }
+
+    #[test]
+    fn expire_retries_the_rest() {
+        let second = Duration::from_secs(1);
+        let mut keymap = keymap(&["a", "abc", "bd"]);
+        assert_eq!(feed(&mut keymap, "ab", Duration::ZERO), [Pending, Pending]);
+        // "b" is the start of "bd" again, but it's just as late:
+        assert_eq!(keymap.expire(second), [Action("a"), Unmatched(keys("b"))]);
+        assert_eq!(keymap.deadline(), None);
+    }
+
+    #[test]
+    fn late_keys_finish_the_old_chord() {
+        let second = Duration::from_secs(1);
+        let mut keymap = keymap(&["<C-x>", "<C-x> <C-s>"]);
+        assert_eq!(feed(&mut keymap, "<C-x>", Duration::ZERO), [Pending]);
+        assert_eq!(feed(&mut keymap, "<C-s>", 2 * second), [Action("<C-x>"), Unmatched(keys("<C-s>"))]);
+    }
+}
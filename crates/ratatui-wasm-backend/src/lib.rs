@@ -5,11 +5,55 @@
 //! 
 
 pub mod backend;
+pub mod error;
+pub mod frame;
+pub mod glyphs;
+pub mod host;
+pub mod html;
+pub mod image;
+pub mod palette;
+pub mod play;
+pub mod record;
+pub mod remote;
+pub mod sgr;
+pub mod snapshot;
+pub mod terminfo;
 pub mod types;
+pub mod vt;
+pub mod width;
+
+mod json;
+
+#[cfg(feature = "parser")]
+pub mod app;
 
 #[cfg(feature = "parser")]
 pub mod ctrl;
 
+#[cfg(feature = "crossterm-compat")]
+pub mod crossterm_compat;
+
+#[cfg(feature = "parser")]
+pub mod input;
+
+#[cfg(feature = "parser")]
+pub mod keymap;
+
+#[cfg(feature = "parser")]
+pub mod mux;
+
+#[cfg(feature = "parser")]
+pub mod run;
+
+#[cfg(feature = "parser")]
+pub mod testing;
+
+#[cfg(feature = "log")]
+pub mod logging;
+
+#[cfg(feature = "wasi")]
+pub mod wasi;
+
 pub use anes;
 pub use ratatui;
 
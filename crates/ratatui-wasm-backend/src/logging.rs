@@ -0,0 +1,368 @@
+//! A [log] implementation which doesn't scribble over your TUI.
+//!
+//! If you log with `console.log` (or to stderr) while the alternate screen is active, the output lands in the
+//! middle of your UI. Instead, [init] a logger which holds messages in memory while
+//! [AnsiBackend::exclusive](crate::backend::AnsiBackend::exclusive) is in effect. When you call
+//! [AnsiBackend::normal](crate::backend::AnsiBackend::normal), the backend prints them to the normal screen.
+//!
+//! Each backend holds its own messages. If you have more than one (ex: in a [SessionManager](crate::mux)), log
+//! within a [scope] to say which one a message is for. Messages logged outside of any scope go to every backend
+//! in exclusive mode. Messages logged while none is (ex: by an inline app) are held until one is, or until you
+//! [take_held] them.
+//!
+//! ```ignore
+//! logging::init(LogOptions::default())?;
+//! log::warn!("Couldn't save {file_name}");
+//! ```
+//!
+//! Or, give it a `sink` to write messages somewhere else. (ex: a [JsWriter](crate::types::JsWriter) that appends
+//! to a file.)
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, VecDeque},
+    io::Write,
+    mem,
+};
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+pub struct LogOptions {
+    pub level: LevelFilter,
+
+    /// Where to write messages. If `None`, messages are printed to the terminal by the backend, once it's not in
+    /// exclusive mode.
+    pub sink: Option<Box<dyn Write>>,
+
+    /// Hold messages for `sink` while the alternate screen is active. Set this if the sink writes to the same
+    /// terminal. (ex: stderr)
+    pub hold_while_exclusive: bool,
+
+    /// The most messages to hold onto. Older messages are dropped first.
+    pub max_held: usize,
+}
+
+impl Default for LogOptions {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::Info,
+            sink: None,
+            hold_while_exclusive: false,
+            max_held: 1000,
+        }
+    }
+}
+
+/// Install the logger. Fails if some other logger has already been installed.
+pub fn init(options: LogOptions) -> Result<(), SetLoggerError> {
+    let LogOptions { level, sink, hold_while_exclusive, max_held } = options;
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(level);
+    STATE.with_borrow_mut(|state| {
+        // Keep anything already held for backends in exclusive mode.
+        state.sink = sink;
+        state.hold_while_exclusive = hold_while_exclusive;
+        state.max_held = max_held;
+    });
+    Ok(())
+}
+
+static LOGGER: Logger = Logger;
+
+/// Install the logger for the current test's thread, with fresh state. ([init] only works once per process.)
+#[cfg(all(test, feature = "parser"))]
+pub(crate) fn setup(sink: Option<Box<dyn Write>>, hold_while_exclusive: bool) {
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(LevelFilter::Info);
+    STATE.with_borrow_mut(|state| {
+        *state = State {
+            sink,
+            hold_while_exclusive,
+            max_held: 1000,
+            ..State::default()
+        }
+    });
+}
+
+struct Logger;
+
+// WASM is single-threaded, so keep state per-thread. That also avoids requiring the sink to be `Send`.
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+/// Identifies a backend in exclusive mode, which holds its own log messages.
+/// (See [AnsiBackend::log_screen](crate::backend::AnsiBackend::log_screen))
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ScreenId(u64);
+
+#[derive(Default)]
+struct State {
+    sink: Option<Box<dyn Write>>,
+    hold_while_exclusive: bool,
+    max_held: usize,
+
+    /// Messages held for each backend in exclusive mode.
+    screens: BTreeMap<ScreenId, Held>,
+
+    /// Messages logged while no backend was in exclusive mode, with no sink to write them to.
+    unclaimed: Held,
+    next_id: u64,
+
+    /// The backend that messages are being logged for. (See [scope])
+    current: Option<ScreenId>,
+}
+
+#[derive(Default)]
+struct Held {
+    lines: VecDeque<String>,
+    dropped: usize,
+}
+
+impl Held {
+    fn hold(&mut self, line: String, max_held: usize) {
+        if max_held == 0 {
+            self.dropped += 1;
+            return;
+        }
+        if self.lines.len() >= max_held {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+        self.lines.push_back(line);
+    }
+
+    fn append(&mut self, other: Held, max_held: usize) {
+        self.dropped += other.dropped;
+        for line in other.lines {
+            self.hold(line, max_held);
+        }
+    }
+
+    fn take(&mut self) -> String {
+        let mut out = String::new();
+        if self.dropped > 0 {
+            out.push_str(&format!("({} older log messages were dropped)\n", self.dropped));
+            self.dropped = 0;
+        }
+        for line in self.lines.drain(..) {
+            out.push_str(&line);
+        }
+        out
+    }
+}
+
+impl State {
+    fn log(&mut self, line: String) {
+        let targets: Vec<ScreenId> = match self.current {
+            Some(current) if self.screens.contains_key(&current) => vec![current],
+            // That backend isn't in exclusive mode. Don't hold its messages for some other one.
+            Some(_) => vec![],
+            None => self.screens.keys().copied().collect(),
+        };
+
+        if let Some(sink) = &mut self.sink
+            && (targets.is_empty() || !self.hold_while_exclusive)
+        {
+            let _ = sink.write_all(line.as_bytes());
+            return;
+        }
+        let max_held = self.max_held;
+        match targets.as_slice() {
+            [] => self.unclaimed.hold(line, max_held),
+            targets => {
+                for target in targets {
+                    if let Some(held) = self.screens.get_mut(target) {
+                        held.hold(line.clone(), max_held);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!("[{} {}] {}\n", record.level(), record.target(), record.args());
+
+        // Ignore messages logged while we're already busy. (ex: by the sink itself)
+        let _ = STATE.try_with(|state| {
+            let Ok(mut state) = state.try_borrow_mut() else { return };
+            state.log(line);
+        });
+    }
+
+    fn flush(&self) {
+        let _ = STATE.try_with(|state| {
+            if let Ok(mut state) = state.try_borrow_mut()
+                && let Some(sink) = &mut state.sink
+            {
+                let _ = sink.flush();
+            }
+        });
+    }
+}
+
+/// Run `f`, and hold anything it logs for one backend only. (ex: while handling one session's input)
+pub fn scope<R>(screen: Option<ScreenId>, f: impl FnOnce() -> R) -> R {
+    let previous = STATE.with_borrow_mut(|state| mem::replace(&mut state.current, screen));
+    let result = f();
+    STATE.with_borrow_mut(|state| state.current = previous);
+    result
+}
+
+/// Messages that no backend is going to print: logged while none was in exclusive mode, and with no sink to
+/// write them to. (ex: print them yourself, when an inline app exits)
+pub fn take_held() -> String {
+    STATE.with_borrow_mut(|state| state.unclaimed.take())
+}
+
+/// Called by a backend when it enters the alternate screen. Messages start being held for it.
+pub(crate) fn enter_exclusive() -> ScreenId {
+    STATE.with_borrow_mut(|state| {
+        let id = ScreenId(state.next_id);
+        state.next_id += 1;
+        // Messages logged before now had nowhere to go. This backend can show them when it's done.
+        let held = mem::take(&mut state.unclaimed);
+        let max_held = state.max_held;
+        state.screens.entry(id).or_default().append(held, max_held);
+        id
+    })
+}
+
+/// Called by a backend when it leaves the alternate screen.
+///
+/// Returns the messages held for it, which it should print to the terminal itself.
+pub(crate) fn leave_exclusive(screen: ScreenId) -> String {
+    STATE.with_borrow_mut(|state| {
+        let Some(mut held) = state.screens.remove(&screen) else { return String::new() };
+        let text = held.take();
+        let Some(sink) = &mut state.sink else { return text };
+        // Nowhere to report a failing sink. Dropping messages is the best we can do.
+        let _ = sink.write_all(text.as_bytes());
+        let _ = sink.flush();
+        String::new()
+    })
+}
+
+#[cfg(all(test, feature = "parser"))]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use ratatui::{backend::Backend as _, layout::Size};
+
+    use super::*;
+    use crate::testing::TestHost;
+
+    fn output(host: &mut TestHost) -> String {
+        String::from_utf8(host.take_output()).unwrap()
+    }
+
+    #[derive(Clone, Default)]
+    struct Sink(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn held_until_normal() {
+        crate::logging::setup(None, false);
+        let mut host = TestHost::new(Size::new(20, 5));
+        let mut backend = host.backend();
+        backend.exclusive().unwrap();
+        log::info!("hello");
+        backend.flush().unwrap();
+        assert!(!output(&mut host).contains("hello"));
+
+        backend.normal().unwrap();
+        backend.flush().unwrap();
+        assert!(output(&mut host).contains("[INFO ratatui_wasm_backend::logging::tests] hello\r\n"));
+        assert_eq!(take_held(), "");
+    }
+
+    #[test]
+    fn inline_backends_dont_print() {
+        crate::logging::setup(None, false);
+        let mut host = TestHost::new(Size::new(20, 5));
+        let mut backend = host.backend();
+        log::info!("hello");
+        backend.flush().unwrap();
+        assert_eq!(output(&mut host), "");
+        assert!(take_held().contains("hello"));
+    }
+
+    #[test]
+    fn each_backend_holds_its_own() {
+        crate::logging::setup(None, false);
+        let mut hosts = [TestHost::new(Size::new(20, 5)), TestHost::new(Size::new(20, 5))];
+        let mut backends = hosts.each_ref().map(TestHost::backend);
+        for backend in &mut backends {
+            backend.exclusive().unwrap();
+        }
+        scope(backends[0].log_screen(), || log::info!("only a"));
+        scope(backends[1].log_screen(), || log::info!("only b"));
+        log::info!("everyone");
+
+        backends[0].normal().unwrap();
+        backends[0].flush().unwrap();
+        let a = output(&mut hosts[0]);
+        assert!(a.contains("only a") && a.contains("everyone") && !a.contains("only b"), "{a:?}");
+
+        // The other one still holds messages:
+        log::info!("later");
+        backends[1].flush().unwrap();
+        assert!(!output(&mut hosts[1]).contains("only"));
+        backends[1].normal().unwrap();
+        backends[1].flush().unwrap();
+        let b = output(&mut hosts[1]);
+        assert!(b.contains("only b") && b.contains("everyone") && b.contains("later") && !b.contains("only a"), "{b:?}");
+        assert!(!output(&mut hosts[0]).contains("later"));
+    }
+
+    #[test]
+    fn sink() {
+        let sink = Sink::default();
+        crate::logging::setup(Some(Box::new(sink.clone())), true);
+        let sink_text = || String::from_utf8(mem::take(&mut *sink.0.borrow_mut())).unwrap();
+        let mut host = TestHost::new(Size::new(20, 5));
+        let mut backend = host.backend();
+
+        log::info!("before");
+        assert!(sink_text().contains("before"));
+
+        backend.exclusive().unwrap();
+        log::info!("during");
+        assert_eq!(sink_text(), "");
+        backend.normal().unwrap();
+        backend.flush().unwrap();
+        assert!(sink_text().contains("during"));
+        assert!(!output(&mut host).contains("during"));
+    }
+
+    #[test]
+    fn dropped() {
+        crate::logging::setup(None, false);
+        STATE.with_borrow_mut(|state| state.max_held = 2);
+        for i in 0..5 {
+            log::info!("message {i}");
+        }
+        let held = take_held();
+        assert!(held.starts_with("(3 older log messages were dropped)\n"), "{held:?}");
+        assert!(held.contains("message 3") && held.contains("message 4"));
+    }
+}
@@ -0,0 +1,414 @@
+//! Serve many terminal clients (ex: browser tabs, or pty connections) from one WASM instance.
+//!
+//! A [SessionManager] keeps a separate [AnsiBackend], input parser, and size for each session. Your host code
+//! passes along each client's input and resizes by session id, and each session's output goes to its own writer:
+//!
+//! ```ignore
+//! let mut sessions = SessionManager::new();
+//! sessions.open(1, tab_one_writer, Size::new(80, 24), MyApp::default())?;
+//! sessions.open(2, tab_two_writer, Size::new(120, 40), MyApp::default())?;
+//!
+//! // Later, from the host:
+//! if sessions.input(1, bytes)? == Control::Quit {
+//!     close_tab(1);
+//! }
+//! ```
+//!
+//! Sessions can also share state: [SessionManager::watch] opens a read-only viewer of another session's app.
+//! Whenever the app changes, every session showing it is redrawn.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    io::{Error as IOError, ErrorKind, Result as IOResult, Write},
+    rc::Rc,
+};
+
+use ratatui::{Terminal, layout::Size};
+
+use crate::{
+    app::{App, Control, Event},
+    backend::AnsiBackend,
+    input::Parser,
+};
+
+pub type SessionId = u32;
+
+/// Routes input and output for many independent sessions.
+pub struct SessionManager<A> {
+    sessions: BTreeMap<SessionId, Session<A>>,
+}
+
+struct Session<A> {
+    terminal: Terminal<AnsiBackend>,
+    parser: Parser,
+    size: Rc<Cell<Size>>,
+
+    /// Shared with any viewers.
+    app: Rc<RefCell<A>>,
+
+    /// Viewers can see the app, but not send it input.
+    viewer: bool,
+}
+
+impl<A: App> Default for SessionManager<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: App> SessionManager<A> {
+    pub fn new() -> Self {
+        Self { sessions: BTreeMap::new() }
+    }
+
+    /// Start a new session, and draw its first frame.
+    ///
+    /// Replaces (without cleaning up) any existing session with the same id.
+    pub fn open(&mut self, id: SessionId, writer: impl Write + 'static, size: Size, app: A) -> IOResult<()> {
+        self.insert(id, writer, size, Rc::new(RefCell::new(app)), false)
+    }
+
+    /// Start a session which shows the same app as session `target`, but ignores input.
+    pub fn watch(&mut self, id: SessionId, writer: impl Write + 'static, size: Size, target: SessionId) -> IOResult<()> {
+        let app = self.session(target)?.app.clone();
+        self.insert(id, writer, size, app, true)
+    }
+
+    fn insert(
+        &mut self,
+        id: SessionId,
+        writer: impl Write + 'static,
+        size: Size,
+        app: Rc<RefCell<A>>,
+        viewer: bool,
+    ) -> IOResult<()> {
+        let size = Rc::new(Cell::new(size));
+        let mut backend = AnsiBackend::with_io(writer, size.clone());
+        backend.exclusive()?;
+        let mut session = Session {
+            terminal: Terminal::new(backend)?,
+            parser: Parser::default(),
+            size,
+            app,
+            viewer,
+        };
+        session.render()?;
+        self.sessions.insert(id, session);
+        Ok(())
+    }
+
+    /// Handle input from a session's client, then redraw every session that shows its app.
+    ///
+    /// If the app quits, the session is closed, and this returns [Control::Quit].
+    pub fn input(&mut self, id: SessionId, bytes: &[u8]) -> IOResult<Control> {
+        let session = self.session_mut(id)?;
+        session.parser.advance(bytes, false);
+        if session.viewer {
+            // Drain the parser, so it doesn't hold onto input forever.
+            session.parser.by_ref().for_each(drop);
+            return Ok(Control::Continue);
+        }
+
+        let events = session.parser.by_ref().map(Event::Input).collect();
+        self.dispatch(id, events, false)
+    }
+
+    /// Let a session know that its client's terminal has changed size.
+    pub fn resize(&mut self, id: SessionId, size: Size) -> IOResult<Control> {
+        let session = self.session_mut(id)?;
+        if session.size.get() == size {
+            return Ok(Control::Continue);
+        }
+        session.size.set(size);
+        if session.viewer {
+            session.render()?;
+            return Ok(Control::Continue);
+        }
+        // Redraw even if the app doesn't care, to fill the new area.
+        self.dispatch(id, vec![Event::Resize(size)], true)
+    }
+
+    /// Pass `events` to a session's app, then redraw every session showing it if it changed. Or, if `redraw`,
+    /// redraw this session regardless.
+    fn dispatch(&mut self, id: SessionId, events: Vec<Event>, redraw: bool) -> IOResult<Control> {
+        if events.is_empty() {
+            return Ok(Control::Continue);
+        }
+        let session = self.session_mut(id)?;
+        let app = session.app.clone();
+        let control = session.log_scope(|_| {
+            let mut control = Control::Unchanged;
+            for event in events {
+                match app.borrow_mut().handle_event(event) {
+                    Control::Unchanged => {}
+                    Control::Continue => control = Control::Continue,
+                    Control::Quit => return Control::Quit,
+                }
+            }
+            control
+        });
+
+        if control == Control::Quit {
+            self.close(id)?;
+        }
+        if control != Control::Unchanged {
+            self.render_app(&app)?;
+        } else if redraw {
+            self.session_mut(id)?.render()?;
+            return Ok(Control::Continue);
+        }
+        Ok(control)
+    }
+
+    /// Redraw a session. (ex: after changing its app's state from outside)
+    pub fn render(&mut self, id: SessionId) -> IOResult<()> {
+        let app = self.session(id)?.app.clone();
+        self.render_app(&app)
+    }
+
+    /// Redraw every session.
+    pub fn render_all(&mut self) -> IOResult<()> {
+        for session in self.sessions.values_mut() {
+            session.render()?;
+        }
+        Ok(())
+    }
+
+    /// Redraw every session that shows `app`.
+    fn render_app(&mut self, app: &Rc<RefCell<A>>) -> IOResult<()> {
+        for session in self.sessions.values_mut() {
+            if Rc::ptr_eq(&session.app, app) {
+                session.render()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// End a session, and return its terminal to normal mode.
+    ///
+    /// Viewers of the session's app stay open, showing its last state, until you close them too.
+    pub fn close(&mut self, id: SessionId) -> IOResult<()> {
+        let Some(mut session) = self.sessions.remove(&id) else {
+            return Err(not_found(id));
+        };
+        let backend = session.terminal.backend_mut();
+        backend.normal()?;
+        ratatui::backend::Backend::flush(backend)
+    }
+
+    /// Access a session's app. (ex: to update it from outside)
+    pub fn app(&self, id: SessionId) -> Option<Rc<RefCell<A>>> {
+        self.sessions.get(&id).map(|it| it.app.clone())
+    }
+
+    pub fn contains(&self, id: SessionId) -> bool {
+        self.sessions.contains_key(&id)
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = SessionId> + '_ {
+        self.sessions.keys().copied()
+    }
+
+    fn session(&self, id: SessionId) -> IOResult<&Session<A>> {
+        self.sessions.get(&id).ok_or_else(|| not_found(id))
+    }
+
+    fn session_mut(&mut self, id: SessionId) -> IOResult<&mut Session<A>> {
+        self.sessions.get_mut(&id).ok_or_else(|| not_found(id))
+    }
+}
+
+impl<A: App> Session<A> {
+    fn render(&mut self) -> IOResult<()> {
+        self.log_scope(|session| {
+            let app = &session.app;
+            session.terminal.draw(|frame| app.borrow_mut().render(frame))?;
+            Ok(())
+        })
+    }
+
+    /// Run `f`, holding anything it logs for this session only. (See [logging](crate::logging))
+    #[cfg(feature = "log")]
+    fn log_scope<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        let screen = self.terminal.backend().log_screen();
+        crate::logging::scope(screen, || f(self))
+    }
+
+    #[cfg(not(feature = "log"))]
+    fn log_scope<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        f(self)
+    }
+}
+
+fn not_found(id: SessionId) -> IOError {
+    IOError::new(ErrorKind::NotFound, format!("no session with id {id}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::widgets::Paragraph;
+
+    use super::*;
+    use crate::{
+        input::{KeyCode, Sequence},
+        vt::VirtualTerminal,
+    };
+
+    /// Counts the characters typed, and shows the count and the size it's drawn at. Ignores resizes.
+    #[derive(Default)]
+    struct Counter {
+        keys: usize,
+    }
+
+    impl App for Counter {
+        fn handle_event(&mut self, event: Event) -> Control {
+            match event {
+                Event::Input(Sequence::Key(KeyCode::Char(_), _)) => {
+                    self.keys += 1;
+                    Control::Continue
+                }
+                _ => Control::Unchanged,
+            }
+        }
+
+        fn render(&mut self, frame: &mut ratatui::Frame) {
+            let area = frame.area();
+            let text = format!("{} {}x{}", self.keys, area.width, area.height);
+            frame.render_widget(Paragraph::new(text), area);
+        }
+    }
+
+    /// Logs each key it gets, and quits on `q`.
+    #[cfg(feature = "log")]
+    #[derive(Default)]
+    struct Logger;
+
+    #[cfg(feature = "log")]
+    impl App for Logger {
+        fn handle_event(&mut self, event: Event) -> Control {
+            if let Event::Input(Sequence::Key(KeyCode::Char(ch), _)) = event {
+                log::info!("key {ch}");
+                if ch == 'q' {
+                    return Control::Quit;
+                }
+            }
+            Control::Continue
+        }
+
+        fn render(&mut self, frame: &mut ratatui::Frame) {
+            frame.render_widget(Paragraph::new("hi"), frame.area());
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Output {
+        fn take(&self) -> String {
+            String::from_utf8(std::mem::take(&mut *self.0.borrow_mut())).unwrap()
+        }
+    }
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> IOResult<()> {
+            Ok(())
+        }
+    }
+
+    /// A session's client: what it was sent, on a terminal of its size.
+    struct Client {
+        output: Output,
+        vt: VirtualTerminal,
+    }
+
+    impl Client {
+        fn new(size: Size) -> Self {
+            Self { output: Output::default(), vt: VirtualTerminal::new(size) }
+        }
+
+        /// The first line of the screen, after catching up on output.
+        fn line(&mut self) -> String {
+            self.vt.write(self.output.take().as_bytes());
+            self.vt.text().lines().next().unwrap_or_default().trim_end().to_string()
+        }
+    }
+
+    #[test]
+    fn viewers_follow_the_owner() {
+        let mut owner = Client::new(Size::new(10, 2));
+        let mut viewer = Client::new(Size::new(12, 3));
+        let mut sessions = SessionManager::new();
+        sessions.open(1, owner.output.clone(), Size::new(10, 2), Counter::default()).unwrap();
+        sessions.watch(2, viewer.output.clone(), Size::new(12, 3), 1).unwrap();
+        assert_eq!((owner.line(), viewer.line()), ("0 10x2".into(), "0 12x3".into()));
+
+        assert_eq!(sessions.input(1, b"ab").unwrap(), Control::Continue);
+        assert_eq!((owner.line(), viewer.line()), ("2 10x2".into(), "2 12x3".into()));
+
+        // The viewer's input goes nowhere:
+        assert_eq!(sessions.input(2, b"cd").unwrap(), Control::Continue);
+        assert_eq!(sessions.app(1).unwrap().borrow().keys, 2);
+        assert!(viewer.output.take().is_empty() && owner.output.take().is_empty());
+    }
+
+    #[test]
+    fn sizes_are_per_session() {
+        let mut clients = [Client::new(Size::new(10, 2)), Client::new(Size::new(10, 2))];
+        let mut sessions = SessionManager::new();
+        for (id, client) in clients.iter().enumerate() {
+            sessions.open(id as SessionId, client.output.clone(), Size::new(10, 2), Counter::default()).unwrap();
+        }
+        clients.iter_mut().for_each(|client| drop(client.line()));
+
+        // The app ignores resizes, but the session is redrawn anyway, at its new size:
+        clients[1].vt.resize(Size::new(20, 4));
+        assert_eq!(sessions.resize(1, Size::new(20, 4)).unwrap(), Control::Continue);
+        assert_eq!(clients[1].line(), "0 20x4");
+        assert!(clients[0].output.take().is_empty());
+
+        sessions.input(0, b"a").unwrap();
+        assert_eq!(clients[0].line(), "1 10x2");
+        sessions.input(1, b"a").unwrap();
+        assert_eq!(clients[1].line(), "1 20x4");
+    }
+
+    #[test]
+    fn unknown_sessions() {
+        let mut sessions = SessionManager::<Counter>::new();
+        assert_eq!(sessions.close(1).unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(sessions.input(1, b"a").unwrap_err().kind(), ErrorKind::NotFound);
+        assert!(sessions.watch(2, Output::default(), Size::new(10, 2), 1).is_err());
+        assert!(!sessions.contains(2));
+    }
+
+    #[test]
+    #[cfg(feature = "log")]
+    fn log_messages_stay_in_their_session() {
+        crate::logging::setup(None, false);
+        let outputs = [Output::default(), Output::default()];
+        let mut sessions = SessionManager::new();
+        for (id, output) in outputs.iter().enumerate() {
+            sessions.open(id as SessionId, output.clone(), Size::new(10, 2), Logger).unwrap();
+        }
+        sessions.input(0, b"a").unwrap();
+        sessions.input(1, b"b").unwrap();
+        assert_eq!(sessions.input(0, b"q").unwrap(), Control::Quit);
+
+        let closed = outputs[0].take();
+        assert!(closed.contains("key a") && closed.contains("key q"), "{closed:?}");
+        assert!(!closed.contains("key b"), "{closed:?}");
+
+        // The other session is still holding its messages:
+        sessions.input(1, b"c").unwrap();
+        assert!(!outputs[1].take().contains("key"));
+        sessions.close(1).unwrap();
+        let closed = outputs[1].take();
+        assert!(closed.contains("key b") && closed.contains("key c") && !closed.contains("key a"), "{closed:?}");
+    }
+}
@@ -0,0 +1,246 @@
+//! Maps terminal colors to concrete RGB values.
+//!
+//! A real terminal picks the actual colors for "red", "light blue", etc. from its theme. When we render
+//! somewhere that isn't a terminal (ex: an SVG snapshot), we need to pick them ourselves.
+//!
+//! You can also give [AnsiBackend](crate::backend::AnsiBackend) a palette (see [PaletteOptions]), so that your app
+//! looks the same no matter what the user's terminal theme is.
+
+use std::fmt::{Display, Write as _};
+
+use ratatui::style::Color;
+
+/// An RGB color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Display for Rgb {
+    /// Formats as a CSS hex color. (ex: `#ff8800`)
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Rgb(r, g, b) = self;
+        write!(f, "#{r:02x}{g:02x}{b:02x}")
+    }
+}
+
+/// The colors of a terminal theme.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    /// The default text color.
+    pub foreground: Rgb,
+
+    /// The default background color.
+    pub background: Rgb,
+
+    /// The 16 standard ANSI colors, in ANSI order. (black, red, green, yellow, blue, magenta, cyan, white,
+    /// then their "bright" versions.)
+    pub ansi: [Rgb; 16],
+}
+
+impl Default for Palette {
+    /// The xterm default colors.
+    fn default() -> Self {
+        Self {
+            foreground: Rgb(0xe5, 0xe5, 0xe5),
+            background: Rgb(0x00, 0x00, 0x00),
+            ansi: [
+                Rgb(0x00, 0x00, 0x00),
+                Rgb(0xcd, 0x00, 0x00),
+                Rgb(0x00, 0xcd, 0x00),
+                Rgb(0xcd, 0xcd, 0x00),
+                Rgb(0x00, 0x00, 0xee),
+                Rgb(0xcd, 0x00, 0xcd),
+                Rgb(0x00, 0xcd, 0xcd),
+                Rgb(0xe5, 0xe5, 0xe5),
+                Rgb(0x7f, 0x7f, 0x7f),
+                Rgb(0xff, 0x00, 0x00),
+                Rgb(0x00, 0xff, 0x00),
+                Rgb(0xff, 0xff, 0x00),
+                Rgb(0x5c, 0x5c, 0xff),
+                Rgb(0xff, 0x00, 0xff),
+                Rgb(0x00, 0xff, 0xff),
+                Rgb(0xff, 0xff, 0xff),
+            ],
+        }
+    }
+}
+
+impl Palette {
+    /// Resolve a color as it would be output by [AnsiBackend](crate::backend::AnsiBackend).
+    ///
+    /// `Default` has no single value, so you must say whether this is a foreground color.
+    pub fn resolve(&self, color: anes::Color, foreground: bool) -> Rgb {
+        use anes::Color as AColor;
+        let index = match color {
+            AColor::Default if foreground => return self.foreground,
+            AColor::Default => return self.background,
+            AColor::Rgb(r, g, b) => return Rgb(r, g, b),
+            AColor::Ansi(index) => index,
+            AColor::Black => 0,
+            AColor::DarkRed => 1,
+            AColor::DarkGreen => 2,
+            AColor::DarkYellow => 3,
+            AColor::DarkBlue => 4,
+            AColor::DarkMagenta => 5,
+            AColor::DarkCyan => 6,
+            AColor::Gray => 7,
+            AColor::DarkGray => 8,
+            AColor::Red => 9,
+            AColor::Green => 10,
+            AColor::Yellow => 11,
+            AColor::Blue => 12,
+            AColor::Magenta => 13,
+            AColor::Cyan => 14,
+            AColor::White => 15,
+        };
+        self.indexed(index)
+    }
+
+    /// Resolve one of the 256 indexed colors.
+    ///
+    /// Only the first 16 come from the palette. The rest are the standard 6x6x6 color cube and grayscale ramp.
+    pub fn indexed(&self, index: u8) -> Rgb {
+        match index {
+            0..16 => self.ansi[index as usize],
+            16..232 => {
+                let index = index - 16;
+                let level = |it: u8| if it == 0 { 0 } else { 55 + it * 40 };
+                Rgb(level(index / 36), level((index / 6) % 6), level(index % 6))
+            }
+            232.. => {
+                let gray = 8 + (index - 232) * 10;
+                Rgb(gray, gray, gray)
+            }
+        }
+    }
+}
+
+/// How [AnsiBackend::set_palette](crate::backend::AnsiBackend::set_palette) applies a palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteMode {
+    /// Output named and indexed colors as RGB. Requires a terminal with "truecolor" support.
+    ///
+    /// The default foreground and background ([Color::Reset]) are left alone.
+    Rewrite,
+
+    /// Reprogram the terminal's own palette (with `OSC 4`), including the default foreground and background.
+    ///
+    /// The terminal's palette is restored by [AnsiBackend::normal](crate::backend::AnsiBackend::normal).
+    Program,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaletteOptions {
+    pub palette: Palette,
+
+    /// Other indexed colors to change. (ex: `(208, Rgb(0xff, 0x87, 0x00))`) Entries for 0-15 override `palette`.
+    pub indexed: Vec<(u8, Rgb)>,
+
+    pub mode: PaletteMode,
+}
+
+impl PaletteOptions {
+    pub fn new(palette: Palette, mode: PaletteMode) -> Self {
+        Self {
+            palette,
+            indexed: vec![],
+            mode,
+        }
+    }
+
+    /// The RGB value to use in place of an indexed color, if we're changing it.
+    ///
+    /// Agrees with [PaletteOptions::program], where later entries replace earlier ones.
+    fn lookup(&self, index: u8) -> Option<Rgb> {
+        let indexed = self.indexed.iter().rev().find(|(it, _)| *it == index).map(|(_, rgb)| *rgb);
+        indexed.or_else(|| self.palette.ansi.get(index as usize).copied())
+    }
+
+    /// Replace `color` with its RGB value from this palette. (For [PaletteMode::Rewrite].)
+    pub(crate) fn rewrite(&self, color: Color) -> Color {
+        let index = match color {
+            Color::Indexed(index) => Some(index),
+            color => named_index(color),
+        };
+        match index.and_then(|index| self.lookup(index)) {
+            Some(Rgb(r, g, b)) => Color::Rgb(r, g, b),
+            None => color,
+        }
+    }
+
+    /// Sequences to reprogram the terminal's palette. (For [PaletteMode::Program].)
+    pub(crate) fn program(&self) -> String {
+        let mut out = String::from("\x1b]4");
+        let mut push = |index: u8, rgb: Rgb| {
+            let Rgb(r, g, b) = rgb;
+            // Infallible: writing to a String.
+            let _ = write!(out, ";{index};rgb:{r:02x}/{g:02x}/{b:02x}");
+        };
+        for (index, rgb) in self.palette.ansi.iter().enumerate() {
+            push(index as u8, *rgb);
+        }
+        for (index, rgb) in &self.indexed {
+            push(*index, *rgb);
+        }
+        out.push_str(ST);
+
+        for (code, rgb) in [(10, self.palette.foreground), (11, self.palette.background)] {
+            let Rgb(r, g, b) = rgb;
+            let _ = write!(out, "\x1b]{code};rgb:{r:02x}/{g:02x}/{b:02x}{ST}");
+        }
+        out
+    }
+}
+
+/// String Terminator, which ends an OSC sequence.
+const ST: &str = "\x1b\\";
+
+/// Restores the terminal's palette, and default foreground & background colors, after [PaletteOptions::program].
+pub(crate) const RESTORE_PALETTE: &str = "\x1b]104\x1b\\\x1b]110\x1b\\\x1b]111\x1b\\";
+
+/// The ANSI index of a named Ratatui color.
+fn named_index(color: Color) -> Option<u8> {
+    let index = match color {
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::Gray => 7,
+        Color::DarkGray => 8,
+        Color::LightRed => 9,
+        Color::LightGreen => 10,
+        Color::LightYellow => 11,
+        Color::LightBlue => 12,
+        Color::LightMagenta => 13,
+        Color::LightCyan => 14,
+        Color::White => 15,
+        Color::Reset | Color::Rgb(..) | Color::Indexed(_) => return None,
+    };
+    Some(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrite_agrees_with_program() {
+        let mut options = PaletteOptions::new(Palette::default(), PaletteMode::Rewrite);
+        options.indexed = vec![(1, Rgb(1, 1, 1)), (208, Rgb(2, 2, 2)), (1, Rgb(3, 3, 3))];
+
+        assert_eq!(options.rewrite(Color::Red), Color::Rgb(3, 3, 3));
+        assert_eq!(options.rewrite(Color::Indexed(1)), Color::Rgb(3, 3, 3));
+        assert_eq!(options.rewrite(Color::Indexed(208)), Color::Rgb(2, 2, 2));
+        assert_eq!(options.rewrite(Color::Green), Color::Rgb(0, 0xcd, 0));
+        assert_eq!(options.rewrite(Color::Indexed(209)), Color::Indexed(209));
+        assert_eq!(options.rewrite(Color::Reset), Color::Reset);
+
+        // The terminal applies each entry in order, so the last one for 1 wins there too:
+        let program = options.program();
+        let last = program.rfind(";1;").unwrap();
+        assert!(program[last..].starts_with(";1;rgb:03/03/03"), "{program:?}");
+        assert!(program.contains(";208;rgb:02/02/02"), "{program:?}");
+    }
+}
@@ -0,0 +1,268 @@
+//! Replay recorded terminal output, to see exactly what the user saw.
+//!
+//! A [Player] reads an [asciicast v2] file (see [record](crate::record)) or a raw log of output bytes,
+//! and runs it through a [VirtualTerminal]. You can then seek to any point in time, and get the screen
+//! contents as a Ratatui [Buffer]. (ex: to compare against a freshly rendered frame in a regression test.)
+//!
+//! [asciicast v2]: https://docs.asciinema.org/manual/asciicast/v2/
+
+use std::{error::Error, fmt::Display, time::Duration};
+
+use ratatui::{buffer::Buffer, layout::Size};
+
+use crate::{json, vt::VirtualTerminal};
+
+/// Plays back recorded terminal output.
+pub struct Player {
+    initial_size: Size,
+    events: Vec<Event>,
+
+    /// How many of `events` have been written to `terminal`.
+    applied: usize,
+
+    /// How many bytes of the next event [Player::seek_bytes] has written to `terminal`, too.
+    partial: usize,
+
+    terminal: VirtualTerminal,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Event {
+    time: Duration,
+    kind: EventKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum EventKind {
+    Output(Vec<u8>),
+    Resize(Size),
+}
+
+/// A problem reading a recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayError {
+    /// 1-based line number in the file.
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for PlayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for PlayError {}
+
+impl Player {
+    /// Load an asciicast v2 recording.
+    ///
+    /// Input ("i") and marker ("m") events are ignored, since they don't change the screen.
+    pub fn from_asciicast(cast: &str) -> Result<Self, PlayError> {
+        let mut lines = cast
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line))
+            .filter(|(_, line)| !line.trim().is_empty());
+
+        let err = |line: usize, message: &str| PlayError {
+            line,
+            message: message.to_string(),
+        };
+
+        let (line, header) = lines.next().ok_or_else(|| err(1, "missing header"))?;
+        let header = json::parse(header).map_err(|message| err(line, &message))?;
+        if header.get("version").and_then(|it| it.as_f64()) != Some(2.0) {
+            return Err(err(line, "only asciicast version 2 is supported"));
+        }
+        let dimension = |name: &str| -> Result<u16, PlayError> {
+            let value = header.get(name).and_then(|it| it.as_f64());
+            value.map(|it| it as u16).ok_or_else(|| err(line, &format!("header is missing {name}")))
+        };
+        let initial_size = Size::new(dimension("width")?, dimension("height")?);
+
+        let mut events = vec![];
+        for (line, text) in lines {
+            let event = json::parse(text).map_err(|message| err(line, &message))?;
+            let json::Value::Array(items) = event else {
+                return Err(err(line, "expected an event array"));
+            };
+            let [time, code, data] = items.as_slice() else {
+                return Err(err(line, "expected [time, code, data]"));
+            };
+            let time = time
+                .as_f64()
+                .filter(|it| it.is_finite() && *it >= 0.0)
+                .ok_or_else(|| err(line, "invalid time"))?;
+            let time = Duration::from_secs_f64(time);
+            let data = data.as_str().ok_or_else(|| err(line, "event data must be a string"))?;
+
+            let kind = match code.as_str() {
+                Some("o") => EventKind::Output(data.as_bytes().to_vec()),
+                Some("r") => {
+                    let size = data
+                        .split_once('x')
+                        .and_then(|(w, h)| Some(Size::new(w.parse().ok()?, h.parse().ok()?)))
+                        .ok_or_else(|| err(line, "invalid resize event"))?;
+                    EventKind::Resize(size)
+                }
+                Some(_) => continue,
+                None => return Err(err(line, "event code must be a string")),
+            };
+            events.push(Event { time, kind });
+        }
+
+        Ok(Self::new(initial_size, events))
+    }
+
+    /// Load a raw log of terminal output. (ex: everything an [AnsiBackend](crate::backend::AnsiBackend) wrote)
+    ///
+    /// There are no timestamps, so everything happens at time zero. Use [Player::seek_bytes] to step through it.
+    pub fn from_raw(bytes: &[u8], size: Size) -> Self {
+        let events = vec![Event {
+            time: Duration::ZERO,
+            kind: EventKind::Output(bytes.to_vec()),
+        }];
+        Self::new(size, events)
+    }
+
+    fn new(initial_size: Size, events: Vec<Event>) -> Self {
+        Self {
+            initial_size,
+            events,
+            applied: 0,
+            partial: 0,
+            terminal: VirtualTerminal::new(initial_size),
+        }
+    }
+
+    /// The time of the last event.
+    pub fn duration(&self) -> Duration {
+        self.events.last().map(|it| it.time).unwrap_or_default()
+    }
+
+    /// Times at which the screen may have changed. Useful for stepping through a recording frame by frame.
+    pub fn event_times(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.events.iter().map(|it| it.time)
+    }
+
+    /// Seek to `time`, and return what the screen looked like then.
+    ///
+    /// Includes every event at or before `time`. Seeking forward is incremental, seeking backward replays
+    /// from the start.
+    pub fn seek(&mut self, time: Duration) -> &Buffer {
+        let target = self.events.partition_point(|it| it.time <= time);
+        if target < self.applied || (target == self.applied && self.partial > 0) {
+            self.rewind();
+        }
+        while self.applied < target {
+            let event = &self.events[self.applied];
+            match &event.kind {
+                // Finish the event, if seek_bytes() stopped partway through it.
+                EventKind::Output(bytes) => self.terminal.write(&bytes[self.partial..]),
+                EventKind::Resize(size) => self.terminal.resize(*size),
+            }
+            self.partial = 0;
+            self.applied += 1;
+        }
+        self.terminal.buffer()
+    }
+
+    /// Seek to just after the first `offset` bytes of output, ignoring time.
+    pub fn seek_bytes(&mut self, offset: usize) -> &Buffer {
+        self.rewind();
+        let mut remaining = offset;
+        for event in &self.events {
+            match &event.kind {
+                EventKind::Output(bytes) => {
+                    let count = remaining.min(bytes.len());
+                    self.terminal.write(&bytes[..count]);
+                    remaining -= count;
+                    if count < bytes.len() {
+                        self.partial = count;
+                        break;
+                    }
+                }
+                EventKind::Resize(size) => self.terminal.resize(*size),
+            }
+            self.applied += 1;
+        }
+        self.terminal.buffer()
+    }
+
+    /// Like [Player::seek], but returns an owned copy of the screen.
+    pub fn buffer_at(&mut self, time: Duration) -> Buffer {
+        self.seek(time).clone()
+    }
+
+    /// The virtual terminal at the current position. (ex: for the cursor position, or plain text)
+    pub fn terminal(&self) -> &VirtualTerminal {
+        &self.terminal
+    }
+
+    fn rewind(&mut self) {
+        self.terminal = VirtualTerminal::new(self.initial_size);
+        self.applied = 0;
+        self.partial = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAST: &str = r#"{"version": 2, "width": 10, "height": 2}
+[0.5, "o", "abc"]
+[1.0, "o", "def"]
+[1.5, "r", "5x2"]
+[2.0, "o", "\r\nxyz"]
+"#;
+
+    fn text(player: &Player) -> String {
+        player.terminal().text()
+    }
+
+    #[test]
+    fn seek_forward_and_back() {
+        let mut player = Player::from_asciicast(CAST).unwrap();
+        assert_eq!(player.duration(), Duration::from_secs(2));
+        player.seek(Duration::from_secs(1));
+        assert_eq!(text(&player), "abcdef\n");
+        player.seek(Duration::from_secs(3));
+        assert_eq!(text(&player), "abcde\nxyz");
+        player.seek(Duration::ZERO);
+        assert_eq!(text(&player), "\n");
+    }
+
+    #[test]
+    fn seek_after_partial_seek_bytes() {
+        let mut player = Player::from_asciicast(CAST).unwrap();
+        player.seek_bytes(4);
+        assert_eq!(text(&player), "abcd\n");
+
+        // Forward, finishing the event seek_bytes() stopped in:
+        player.seek(Duration::from_secs(1));
+        assert_eq!(text(&player), "abcdef\n");
+
+        // To just before it:
+        player.seek_bytes(5);
+        player.seek(Duration::from_millis(500));
+        assert_eq!(text(&player), "abc\n");
+    }
+
+    #[test]
+    fn raw() {
+        let mut player = Player::from_raw(b"hello\x1b[2;1Hworld", Size::new(10, 2));
+        assert_eq!(player.seek_bytes(5).content[4].symbol(), "o");
+        assert_eq!(text(&player), "hello\n");
+        player.seek(Duration::ZERO);
+        assert_eq!(text(&player), "hello\nworld");
+    }
+
+    #[test]
+    fn bad_recordings() {
+        assert_eq!(Player::from_asciicast("").err().map(|it| it.line), Some(1));
+        let err = Player::from_asciicast("{\"version\": 2, \"width\": 1, \"height\": 1}\n[1, \"o\"]").err();
+        assert_eq!(err.map(|it| it.line), Some(2));
+    }
+}
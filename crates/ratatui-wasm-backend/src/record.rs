@@ -0,0 +1,356 @@
+//! Record terminal sessions in [asciicast v2] format, from inside the app.
+//!
+//! A [Recording] wraps the writer and size source that you give to
+//! [AnsiBackend::with_io](crate::backend::AnsiBackend::with_io), so that it sees everything the backend outputs:
+//!
+//! ```ignore
+//! let recording = Recording::start(RecordingOptions::new(cast_file_writer, Box::new(JsClock), size))?;
+//! let backend = AnsiBackend::with_io(recording.tee(stdout_writer), recording.watch_size(get_size));
+//!
+//! // Optionally, also record input:
+//! recording.input(bytes)?;
+//! ```
+//!
+//! The result can be played back with [asciinema], or with a [Player](crate::play::Player).
+//!
+//! [asciicast v2]: https://docs.asciinema.org/manual/asciicast/v2/
+//! [asciinema]: https://asciinema.org
+
+use std::{
+    cell::RefCell,
+    fmt::Write as _,
+    io::{Result as IOResult, Write},
+    mem,
+    rc::Rc,
+    time::Duration,
+};
+
+use ratatui::layout::Size;
+
+use crate::{
+    host::{Clock, GetSize},
+    json,
+};
+
+pub struct RecordingOptions {
+    /// Where to write the asciicast file. (ex: a [JsWriter](crate::types::JsWriter) that appends to a file.)
+    pub sink: Box<dyn Write>,
+
+    /// Used to timestamp events.
+    pub clock: Box<dyn Clock>,
+
+    /// The size of the terminal when recording starts.
+    pub size: Size,
+
+    /// Record input events as well as output. Off by default, since it may include sensitive data.
+    pub record_input: bool,
+
+    pub title: Option<String>,
+}
+
+impl RecordingOptions {
+    pub fn new(sink: impl Write + 'static, clock: Box<dyn Clock>, size: Size) -> Self {
+        Self {
+            sink: Box::new(sink),
+            clock,
+            size,
+            record_input: false,
+            title: None,
+        }
+    }
+}
+
+/// A handle to an in-progress recording.
+///
+/// Clones share the same recording.
+#[derive(Clone)]
+pub struct Recording {
+    inner: Rc<RefCell<Recorder>>,
+}
+
+struct Recorder {
+    sink: Box<dyn Write>,
+    clock: Box<dyn Clock>,
+    start: Duration,
+    size: Size,
+    record_input: bool,
+
+    // asciicast events must be valid UTF-8, so hold onto partial characters until the rest arrives:
+    pending_output: Vec<u8>,
+    pending_input: Vec<u8>,
+}
+
+impl Recording {
+    /// Start recording. Writes the asciicast header immediately.
+    pub fn start(options: RecordingOptions) -> IOResult<Self> {
+        let RecordingOptions { mut sink, clock, size, record_input, title } = options;
+
+        let mut header = format!(r#"{{"version":2,"width":{},"height":{}"#, size.width, size.height);
+        if let Some(title) = title {
+            header.push_str(r#","title":"#);
+            json::push_str(&mut header, &title);
+        }
+        header.push_str("}\n");
+        sink.write_all(header.as_bytes())?;
+
+        let start = clock.now();
+        Ok(Self {
+            inner: Rc::new(RefCell::new(Recorder {
+                sink,
+                clock,
+                start,
+                size,
+                record_input,
+                pending_output: vec![],
+                pending_input: vec![],
+            })),
+        })
+    }
+
+    /// Wrap a writer, so that everything written to it is also recorded.
+    pub fn tee<W: Write>(&self, writer: W) -> RecordingWriter<W> {
+        RecordingWriter {
+            inner: writer,
+            recording: self.clone(),
+        }
+    }
+
+    /// Wrap a size source, so that changes in size are recorded as resize events.
+    ///
+    /// A resize is only seen when the backend asks for the size, so it's recorded then. (ex: on the next draw,
+    /// or `Terminal::autoresize()`) To record it as soon as it happens, call [Recording::resize] yourself.
+    pub fn watch_size<S: GetSize>(&self, get_size: S) -> RecordingSize<S> {
+        RecordingSize {
+            inner: get_size,
+            recording: self.clone(),
+        }
+    }
+
+    /// Record bytes that were written to the terminal.
+    pub fn output(&self, bytes: &[u8]) -> IOResult<()> {
+        let mut recorder = self.inner.borrow_mut();
+        recorder.pending_output.extend_from_slice(bytes);
+        let text = take_complete_utf8(&mut recorder.pending_output);
+        recorder.event("o", &text)
+    }
+
+    /// Record bytes that were read from stdin. Ignored unless `record_input` is set.
+    pub fn input(&self, bytes: &[u8]) -> IOResult<()> {
+        let mut recorder = self.inner.borrow_mut();
+        if !recorder.record_input {
+            return Ok(());
+        }
+        recorder.pending_input.extend_from_slice(bytes);
+        let text = take_complete_utf8(&mut recorder.pending_input);
+        recorder.event("i", &text)
+    }
+
+    /// Record that the terminal has changed size. Does nothing if the size is the same as last time.
+    pub fn resize(&self, size: Size) -> IOResult<()> {
+        let mut recorder = self.inner.borrow_mut();
+        if recorder.size == size {
+            return Ok(());
+        }
+        recorder.size = size;
+        recorder.event("r", &format!("{}x{}", size.width, size.height))
+    }
+
+    /// Write out any partial characters we were holding onto, and flush the sink.
+    pub fn finish(&self) -> IOResult<()> {
+        let mut recorder = self.inner.borrow_mut();
+        let output = String::from_utf8_lossy(&mem::take(&mut recorder.pending_output)).into_owned();
+        recorder.event("o", &output)?;
+        let input = String::from_utf8_lossy(&mem::take(&mut recorder.pending_input)).into_owned();
+        recorder.event("i", &input)?;
+        recorder.sink.flush()
+    }
+}
+
+impl Recorder {
+    fn event(&mut self, code: &str, data: &str) -> IOResult<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let elapsed = self.clock.now().saturating_sub(self.start);
+        let mut line = String::new();
+        let _ = write!(line, "[{:.6}, \"{code}\", ", elapsed.as_secs_f64());
+        json::push_str(&mut line, data);
+        line.push_str("]\n");
+        self.sink.write_all(line.as_bytes())
+    }
+}
+
+/// A writer which records everything that's written through it. See [Recording::tee].
+pub struct RecordingWriter<W> {
+    inner: W,
+    recording: Recording,
+}
+
+impl<W: Write> Write for RecordingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        let written = self.inner.write(buf)?;
+        self.recording.output(&buf[..written])?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        self.inner.flush()
+    }
+}
+
+/// A size source which records resizes. See [Recording::watch_size].
+pub struct RecordingSize<S> {
+    inner: S,
+    recording: Recording,
+}
+
+impl<S: GetSize> GetSize for RecordingSize<S> {
+    fn get_size(&self) -> IOResult<Size> {
+        let size = self.inner.get_size()?;
+        self.recording.resize(size)?;
+        Ok(size)
+    }
+}
+
+/// Takes as much of `pending` as is valid UTF-8, leaving behind a trailing partial character, if any.
+fn take_complete_utf8(pending: &mut Vec<u8>) -> String {
+    let complete = match std::str::from_utf8(pending) {
+        Ok(_) => pending.len(),
+        Err(err) if err.error_len().is_none() => err.valid_up_to(),
+        // Actually invalid, not just incomplete. Let from_utf8_lossy() replace it.
+        Err(_) => pending.len(),
+    };
+    let rest = pending.split_off(complete);
+    let text = String::from_utf8_lossy(pending).into_owned();
+    *pending = rest;
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use ratatui::{Terminal, layout::Rect, widgets::Paragraph};
+
+    use super::*;
+    use crate::{backend::AnsiBackend, host::FakeClock, play::Player};
+
+    /// Collects what's written to it. Clones share the same bytes.
+    #[derive(Clone, Default)]
+    struct Sink(Rc<RefCell<Vec<u8>>>);
+
+    impl Sink {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> IOResult<()> {
+            Ok(())
+        }
+    }
+
+    fn start(sink: &Sink, clock: &FakeClock, size: Size) -> Recording {
+        let mut options = RecordingOptions::new(sink.clone(), Box::new(clock.clone()), size);
+        options.record_input = true;
+        options.title = Some("a \"title\"".into());
+        Recording::start(options).unwrap()
+    }
+
+    #[test]
+    fn header_and_events() {
+        let (sink, clock) = (Sink::default(), FakeClock::new());
+        clock.set(Duration::from_secs(100));
+        let recording = start(&sink, &clock, Size::new(10, 2));
+        assert_eq!(sink.text(), "{\"version\":2,\"width\":10,\"height\":2,\"title\":\"a \\\"title\\\"\"}\n");
+
+        clock.advance(Duration::from_millis(1500));
+        recording.output(b"\x1b[1mhi\n").unwrap();
+        recording.input(b"q").unwrap();
+        recording.resize(Size::new(10, 2)).unwrap();
+        recording.resize(Size::new(20, 5)).unwrap();
+        let events: Vec<_> = sink.text().lines().skip(1).map(String::from).collect();
+        assert_eq!(
+            events,
+            [r#"[1.500000, "o", "\u001b[1mhi\n"]"#, r#"[1.500000, "i", "q"]"#, r#"[1.500000, "r", "20x5"]"#]
+        );
+    }
+
+    #[test]
+    fn split_characters() {
+        let crab = "🦀".as_bytes();
+        let mut pending = crab[..3].to_vec();
+        assert_eq!(take_complete_utf8(&mut pending), "");
+        assert_eq!(pending, &crab[..3]);
+        pending.extend_from_slice(&crab[3..]);
+        pending.extend_from_slice(b"a\xe6");
+        assert_eq!(take_complete_utf8(&mut pending), "🦀a");
+        assert_eq!(pending, b"\xe6");
+
+        // Bytes that can't become a character aren't held:
+        let mut pending = b"a\xffb".to_vec();
+        assert_eq!(take_complete_utf8(&mut pending), "a\u{fffd}b");
+        assert!(pending.is_empty());
+
+        // A character split across writes comes out whole:
+        let (sink, clock) = (Sink::default(), FakeClock::new());
+        let recording = start(&sink, &clock, Size::new(10, 2));
+        let mut writer = recording.tee(std::io::sink());
+        writer.write_all(&crab[..1]).unwrap();
+        writer.write_all(&crab[1..]).unwrap();
+        assert!(sink.text().ends_with("[0.000000, \"o\", \"🦀\"]\n"), "{}", sink.text());
+
+        // Or is replaced, at the end:
+        writer.write_all(&crab[..2]).unwrap();
+        recording.finish().unwrap();
+        assert!(sink.text().ends_with("[0.000000, \"o\", \"\u{fffd}\"]\n"), "{}", sink.text());
+    }
+
+    #[test]
+    fn resizes_are_recorded_when_the_backend_asks() {
+        let (sink, clock) = (Sink::default(), FakeClock::new());
+        let size = Rc::new(Cell::new(Size::new(10, 2)));
+        let recording = start(&sink, &clock, size.get());
+        let get_size = recording.watch_size(size.clone());
+
+        size.set(Size::new(12, 3));
+        clock.advance(Duration::from_secs(1));
+        assert!(!sink.text().contains("\"r\""));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(get_size.get_size().unwrap(), Size::new(12, 3));
+        get_size.get_size().unwrap();
+        assert_eq!(sink.text().matches("\"r\"").count(), 1);
+        assert!(sink.text().ends_with("[2.000000, \"r\", \"12x3\"]\n"), "{}", sink.text());
+    }
+
+    #[test]
+    fn play_back() {
+        let (sink, clock) = (Sink::default(), FakeClock::new());
+        let size = Rc::new(Cell::new(Size::new(12, 2)));
+        let recording = start(&sink, &clock, size.get());
+        let backend = AnsiBackend::with_io(recording.tee(std::io::sink()), recording.watch_size(size.clone()));
+        let mut terminal = Terminal::new(backend).unwrap();
+
+        let mut frames = vec![];
+        for (text, width) in [("one", 12), ("two 日本", 12), ("three", 8)] {
+            clock.advance(Duration::from_secs(1));
+            size.set(Size::new(width, 2));
+            terminal.autoresize().unwrap();
+            let frame = terminal.draw(|frame| frame.render_widget(Paragraph::new(text), frame.area())).unwrap();
+            frames.push((clock.now(), frame.buffer.clone()));
+        }
+        recording.finish().unwrap();
+
+        let mut player = Player::from_asciicast(&sink.text()).unwrap();
+        for (time, frame) in frames.iter().rev() {
+            assert_eq!(player.seek(*time), frame, "at {time:?}");
+        }
+        assert_eq!(player.seek(Duration::ZERO).area, Rect::new(0, 0, 12, 2));
+    }
+}
@@ -0,0 +1,709 @@
+//! Mirror a Ratatui app to another renderer with a compact binary protocol, instead of ANSI.
+//!
+//! [RemoteBackend] writes each frame as a diff of the cells that changed. [RemoteScreen] reads that stream,
+//! and applies it to a [Buffer]. Put whatever transport you like in between. (ex: a WebSocket) Or, to test
+//! without one, connect them with a [Loopback]:
+//!
+//! ```ignore
+//! let loopback = Loopback::new();
+//! let mut terminal = Terminal::new(RemoteBackend::new(loopback.clone(), Size::new(80, 24)))?;
+//! let frame = terminal.draw(|frame| app.render(frame))?;
+//! assert_eq!(loopback.screen().buffer(), frame.buffer);
+//! ```
+//!
+//! # Protocol
+//!
+//! The stream is a sequence of messages. Each one starts with a one-byte opcode. Numbers are unsigned
+//! [LEB128](https://en.wikipedia.org/wiki/LEB128) varints, and strings are a varint byte length then UTF-8.
+//!
+//! | Opcode | Message      | Fields                                        |
+//! |--------|--------------|-----------------------------------------------|
+//! | `0x01` | Resize       | width, height. (Also clears the screen.)      |
+//! | `0x02` | Define style | id, fg color, bg color, modifier bits         |
+//! | `0x03` | Cells        | x, y, style id, count, then `count` strings   |
+//! | `0x04` | Repeat       | x, y, style id, count, string                 |
+//! | `0x05` | Clear        |                                               |
+//! | `0x06` | Cursor       | x, y, visible (0 or 1)                        |
+//! | `0x07` | End of frame |                                               |
+//! | `0x08` | Reset styles | (Forget all style ids.)                       |
+//!
+//! A color is one byte: `0` for the default, `1..=16` for the 16 named colors (in ANSI order), `17` followed by
+//! an index byte, or `18` followed by red, green, and blue bytes.
+//!
+//! Cells are placed left to right, starting at (x, y). Each one advances by the display width of its string,
+//! and cells hidden under a wide character are filled with blanks in the same style. Cells past the right edge
+//! of the screen are dropped. [RemoteScreen] rejects a Resize to more than 4Mi cells.
+
+use std::{
+    cell::{Ref, RefCell},
+    collections::HashMap,
+    fmt::Display,
+    io::{Error as IOError, ErrorKind, Result as IOResult, Write},
+    rc::Rc,
+};
+
+use ratatui::{
+    backend::{Backend, WindowSize},
+    buffer::{Buffer, Cell},
+    layout::{Position, Rect, Size},
+    style::{Color, Modifier},
+};
+use unicode_width::UnicodeWidthStr as _;
+
+use crate::{host::GetSize, sgr::Sgr};
+
+const RESIZE: u8 = 0x01;
+const DEFINE_STYLE: u8 = 0x02;
+const CELLS: u8 = 0x03;
+const REPEAT: u8 = 0x04;
+const CLEAR: u8 = 0x05;
+const CURSOR: u8 = 0x06;
+const END_FRAME: u8 = 0x07;
+const RESET_STYLES: u8 = 0x08;
+
+/// Start over with style ids after this many, so neither side's table grows forever.
+const MAX_STYLES: usize = 1024;
+
+/// Only use a Repeat message for at least this many identical cells.
+const MIN_REPEAT: usize = 4;
+
+/// The largest screen a [RemoteScreen] will allocate, in cells.
+const MAX_CELLS: u32 = 4 * 1024 * 1024;
+
+/// A Ratatui [Backend] that writes the [remote](self) protocol.
+pub struct RemoteBackend {
+    writer: Box<dyn Write>,
+    get_size: Box<dyn GetSize>,
+    buf: Vec<u8>,
+
+    /// The size we last told the other side about.
+    size: Option<Size>,
+    styles: HashMap<Sgr, u32>,
+    cursor: Position,
+    cursor_visible: bool,
+}
+
+impl RemoteBackend {
+    pub fn new(writer: impl Write + 'static, get_size: impl GetSize + 'static) -> Self {
+        Self {
+            writer: Box::new(writer),
+            get_size: Box::new(get_size),
+            buf: vec![],
+            size: None,
+            styles: HashMap::new(),
+            cursor: Position::ORIGIN,
+            cursor_visible: true,
+        }
+    }
+
+    fn sync_size(&mut self) -> IOResult<()> {
+        let size = self.get_size.get_size()?;
+        if self.size != Some(size) {
+            self.buf.push(RESIZE);
+            push_varint(&mut self.buf, size.width.into());
+            push_varint(&mut self.buf, size.height.into());
+            self.size = Some(size);
+        }
+        Ok(())
+    }
+
+    fn style_id(&mut self, cell: &Cell) -> u32 {
+        let sgr = Sgr::of(cell);
+        if let Some(id) = self.styles.get(&sgr) {
+            return *id;
+        }
+        if self.styles.len() >= MAX_STYLES {
+            self.styles.clear();
+            self.buf.push(RESET_STYLES);
+        }
+        let id = self.styles.len() as u32;
+        self.styles.insert(sgr, id);
+        self.buf.push(DEFINE_STYLE);
+        push_varint(&mut self.buf, id);
+        push_color(&mut self.buf, sgr.fg);
+        push_color(&mut self.buf, sgr.bg);
+        push_varint(&mut self.buf, sgr.modifier.bits().into());
+        id
+    }
+
+    /// Write one run of same-styled, adjacent cells.
+    fn push_run(&mut self, x: u16, y: u16, cells: &[&Cell]) {
+        let style = self.style_id(cells[0]);
+        let mut x = x;
+        let mut rest = cells;
+        while !rest.is_empty() {
+            let symbol = rest[0].symbol();
+            let same = rest.iter().take_while(|it| it.symbol() == symbol).count();
+            let width = symbol.width().max(1) as u16;
+
+            // Cells up to the next long-enough stretch of repeats:
+            let (count, opcode) = if same >= MIN_REPEAT {
+                (same, REPEAT)
+            } else {
+                let mut count = same;
+                while count < rest.len() {
+                    let next = rest[count].symbol();
+                    let next_same = rest[count..].iter().take_while(|it| it.symbol() == next).count();
+                    if next_same >= MIN_REPEAT {
+                        break;
+                    }
+                    count += next_same;
+                }
+                (count, CELLS)
+            };
+
+            self.buf.push(opcode);
+            push_varint(&mut self.buf, x.into());
+            push_varint(&mut self.buf, y.into());
+            push_varint(&mut self.buf, style);
+            push_varint(&mut self.buf, count as u32);
+            if opcode == REPEAT {
+                push_str(&mut self.buf, symbol);
+                x = x.saturating_add(width.saturating_mul(count as u16));
+            } else {
+                for cell in &rest[..count] {
+                    push_str(&mut self.buf, cell.symbol());
+                    x = x.saturating_add(cell.symbol().width().max(1) as u16);
+                }
+            }
+            rest = &rest[count..];
+        }
+    }
+
+    fn push_cursor(&mut self) {
+        self.buf.push(CURSOR);
+        push_varint(&mut self.buf, self.cursor.x.into());
+        push_varint(&mut self.buf, self.cursor.y.into());
+        self.buf.push(self.cursor_visible as u8);
+    }
+}
+
+impl Backend for RemoteBackend {
+    fn draw<'a, I>(&mut self, content: I) -> IOResult<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>,
+    {
+        self.sync_size()?;
+
+        // Group cells into runs which are adjacent, and have the same style:
+        let mut run: Vec<&Cell> = vec![];
+        let mut start = (0, 0);
+        let mut next_x = 0;
+        for (x, y, cell) in content {
+            if cell.skip {
+                continue;
+            }
+            let continues = run.last().is_some_and(|prev| {
+                y == start.1 && x == next_x && Sgr::of(prev) == Sgr::of(cell)
+            });
+            if !continues {
+                if !run.is_empty() {
+                    self.push_run(start.0, start.1, &run);
+                }
+                run.clear();
+                start = (x, y);
+            }
+            run.push(cell);
+            next_x = x.saturating_add(cell.symbol().width().max(1) as u16);
+        }
+        if !run.is_empty() {
+            self.push_run(start.0, start.1, &run);
+        }
+        Ok(())
+    }
+
+    fn hide_cursor(&mut self) -> IOResult<()> {
+        if self.cursor_visible {
+            self.cursor_visible = false;
+            self.push_cursor();
+        }
+        Ok(())
+    }
+
+    fn show_cursor(&mut self) -> IOResult<()> {
+        if !self.cursor_visible {
+            self.cursor_visible = true;
+            self.push_cursor();
+        }
+        Ok(())
+    }
+
+    fn get_cursor_position(&mut self) -> IOResult<Position> {
+        Ok(self.cursor)
+    }
+
+    fn set_cursor_position<P: Into<Position>>(&mut self, position: P) -> IOResult<()> {
+        let position = position.into();
+        if position != self.cursor {
+            self.cursor = position;
+            self.push_cursor();
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) -> IOResult<()> {
+        self.sync_size()?;
+        self.buf.push(CLEAR);
+        Ok(())
+    }
+
+    fn size(&self) -> IOResult<Size> {
+        self.get_size.get_size()
+    }
+
+    fn window_size(&mut self) -> IOResult<WindowSize> {
+        Ok(WindowSize {
+            columns_rows: self.size()?,
+            pixels: Default::default(),
+        })
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.buf.push(END_FRAME);
+        self.writer.write_all(&std::mem::take(&mut self.buf))?;
+        self.writer.flush()
+    }
+}
+
+/// Applies a stream from a [RemoteBackend] to a [Buffer].
+pub struct RemoteScreen {
+    buffer: Buffer,
+    styles: Vec<Sgr>,
+    cursor: Position,
+    cursor_visible: bool,
+
+    /// The start of a message that hasn't completely arrived yet.
+    pending: Vec<u8>,
+
+    /// Bytes consumed so far, for error messages.
+    offset: usize,
+}
+
+/// The stream wasn't valid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    /// Where in the stream the bad message started.
+    pub offset: usize,
+    pub message: String,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "at byte {}: {}", self.offset, self.message)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl Default for RemoteScreen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RemoteScreen {
+    pub fn new() -> Self {
+        Self {
+            buffer: Buffer::empty(Rect::ZERO),
+            styles: vec![],
+            cursor: Position::ORIGIN,
+            cursor_visible: true,
+            pending: vec![],
+            offset: 0,
+        }
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    /// Where the cursor should be shown, if it's visible.
+    pub fn cursor(&self) -> Option<Position> {
+        self.cursor_visible.then_some(self.cursor)
+    }
+
+    /// Apply bytes from the stream. They don't need to line up with messages or frames.
+    ///
+    /// Returns how many frames were completed. (ex: to know when to repaint)
+    pub fn push(&mut self, bytes: &[u8]) -> Result<usize, DecodeError> {
+        self.pending.extend_from_slice(bytes);
+        let input = std::mem::take(&mut self.pending);
+        let mut frames = 0;
+        let mut start = 0;
+        while start < input.len() {
+            let mut reader = Reader { bytes: &input[start..], pos: 0 };
+            match self.message(&mut reader) {
+                Ok(end_of_frame) => {
+                    frames += end_of_frame as usize;
+                    start += reader.pos;
+                    self.offset += reader.pos;
+                }
+                Err(Problem::Incomplete) => break,
+                Err(Problem::Invalid(message)) => {
+                    return Err(DecodeError { offset: self.offset, message });
+                }
+            }
+        }
+        self.pending = input[start..].to_vec();
+        Ok(frames)
+    }
+
+    /// Apply one message. Returns true if it's the end of a frame.
+    fn message(&mut self, reader: &mut Reader) -> Result<bool, Problem> {
+        match reader.byte()? {
+            RESIZE => {
+                let width = reader.u16()?;
+                let height = reader.u16()?;
+                if u32::from(width) * u32::from(height) > MAX_CELLS {
+                    return Err(Problem::Invalid(format!("{width}x{height} is too big")));
+                }
+                self.buffer = Buffer::empty(Rect::new(0, 0, width, height));
+            }
+            DEFINE_STYLE => {
+                let id = reader.varint()? as usize;
+                let fg = reader.color()?;
+                let bg = reader.color()?;
+                let modifier = Modifier::from_bits_truncate(reader.u16()?);
+                if id != self.styles.len() {
+                    return Err(Problem::Invalid(format!("expected style id {}, got {id}", self.styles.len())));
+                }
+                self.styles.push(Sgr { fg, bg, modifier });
+            }
+            opcode @ (CELLS | REPEAT) => {
+                let mut x = reader.u16()?;
+                let y = reader.u16()?;
+                let style = reader.varint()? as usize;
+                let count = reader.varint()?;
+                let style = *self
+                    .styles
+                    .get(style)
+                    .ok_or_else(|| Problem::Invalid(format!("undefined style {style}")))?;
+
+                if opcode == REPEAT {
+                    let symbol = reader.string()?;
+                    // Each cell takes at least one column. Don't spin on cells that are past the edge of the screen.
+                    for _ in 0..count.min(self.buffer.area.width.saturating_sub(x).into()) {
+                        x = self.put(x, y, symbol, style);
+                    }
+                } else {
+                    // Read every string, even past the edge, so the next message starts in the right place.
+                    for _ in 0..count {
+                        let symbol = reader.string()?;
+                        x = self.put(x, y, symbol, style);
+                    }
+                }
+            }
+            CLEAR => self.buffer.reset(),
+            CURSOR => {
+                self.cursor = Position { x: reader.u16()?, y: reader.u16()? };
+                self.cursor_visible = reader.byte()? != 0;
+            }
+            END_FRAME => return Ok(true),
+            RESET_STYLES => self.styles.clear(),
+            opcode => return Err(Problem::Invalid(format!("unknown opcode {opcode:#04x}"))),
+        }
+        Ok(false)
+    }
+
+    /// Put a cell in the buffer, and return the x position of the next cell.
+    fn put(&mut self, x: u16, y: u16, symbol: &str, style: Sgr) -> u16 {
+        let width = symbol.width().max(1) as u16;
+        for offset in 0..width {
+            let Some(cell) = self.buffer.cell_mut((x.saturating_add(offset), y)) else { break };
+            cell.set_symbol(if offset == 0 { symbol } else { " " });
+            cell.fg = style.fg;
+            cell.bg = style.bg;
+            cell.modifier = style.modifier;
+        }
+        x.saturating_add(width)
+    }
+}
+
+/// A [Write] which feeds a [RemoteScreen] directly. (ex: to test an app through the protocol, without a transport)
+///
+/// Clones share the same screen, so you can give one to a [RemoteBackend] and keep one to check the screen.
+#[derive(Clone, Default)]
+pub struct Loopback(Rc<RefCell<RemoteScreen>>);
+
+impl Loopback {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn screen(&self) -> Ref<'_, RemoteScreen> {
+        self.0.borrow()
+    }
+}
+
+impl Write for Loopback {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        self.0.borrow_mut().push(buf).map_err(|err| IOError::new(ErrorKind::InvalidData, err))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        Ok(())
+    }
+}
+
+enum Problem {
+    /// Need more bytes.
+    Incomplete,
+    Invalid(String),
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, Problem> {
+        let byte = *self.bytes.get(self.pos).ok_or(Problem::Incomplete)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u32, Problem> {
+        let mut value: u32 = 0;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u32).checked_shl(shift).unwrap_or(0);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Problem::Invalid("varint too long".into()))
+    }
+
+    fn u16(&mut self) -> Result<u16, Problem> {
+        let value = self.varint()?;
+        value.try_into().map_err(|_| Problem::Invalid(format!("{value} is out of range")))
+    }
+
+    fn string(&mut self) -> Result<&'a str, Problem> {
+        let len = self.varint()? as usize;
+        let bytes = self.bytes.get(self.pos..self.pos + len).ok_or(Problem::Incomplete)?;
+        self.pos += len;
+        std::str::from_utf8(bytes).map_err(|_| Problem::Invalid("invalid UTF-8".into()))
+    }
+
+    fn color(&mut self) -> Result<Color, Problem> {
+        let color = match self.byte()? {
+            0 => Color::Reset,
+            tag @ 1..=16 => NAMED[(tag - 1) as usize],
+            17 => Color::Indexed(self.byte()?),
+            18 => Color::Rgb(self.byte()?, self.byte()?, self.byte()?),
+            tag => return Err(Problem::Invalid(format!("unknown color tag {tag}"))),
+        };
+        Ok(color)
+    }
+}
+
+/// The named colors, in ANSI order.
+const NAMED: [Color; 16] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::Gray,
+    Color::DarkGray,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+    Color::White,
+];
+
+fn push_varint(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn push_str(out: &mut Vec<u8>, value: &str) {
+    push_varint(out, value.len() as u32);
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn push_color(out: &mut Vec<u8>, color: Color) {
+    match color {
+        Color::Reset => out.push(0),
+        Color::Indexed(index) => out.extend_from_slice(&[17, index]),
+        Color::Rgb(r, g, b) => out.extend_from_slice(&[18, r, g, b]),
+        named => {
+            let index = NAMED.iter().position(|it| *it == named).unwrap_or_default();
+            out.push(index as u8 + 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell as StdCell, time::Instant};
+
+    use ratatui::{
+        Terminal,
+        style::{Style, Stylize as _},
+        text::Line,
+        widgets::{Block, Paragraph},
+    };
+
+    use super::*;
+
+    fn check(expected: &Buffer, loopback: &Loopback) {
+        let screen = loopback.screen();
+        assert_eq!(screen.buffer().area, expected.area);
+        // Cells hidden under a wide character are blank, in its style, on the remote side:
+        let mut hidden = 0;
+        for (i, (actual, cell)) in screen.buffer().content.iter().zip(&expected.content).enumerate() {
+            let position = expected.pos_of(i);
+            if hidden == 0 {
+                assert_eq!(actual.symbol(), cell.symbol(), "at {position:?}");
+                assert_eq!(Sgr::of(actual), Sgr::of(cell), "at {position:?}");
+                hidden = cell.symbol().width();
+            } else {
+                assert_eq!(actual.symbol(), " ", "at {position:?}");
+            }
+            hidden = hidden.saturating_sub(1);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let loopback = Loopback::new();
+        let size = Rc::new(StdCell::new(Size::new(30, 6)));
+        let mut terminal = Terminal::new(RemoteBackend::new(loopback.clone(), size.clone())).unwrap();
+
+        let frames: [&dyn Fn(&mut ratatui::Frame); 3] = [
+            &|frame| frame.render_widget(Block::bordered().title("title").blue(), frame.area()),
+            &|frame| {
+                let lines = vec![
+                    Line::from("日本語 and emoji 🦀").bold(),
+                    Line::from("aaaaaaaaaa bbbbb".on_red()),
+                    Line::styled("rgb", Style::new().fg(Color::Rgb(1, 2, 3)).bg(Color::Indexed(200))),
+                ];
+                frame.render_widget(Paragraph::new(lines).block(Block::bordered()), frame.area());
+            },
+            &|frame| frame.render_widget(Paragraph::new("x".repeat(200)).wrap(Default::default()), frame.area()),
+        ];
+        for draw in frames {
+            let expected = terminal.draw(draw).unwrap().buffer.clone();
+            check(&expected, &loopback);
+        }
+
+        size.set(Size::new(12, 3));
+        terminal.autoresize().unwrap();
+        let expected = terminal.draw(frames[1]).unwrap().buffer.clone();
+        check(&expected, &loopback);
+        assert_eq!(loopback.screen().cursor(), None);
+    }
+
+    #[test]
+    fn many_styles() {
+        let loopback = Loopback::new();
+        let mut terminal = Terminal::new(RemoteBackend::new(loopback.clone(), Size::new(64, 40))).unwrap();
+        for frame_index in 0..2u8 {
+            let expected = terminal
+                .draw(|frame| {
+                    let buffer = frame.buffer_mut();
+                    for (i, cell) in buffer.content.iter_mut().enumerate() {
+                        cell.set_char('#').set_bg(Color::Rgb(i as u8, (i >> 8) as u8, frame_index));
+                    }
+                })
+                .unwrap()
+                .buffer
+                .clone();
+            check(&expected, &loopback);
+        }
+    }
+
+    #[test]
+    fn split_anywhere() {
+        let output = Loopback::new();
+        let mut bytes = vec![];
+        {
+            let recorder = Rc::new(RefCell::new(vec![]));
+            struct Recorder(Rc<RefCell<Vec<u8>>>);
+            impl Write for Recorder {
+                fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+                    self.0.borrow_mut().extend_from_slice(buf);
+                    Ok(buf.len())
+                }
+                fn flush(&mut self) -> IOResult<()> {
+                    Ok(())
+                }
+            }
+            let mut terminal = Terminal::new(RemoteBackend::new(Recorder(recorder.clone()), Size::new(20, 4))).unwrap();
+            terminal.draw(|frame| frame.render_widget(Block::bordered().title("日本").red(), frame.area())).unwrap();
+            bytes.extend_from_slice(&recorder.borrow());
+        }
+        output.clone().write_all(&bytes).unwrap();
+
+        for split in 0..bytes.len() {
+            let mut screen = RemoteScreen::new();
+            assert_eq!(screen.push(&bytes[..split]).unwrap(), 0);
+            assert_eq!(screen.push(&bytes[split..]).unwrap(), 1);
+            assert_eq!(screen.buffer(), output.screen().buffer());
+        }
+    }
+
+    #[test]
+    fn huge_repeat() {
+        let mut bytes = vec![RESIZE, 10, 2, DEFINE_STYLE, 0, 0, 0, 0, REPEAT, 3, 1, 0];
+        push_varint(&mut bytes, u32::MAX);
+        push_str(&mut bytes, "x");
+        bytes.push(END_FRAME);
+
+        let started = Instant::now();
+        let mut screen = RemoteScreen::new();
+        assert_eq!(screen.push(&bytes), Ok(1));
+        assert!(started.elapsed().as_secs() < 1);
+        let row: String = screen.buffer().content[10..].iter().map(Cell::symbol).collect();
+        assert_eq!(row, "   xxxxxxx");
+    }
+
+    #[test]
+    fn cells_past_the_edge() {
+        let mut bytes = vec![RESIZE, 3, 1, DEFINE_STYLE, 0, 0, 0, 0, CELLS, 1, 0, 0, 4];
+        for symbol in ["a", "b", "c", "d"] {
+            push_str(&mut bytes, symbol);
+        }
+        bytes.extend_from_slice(&[CURSOR, 2, 0, 1, END_FRAME]);
+
+        let mut screen = RemoteScreen::new();
+        assert_eq!(screen.push(&bytes), Ok(1));
+        let row: String = screen.buffer().content.iter().map(Cell::symbol).collect();
+        assert_eq!(row, " ab");
+        assert_eq!(screen.cursor(), Some(Position::new(2, 0)));
+    }
+
+    #[test]
+    fn invalid() {
+        let mut screen = RemoteScreen::new();
+        let err = screen.push(&[RESIZE, 1, 1, END_FRAME, 0xff]).unwrap_err();
+        assert_eq!(err.offset, 4);
+        let mut screen = RemoteScreen::new();
+        assert!(screen.push(&[CELLS, 0, 0, 7, 1, 1, b'x']).is_err());
+        let mut screen = RemoteScreen::new();
+        assert!(screen.push(&[DEFINE_STYLE, 0, 99, 0, 0]).is_err());
+        let mut screen = RemoteScreen::new();
+        assert!(screen.push(&[RESIZE, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).is_err());
+        let mut screen = RemoteScreen::new();
+        assert!(screen.push(&[RESIZE, 0xff, 0xff, 0x03, 0xff, 0xff, 0x03]).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! Run an [App], redrawing it on input and on timer ticks.
+//!
+//! WASM can't block or sleep, so the host drives the loop: it passes along input as it arrives, and calls
+//! [Runner::tick] when a timeout that the runner asked for is up.
+//!
+//! ```ignore
+//! // `set_timeout` is a JsTimer from the host. (ex: `(ms) => setTimeout(() => main.tick(), ms)`)
+//! let runner = Runner::new(backend, Stopwatch::default(), JsClock, set_timeout)?;
+//!
+//! // From the host:
+//! runner.input(bytes)?;
+//! runner.tick()?;  // from the setTimeout callback
+//! ```
+//!
+//! The app is only redrawn when it says something changed. (See [Control::Unchanged])
+
+use std::{io::Result as IOResult, time::Duration};
+
+use ratatui::{Terminal, layout::Size};
+
+use crate::{
+    app::{App, Control, Event},
+    backend::AnsiBackend,
+    host::{Clock, Timer},
+    input::Parser,
+};
+
+/// Drives one [App].
+pub struct Runner<A> {
+    terminal: Terminal<AnsiBackend>,
+    parser: Parser,
+    app: A,
+    clock: Box<dyn Clock>,
+    timer: Box<dyn Timer>,
+
+    /// When the timeout we last asked the host for is due, if it hasn't fired yet.
+    scheduled: Option<Duration>,
+    quit: bool,
+}
+
+impl<A: App> Runner<A> {
+    /// Switch the terminal to the alternate screen, and draw the app's first frame.
+    pub fn new(
+        mut backend: AnsiBackend,
+        app: A,
+        clock: impl Clock + 'static,
+        timer: impl Timer + 'static,
+    ) -> IOResult<Self> {
+        backend.exclusive()?;
+        let mut runner = Self {
+            terminal: Terminal::new(backend)?,
+            parser: Parser::default(),
+            app,
+            clock: Box::new(clock),
+            timer: Box::new(timer),
+            scheduled: None,
+            quit: false,
+        };
+        runner.finish(true)?;
+        Ok(runner)
+    }
+
+    /// Handle input from the terminal.
+    ///
+    /// If the app quits, the terminal is returned to normal mode, and this returns [Control::Quit].
+    pub fn input(&mut self, bytes: &[u8]) -> IOResult<Control> {
+        self.parser.advance(bytes, false);
+        let events: Vec<_> = self.parser.by_ref().map(Event::Input).collect();
+        if events.is_empty() {
+            return Ok(self.control());
+        }
+        self.dispatch(events, false)
+    }
+
+    /// Let the app know that the terminal has changed size.
+    ///
+    /// The backend gets the size from its [GetSize](crate::host::GetSize), so update that first.
+    pub fn resize(&mut self, size: Size) -> IOResult<Control> {
+        // Redraw even if the app doesn't care, to fill the new area.
+        self.dispatch(vec![Event::Resize(size)], true)
+    }
+
+    /// Call this when a timeout from the [Timer] is up.
+    ///
+    /// It's OK to call it at other times, too. The app is only updated once its [App::next_tick] arrives.
+    pub fn tick(&mut self) -> IOResult<Control> {
+        if self.quit {
+            return Ok(Control::Quit);
+        }
+        let now = self.clock.now();
+        // We may be called at other times, too, so only forget our timeout once it's due. Otherwise we'd ask for
+        // another on every call, and they'd pile up. finish() asks again if we need to.
+        if self.scheduled.is_some_and(|scheduled| now >= scheduled) {
+            self.scheduled = None;
+        }
+
+        let changed = match self.app.next_tick() {
+            Some(due) if due <= now => match self.app.update(now) {
+                Control::Continue => true,
+                Control::Unchanged => false,
+                Control::Quit => return self.quit(),
+            },
+            _ => false,
+        };
+        self.finish(changed)
+    }
+
+    /// Redraw the app. (ex: after changing its state from outside)
+    pub fn render(&mut self) -> IOResult<()> {
+        let app = &mut self.app;
+        self.terminal.draw(|frame| app.render(frame))?;
+        Ok(())
+    }
+
+    pub fn app(&self) -> &A {
+        &self.app
+    }
+
+    /// Access the app. Call [Runner::render] after changing it.
+    pub fn app_mut(&mut self) -> &mut A {
+        &mut self.app
+    }
+
+    pub fn terminal_mut(&mut self) -> &mut Terminal<AnsiBackend> {
+        &mut self.terminal
+    }
+
+    /// Whether the app has quit.
+    pub fn is_done(&self) -> bool {
+        self.quit
+    }
+
+    fn dispatch(&mut self, events: Vec<Event>, mut changed: bool) -> IOResult<Control> {
+        if self.quit {
+            return Ok(Control::Quit);
+        }
+        for event in events {
+            match self.app.handle_event(event) {
+                Control::Continue => changed = true,
+                Control::Unchanged => {}
+                Control::Quit => return self.quit(),
+            }
+        }
+        self.finish(changed)
+    }
+
+    /// Redraw if needed, and make sure we'll be called back for the app's next tick.
+    fn finish(&mut self, changed: bool) -> IOResult<Control> {
+        if changed {
+            self.render()?;
+        }
+
+        if let Some(due) = self.app.next_tick()
+            && self.scheduled.is_none_or(|scheduled| due < scheduled)
+        {
+            self.timer.set_timeout(due.saturating_sub(self.clock.now()))?;
+            self.scheduled = Some(due);
+        }
+        Ok(Control::Continue)
+    }
+
+    fn quit(&mut self) -> IOResult<Control> {
+        self.quit = true;
+        let backend = self.terminal.backend_mut();
+        backend.normal()?;
+        ratatui::backend::Backend::flush(backend)?;
+        Ok(Control::Quit)
+    }
+
+    fn control(&self) -> Control {
+        if self.quit { Control::Quit } else { Control::Continue }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::Frame;
+
+    use super::*;
+    use crate::{host::FakeClock, testing::TestHost};
+
+    /// Ticks once a second.
+    #[derive(Default)]
+    struct Ticker {
+        ticks: u32,
+    }
+
+    impl App for Ticker {
+        fn handle_event(&mut self, _event: Event) -> Control {
+            Control::Unchanged
+        }
+
+        fn update(&mut self, _now: Duration) -> Control {
+            self.ticks += 1;
+            Control::Continue
+        }
+
+        fn next_tick(&self) -> Option<Duration> {
+            Some(Duration::from_secs(self.ticks.into()) + Duration::from_secs(1))
+        }
+
+        fn render(&mut self, _frame: &mut Frame) {}
+    }
+
+    #[test]
+    fn early_ticks_dont_pile_up_timeouts() {
+        let host = TestHost::new(Size::new(10, 2));
+        let clock = FakeClock::new();
+        let mut runner = Runner::new(host.backend(), Ticker::default(), clock.clone(), clock.clone()).unwrap();
+        assert_eq!(clock.next_timeout(), Some(Duration::from_secs(1)));
+
+        for _ in 0..5 {
+            clock.advance(Duration::from_millis(100));
+            runner.tick().unwrap();
+        }
+        assert_eq!(runner.app().ticks, 0);
+
+        clock.set(Duration::from_secs(1));
+        assert_eq!(clock.take_due(), 1);
+        runner.tick().unwrap();
+        assert_eq!(runner.app().ticks, 1);
+        // And the next one:
+        assert_eq!(clock.next_timeout(), Some(Duration::from_secs(2)));
+        clock.set(Duration::from_secs(2));
+        assert_eq!(clock.take_due(), 1);
+    }
+}
@@ -0,0 +1,331 @@
+//! Select Graphic Rendition: The colors and attributes that the terminal applies to the text we print.
+//!
+//! [Sgr] models the terminal's current state, so that we can move from one style to the next with a single
+//! `CSI ... m` sequence, using as few bytes as possible.
+
+use std::fmt::Write as _;
+
+use ratatui::{
+    buffer::Cell,
+    style::{Color, Modifier},
+};
+
+/// The terminal's graphic rendition state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Sgr {
+    pub fg: Color,
+    pub bg: Color,
+    pub modifier: Modifier,
+}
+
+/// Each modifier, with the parameter that turns it on, and the one that turns it off.
+///
+/// Note that some modifiers share an "off" parameter: 22 turns off both BOLD and DIM, and 25 turns off both blinks.
+const MODIFIERS: [(Modifier, u8, u8); 9] = [
+    (Modifier::BOLD, 1, 22),
+    (Modifier::DIM, 2, 22),
+    (Modifier::ITALIC, 3, 23),
+    (Modifier::UNDERLINED, 4, 24),
+    (Modifier::SLOW_BLINK, 5, 25),
+    (Modifier::RAPID_BLINK, 6, 25),
+    (Modifier::REVERSED, 7, 27),
+    (Modifier::HIDDEN, 8, 28),
+    (Modifier::CROSSED_OUT, 9, 29),
+];
+
+impl Sgr {
+    pub fn of(cell: &Cell) -> Self {
+        Self {
+            fg: cell.fg,
+            bg: cell.bg,
+            modifier: cell.modifier,
+        }
+    }
+
+    /// Appends the shortest sequence that changes the terminal from `from` to `self`.
+    ///
+    /// If `from` is `None`, we don't know the terminal's current state, so we start with a reset.
+    /// Appends nothing if there's nothing to change.
+    pub fn transition(&self, from: Option<&Sgr>, out: &mut String) {
+        let params = match from {
+            Some(from) if from == self => return,
+            Some(from) => {
+                let incremental = self.incremental_params(from);
+                let reset = self.reset_params();
+                if reset.len() < incremental.len() { reset } else { incremental }
+            }
+            None => self.reset_params(),
+        };
+        out.push_str("\x1b[");
+        out.push_str(&params);
+        out.push('m');
+    }
+
+    /// Parameters to reset, then apply everything in `self`.
+    fn reset_params(&self) -> String {
+        let mut params = Params::default();
+        params.push("0");
+        for (modifier, on, _) in MODIFIERS {
+            if self.modifier.contains(modifier) {
+                params.push(on);
+            }
+        }
+        if self.fg != Color::Reset {
+            params.push(ColorParam(self.fg, Layer::Fg));
+        }
+        if self.bg != Color::Reset {
+            params.push(ColorParam(self.bg, Layer::Bg));
+        }
+        params.0
+    }
+
+    /// Parameters to change only what's different between `from` and `self`.
+    fn incremental_params(&self, from: &Sgr) -> String {
+        let mut params = Params::default();
+        let removed = from.modifier - self.modifier;
+        let mut to_add = self.modifier - from.modifier;
+
+        let mut offs = vec![];
+        for (modifier, _, off) in MODIFIERS {
+            if removed.contains(modifier) && !offs.contains(&off) {
+                offs.push(off);
+                // The "off" may also turn off a modifier we want to keep. (ex: removing BOLD but keeping DIM)
+                for (sibling, _, sibling_off) in MODIFIERS {
+                    if sibling_off == off && self.modifier.contains(sibling) {
+                        to_add |= sibling;
+                    }
+                }
+            }
+        }
+        for off in offs {
+            params.push(off);
+        }
+        for (modifier, on, _) in MODIFIERS {
+            if to_add.contains(modifier) {
+                params.push(on);
+            }
+        }
+
+        if self.fg != from.fg {
+            params.push(ColorParam(self.fg, Layer::Fg));
+        }
+        if self.bg != from.bg {
+            params.push(ColorParam(self.bg, Layer::Bg));
+        }
+        params.0
+    }
+}
+
+#[derive(Default)]
+struct Params(String);
+
+impl Params {
+    fn push(&mut self, param: impl std::fmt::Display) {
+        if !self.0.is_empty() {
+            self.0.push(';');
+        }
+        // Infallible: writing to a String.
+        let _ = write!(self.0, "{param}");
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Layer {
+    Fg,
+    Bg,
+}
+
+struct ColorParam(Color, Layer);
+
+impl std::fmt::Display for ColorParam {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ColorParam(color, layer) = *self;
+        // (normal, bright, extended, default)
+        let (base, bright, extended, default) = match layer {
+            Layer::Fg => (30, 90, 38, 39),
+            Layer::Bg => (40, 100, 48, 49),
+        };
+        match color {
+            Color::Reset => write!(f, "{default}"),
+            Color::Black => write!(f, "{}", base),
+            Color::Red => write!(f, "{}", base + 1),
+            Color::Green => write!(f, "{}", base + 2),
+            Color::Yellow => write!(f, "{}", base + 3),
+            Color::Blue => write!(f, "{}", base + 4),
+            Color::Magenta => write!(f, "{}", base + 5),
+            Color::Cyan => write!(f, "{}", base + 6),
+            Color::Gray => write!(f, "{}", base + 7),
+            Color::DarkGray => write!(f, "{}", bright),
+            Color::LightRed => write!(f, "{}", bright + 1),
+            Color::LightGreen => write!(f, "{}", bright + 2),
+            Color::LightYellow => write!(f, "{}", bright + 3),
+            Color::LightBlue => write!(f, "{}", bright + 4),
+            Color::LightMagenta => write!(f, "{}", bright + 5),
+            Color::LightCyan => write!(f, "{}", bright + 6),
+            Color::White => write!(f, "{}", bright + 7),
+            Color::Indexed(index) => write!(f, "{extended};5;{index}"),
+            Color::Rgb(r, g, b) => write!(f, "{extended};2;{r};{g};{b}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORS: [Color; 19] = [
+        Color::Reset,
+        Color::Black,
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::Gray,
+        Color::DarkGray,
+        Color::LightRed,
+        Color::LightGreen,
+        Color::LightYellow,
+        Color::LightBlue,
+        Color::LightMagenta,
+        Color::LightCyan,
+        Color::White,
+        Color::Indexed(200),
+        Color::Rgb(1, 2, 3),
+    ];
+
+    fn transition(from: Option<&Sgr>, to: &Sgr) -> String {
+        let mut out = String::new();
+        to.transition(from, &mut out);
+        out
+    }
+
+    /// What a terminal does with the output of [Sgr::transition].
+    fn apply(mut state: Sgr, seq: &str) -> Sgr {
+        if seq.is_empty() {
+            return state;
+        }
+        let params = seq
+            .strip_prefix("\x1b[")
+            .and_then(|it| it.strip_suffix('m'))
+            .unwrap_or_else(|| panic!("not an SGR sequence: {seq:?}"));
+        let params: Vec<u8> = params.split(';').map(|it| it.parse().unwrap()).collect();
+        let mut params = params.into_iter();
+        while let Some(param) = params.next() {
+            let mut color = |base: u8| match param - base {
+                0..=7 => COLORS[1 + usize::from(param - base)],
+                8 => match params.next() {
+                    Some(5) => Color::Indexed(params.next().unwrap()),
+                    Some(2) => Color::Rgb(params.next().unwrap(), params.next().unwrap(), params.next().unwrap()),
+                    other => panic!("bad extended color: {other:?}"),
+                },
+                9 => Color::Reset,
+                _ => unreachable!(),
+            };
+            match param {
+                0 => state = Sgr::default(),
+                22 => state.modifier -= Modifier::BOLD | Modifier::DIM,
+                25 => state.modifier -= Modifier::SLOW_BLINK | Modifier::RAPID_BLINK,
+                23 | 24 | 27 | 28 | 29 => {
+                    let (modifier, ..) = MODIFIERS.iter().find(|(_, _, off)| *off == param).unwrap();
+                    state.modifier -= *modifier;
+                }
+                1..=9 => state.modifier |= MODIFIERS[usize::from(param) - 1].0,
+                30..=39 => state.fg = color(30),
+                40..=49 => state.bg = color(40),
+                90..=97 => state.fg = COLORS[9 + usize::from(param - 90)],
+                100..=107 => state.bg = COLORS[9 + usize::from(param - 100)],
+                _ => panic!("unexpected parameter {param}"),
+            }
+        }
+        state
+    }
+
+    fn all_modifiers() -> impl Iterator<Item = Modifier> {
+        (0..1u16 << MODIFIERS.len()).map(|bits| {
+            MODIFIERS
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| bits & (1 << i) != 0)
+                .fold(Modifier::empty(), |acc, (_, (modifier, ..))| acc | *modifier)
+        })
+    }
+
+    fn check(from: Sgr, to: Sgr) {
+        let seq = transition(Some(&from), &to);
+        assert_eq!(apply(from, &seq), to, "{from:?} -> {to:?} via {seq:?}");
+        let reset = transition(None, &to);
+        assert!(seq.len() <= reset.len(), "{from:?} -> {to:?}: {seq:?} is longer than {reset:?}");
+        if from == to {
+            assert_eq!(seq, "");
+        }
+    }
+
+    #[test]
+    fn every_pair_of_modifiers() {
+        for from in all_modifiers() {
+            for to in all_modifiers() {
+                check(
+                    Sgr { modifier: from, ..Sgr::default() },
+                    Sgr { modifier: to, ..Sgr::default() },
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_pair_of_colors() {
+        for from in COLORS {
+            for to in COLORS {
+                check(Sgr { fg: from, ..Sgr::default() }, Sgr { fg: to, ..Sgr::default() });
+                check(Sgr { bg: from, ..Sgr::default() }, Sgr { bg: to, ..Sgr::default() });
+                check(
+                    Sgr { fg: from, bg: to, modifier: Modifier::BOLD },
+                    Sgr { fg: to, bg: from, modifier: Modifier::ITALIC },
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn from_unknown_state_resets() {
+        for to in all_modifiers() {
+            let to = Sgr { fg: Color::Red, bg: Color::Reset, modifier: to };
+            let seq = transition(None, &to);
+            assert!(seq.starts_with("\x1b[0"), "{seq:?}");
+            // Whatever the terminal had before:
+            let before = Sgr { fg: Color::Blue, bg: Color::Green, modifier: Modifier::all() };
+            assert_eq!(apply(before, &seq), to);
+        }
+        assert_eq!(transition(None, &Sgr::default()), "\x1b[0m");
+    }
+
+    #[test]
+    fn set_and_clear() {
+        let plain = Sgr::default();
+        let bold = Sgr { modifier: Modifier::BOLD, ..plain };
+        let bold_dim = Sgr { modifier: Modifier::BOLD | Modifier::DIM, ..plain };
+        let dim = Sgr { modifier: Modifier::DIM, fg: Color::Red, ..plain };
+        assert_eq!(transition(Some(&plain), &bold), "\x1b[1m");
+        assert_eq!(transition(Some(&bold), &bold_dim), "\x1b[2m");
+        // 22 turns off both, so DIM has to be turned back on:
+        assert_eq!(transition(Some(&Sgr { fg: Color::Red, ..bold_dim }), &dim), "\x1b[22;2m");
+        let red = Sgr { fg: Color::Red, ..plain };
+        assert_eq!(transition(Some(&plain), &red), "\x1b[31m");
+        assert_eq!(transition(Some(&red), &Sgr { bg: Color::Indexed(17), ..red }), "\x1b[48;5;17m");
+    }
+
+    #[test]
+    fn reset_when_shorter() {
+        let busy = Sgr {
+            fg: Color::Rgb(10, 20, 30),
+            bg: Color::Indexed(100),
+            modifier: Modifier::BOLD | Modifier::ITALIC | Modifier::UNDERLINED,
+        };
+        assert_eq!(transition(Some(&busy), &Sgr::default()), "\x1b[0m");
+        // Incremental, when it's shorter:
+        let less = Sgr { modifier: Modifier::BOLD | Modifier::ITALIC, ..busy };
+        assert_eq!(transition(Some(&busy), &less), "\x1b[24m");
+    }
+}
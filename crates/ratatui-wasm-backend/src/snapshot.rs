@@ -0,0 +1,313 @@
+//! Export a Ratatui [Buffer] as a standalone SVG image or HTML `<pre>`.
+//!
+//! Useful for docs and bug reports, when you want a "screenshot" without a real terminal.
+//!
+//! To capture what an [AnsiBackend](crate::backend::AnsiBackend) is currently showing, use the buffer
+//! that `Terminal::draw()` returns:
+//!
+//! ```ignore
+//! let frame = terminal.draw(|frame| app.render(frame))?;
+//! let svg = snapshot::to_svg(frame.buffer, &SnapshotOptions::default());
+//! ```
+
+use std::fmt::Write as _;
+
+use ratatui::{buffer::Buffer, style::{Color, Modifier}};
+use unicode_width::UnicodeWidthStr as _;
+
+use crate::{
+    backend::ansi_color,
+    html::{SpanRun, push_html_escaped, row_runs},
+    palette::{Palette, Rgb},
+};
+
+pub struct SnapshotOptions {
+    /// Colors to use for the terminal's default and named colors.
+    pub palette: Palette,
+
+    /// CSS `font-family`. Should be a monospace font.
+    pub font_family: String,
+
+    /// Font size, in pixels.
+    pub font_size: f32,
+
+    /// Size of each terminal cell, in pixels. (Only used for SVG.)
+    pub cell_width: f32,
+    pub cell_height: f32,
+}
+
+impl Default for SnapshotOptions {
+    fn default() -> Self {
+        Self {
+            palette: Palette::default(),
+            font_family: "ui-monospace, Menlo, Consolas, monospace".into(),
+            font_size: 14.0,
+            cell_width: 8.4,
+            cell_height: 17.0,
+        }
+    }
+}
+
+/// The colors & styles of a [SpanRun], once the palette and modifiers have been applied.
+struct ResolvedStyle {
+    fg: Rgb,
+    bg: Rgb,
+    bold: bool,
+    dim: bool,
+    italic: bool,
+    underlined: bool,
+    crossed_out: bool,
+}
+
+impl ResolvedStyle {
+    fn new(span: &SpanRun, palette: &Palette) -> Self {
+        let modifier = span.modifier;
+        let mut fg = palette.resolve(ansi_color(span.fg), true);
+        let mut bg = palette.resolve(ansi_color(span.bg), false);
+        if modifier.contains(Modifier::REVERSED) {
+            std::mem::swap(&mut fg, &mut bg);
+        }
+        if modifier.contains(Modifier::HIDDEN) {
+            fg = bg;
+        }
+
+        Self {
+            fg,
+            bg,
+            bold: modifier.contains(Modifier::BOLD),
+            dim: modifier.contains(Modifier::DIM),
+            italic: modifier.contains(Modifier::ITALIC),
+            underlined: modifier.contains(Modifier::UNDERLINED),
+            crossed_out: modifier.contains(Modifier::CROSSED_OUT),
+        }
+    }
+
+    fn text_decoration(&self) -> Option<&'static str> {
+        match (self.underlined, self.crossed_out) {
+            (true, true) => Some("underline line-through"),
+            (true, false) => Some("underline"),
+            (false, true) => Some("line-through"),
+            (false, false) => None,
+        }
+    }
+}
+
+/// Render the buffer as a standalone SVG image.
+pub fn to_svg(buffer: &Buffer, options: &SnapshotOptions) -> String {
+    let SnapshotOptions { palette, font_family, font_size, cell_width, cell_height } = options;
+    let width = buffer.area.width as f32 * cell_width;
+    let height = buffer.area.height as f32 * cell_height;
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width:.1}" height="{height:.1}" viewBox="0 0 {width:.1} {height:.1}" font-family=""#
+    );
+    push_html_escaped(&mut svg, font_family);
+    let _ = writeln!(svg, r#"" font-size="{font_size:.1}">"#);
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="{}"/>"#, palette.background);
+
+    for row in buffer.area.top()..buffer.area.bottom() {
+        let y = (row - buffer.area.top()) as f32 * cell_height;
+        // Approximate baseline, so that descenders fit in the cell.
+        let baseline = y + cell_height * 0.78;
+
+        let mut col = 0;
+        for span in row_runs(buffer, row) {
+            let columns = span.text.width();
+            let x = col as f32 * cell_width;
+            let span_width = columns as f32 * cell_width;
+            col += columns;
+
+            let style = ResolvedStyle::new(&span, palette);
+            if style.bg != palette.background {
+                let _ = writeln!(
+                    svg,
+                    r#"<rect x="{x:.1}" y="{y:.1}" width="{span_width:.1}" height="{cell_height:.1}" fill="{}"/>"#,
+                    style.bg
+                );
+            }
+
+            if span.text.trim().is_empty() && style.text_decoration().is_none() {
+                continue;
+            }
+
+            let _ = write!(
+                svg,
+                r#"<text x="{x:.1}" y="{baseline:.1}" textLength="{span_width:.1}" lengthAdjust="spacingAndGlyphs" xml:space="preserve" fill="{}""#,
+                style.fg
+            );
+            if style.bold {
+                svg.push_str(r#" font-weight="bold""#);
+            }
+            if style.dim {
+                svg.push_str(r#" fill-opacity="0.5""#);
+            }
+            if style.italic {
+                svg.push_str(r#" font-style="italic""#);
+            }
+            if let Some(decoration) = style.text_decoration() {
+                let _ = write!(svg, r#" text-decoration="{decoration}""#);
+            }
+            svg.push('>');
+            push_html_escaped(&mut svg, &span.text);
+            svg.push_str("</text>\n");
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Render the buffer as a standalone HTML `<pre>` element, with inline styles.
+pub fn to_html(buffer: &Buffer, options: &SnapshotOptions) -> String {
+    let SnapshotOptions { palette, font_family, font_size, .. } = options;
+
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        r#"<pre style="color:{};background-color:{};font-size:{font_size:.1}px;line-height:1.2;padding:0.5em;font-family:"#,
+        palette.foreground, palette.background,
+    );
+    push_html_escaped(&mut html, font_family);
+    html.push_str("\">");
+
+    for row in buffer.area.top()..buffer.area.bottom() {
+        if row > buffer.area.top() {
+            html.push('\n');
+        }
+        for span in row_runs(buffer, row) {
+            if is_plain(&span) {
+                push_html_escaped(&mut html, &span.text);
+                continue;
+            }
+
+            let style = ResolvedStyle::new(&span, palette);
+            // Opacity would fade the background too. Fade the text into it instead.
+            let fg = if style.dim { mix(style.fg, style.bg) } else { style.fg };
+            let _ = write!(html, r#"<span style="color:{fg};background-color:{};"#, style.bg);
+            if style.bold {
+                html.push_str("font-weight:bold;");
+            }
+            if style.italic {
+                html.push_str("font-style:italic;");
+            }
+            if let Some(decoration) = style.text_decoration() {
+                let _ = write!(html, "text-decoration:{decoration};");
+            }
+            html.push_str("\">");
+            push_html_escaped(&mut html, &span.text);
+            html.push_str("</span>");
+        }
+    }
+
+    html.push_str("</pre>\n");
+    html
+}
+
+/// Doesn't need a `<span>`, the `<pre>`'s style is enough.
+fn is_plain(span: &SpanRun) -> bool {
+    span.fg == Color::Reset && span.bg == Color::Reset && span.modifier.is_empty()
+}
+
+/// Halfway between two colors.
+fn mix(a: Rgb, b: Rgb) -> Rgb {
+    let half = |a: u8, b: u8| ((a as u16 + b as u16) / 2) as u8;
+    Rgb(half(a.0, b.0), half(a.1, b.1), half(a.2, b.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{
+        layout::Rect,
+        style::{Style, Stylize as _},
+    };
+
+    use super::*;
+
+    /// A one-row buffer with `spans` written side by side.
+    fn buffer(width: u16, spans: &[(&str, Style)]) -> Buffer {
+        let mut buffer = Buffer::empty(Rect::new(0, 0, width, 1));
+        let mut x = 0;
+        for (text, style) in spans {
+            x = buffer.set_stringn(x, 0, text, usize::MAX, *style).0;
+        }
+        buffer
+    }
+
+    #[test]
+    fn colors() {
+        let options = SnapshotOptions::default();
+        let buffer = buffer(
+            4,
+            &[("a", Style::new().red().bg(Color::Indexed(200))), ("b", Style::new().fg(Color::Rgb(1, 2, 3)))],
+        );
+
+        let svg = to_svg(&buffer, &options);
+        assert!(svg.contains(r##"<rect width="100%" height="100%" fill="#000000"/>"##), "{svg}");
+        assert!(svg.contains(r##"<rect x="0.0" y="0.0" width="8.4" height="17.0" fill="#ff00d7"/>"##), "{svg}");
+        assert!(svg.contains(r##"fill="#cd0000">a</text>"##), "{svg}");
+        assert!(svg.contains(r##"fill="#010203">b</text>"##), "{svg}");
+
+        let html = to_html(&buffer, &options);
+        assert!(html.starts_with(r##"<pre style="color:#e5e5e5;background-color:#000000;"##), "{html}");
+        assert!(html.contains(r##"<span style="color:#cd0000;background-color:#ff00d7;">a</span>"##), "{html}");
+        assert!(html.contains(r##"<span style="color:#010203;background-color:#000000;">b</span>  </pre>"##), "{html}");
+    }
+
+    #[test]
+    fn modifiers() {
+        let options = SnapshotOptions::default();
+        let buffer = buffer(
+            4,
+            &[
+                ("b", Style::new().bold()),
+                ("i", Style::new().italic()),
+                ("u", Style::new().underlined()),
+                ("r", Style::new().blue().reversed()),
+            ],
+        );
+
+        let svg = to_svg(&buffer, &options);
+        assert!(svg.contains(r#" font-weight="bold">b</text>"#), "{svg}");
+        assert!(svg.contains(r#" font-style="italic">i</text>"#), "{svg}");
+        assert!(svg.contains(r#" text-decoration="underline">u</text>"#), "{svg}");
+        assert!(svg.contains(r##"<rect x="25.2" y="0.0" width="8.4" height="17.0" fill="#0000ee"/>"##), "{svg}");
+        assert!(svg.contains(r##"fill="#000000">r</text>"##), "{svg}");
+
+        let html = to_html(&buffer, &options);
+        assert!(html.contains(r##"<span style="color:#e5e5e5;background-color:#000000;font-weight:bold;">b</span>"##));
+        assert!(html.contains(r##"<span style="color:#e5e5e5;background-color:#000000;font-style:italic;">i</span>"##));
+        assert!(html.contains("text-decoration:underline;\">u</span>"), "{html}");
+        assert!(html.contains(r##"<span style="color:#000000;background-color:#0000ee;">r</span>"##), "{html}");
+    }
+
+    #[test]
+    fn dim_fades_only_the_text() {
+        let buffer = buffer(1, &[("d", Style::new().white().on_blue().dim())]);
+        let html = to_html(&buffer, &SnapshotOptions::default());
+        assert!(html.contains(r##"<span style="color:#7f7ff6;background-color:#0000ee;">d</span>"##), "{html}");
+        assert!(!html.contains("opacity"), "{html}");
+    }
+
+    #[test]
+    fn escaping() {
+        let options = SnapshotOptions { font_family: "\"Fira\" & <co>".into(), ..Default::default() };
+        let buffer = buffer(5, &[("<&\">", Style::new().red())]);
+        for output in [to_svg(&buffer, &options), to_html(&buffer, &options)] {
+            assert!(output.contains("&quot;Fira&quot; &amp; &lt;co&gt;"), "{output}");
+            assert!(output.contains(">&lt;&amp;&quot;&gt;</"), "{output}");
+            assert!(!output.contains("<&"), "{output}");
+        }
+    }
+
+    #[test]
+    fn wide_characters() {
+        let buffer = buffer(6, &[("日本", Style::new().red()), ("x", Style::new().blue())]);
+        let svg = to_svg(&buffer, &SnapshotOptions::default());
+        assert!(svg.contains(r#"<text x="0.0" y="13.3" textLength="33.6""#), "{svg}");
+        assert!(svg.contains(r#"<text x="33.6" y="13.3" textLength="8.4""#), "{svg}");
+        let html = to_html(&buffer, &SnapshotOptions::default());
+        assert!(html.contains(">日本</span><span") && html.ends_with(">x</span> </pre>\n"), "{html}");
+    }
+}
@@ -0,0 +1,961 @@
+//! Terminal capabilities from the terminfo database, for terminals that aren't (quite) xterm.
+//!
+//! By default, [AnsiBackend](crate::backend::AnsiBackend) writes the sequences that xterm and most modern
+//! terminals understand. For others (ex: `screen`, `tmux`, the Linux console, or a real VT100), give it the
+//! [Terminfo] entry for the user's `TERM`, and it'll use that entry's strings for cursor movement, clearing,
+//! colors and attributes, the alternate screen, and cursor visibility:
+//!
+//! ```ignore
+//! let terminfo = match Terminfo::builtin(&term) {
+//!     Some(terminfo) => terminfo,
+//!     // The host can read the compiled entry from /usr/share/terminfo, or wherever it lives:
+//!     None => Terminfo::parse(&host_terminfo_bytes)?,
+//! };
+//! backend.set_terminfo(Some(terminfo));
+//! ```
+//!
+//! With the `terminfo` feature, entries for common terminals are embedded in the crate. (See [BUILTIN_TERMS])
+//!
+//! Other features (ex: mouse capture, images, palette changes) still use xterm sequences, since terminfo doesn't
+//! describe them. Padding (`$<5>`) is ignored.
+
+use std::{error::Error, fmt::Display};
+
+use ratatui::style::{Color, Modifier};
+
+use crate::sgr::Sgr;
+
+/// Terminals with entries embedded in the crate. (With the `terminfo` feature)
+#[cfg(feature = "terminfo")]
+pub const BUILTIN_TERMS: [&str; 12] = [
+    "xterm",
+    "xterm-256color",
+    "screen",
+    "screen-256color",
+    "tmux",
+    "tmux-256color",
+    "linux",
+    "vt100",
+    "vt220",
+    "rxvt",
+    "rxvt-unicode",
+    "ansi",
+];
+
+#[cfg(feature = "terminfo")]
+const BUILTIN: [&[u8]; 12] = [
+    include_bytes!("../terminfo/xterm"),
+    include_bytes!("../terminfo/xterm-256color"),
+    include_bytes!("../terminfo/screen"),
+    include_bytes!("../terminfo/screen-256color"),
+    include_bytes!("../terminfo/tmux"),
+    include_bytes!("../terminfo/tmux-256color"),
+    include_bytes!("../terminfo/linux"),
+    include_bytes!("../terminfo/vt100"),
+    include_bytes!("../terminfo/vt220"),
+    include_bytes!("../terminfo/rxvt"),
+    include_bytes!("../terminfo/rxvt-unicode"),
+    include_bytes!("../terminfo/ansi"),
+];
+
+/// A compiled terminfo entry: what a terminal can do, and the strings to make it do it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Terminfo {
+    names: Vec<String>,
+    booleans: Vec<bool>,
+    numbers: Vec<Option<i32>>,
+    strings: Vec<Option<Vec<u8>>>,
+}
+
+/// Boolean capabilities, by their index in the standard order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BooleanCap {
+    /// `am`: Printing in the last column wraps to the next line.
+    AutoRightMargin = 1,
+
+    /// `xenl`: ...but not until the next character is printed.
+    EatNewlineGlitch = 4,
+
+    /// `bce`: Erasing fills with the current background color.
+    BackColorErase = 28,
+}
+
+/// Numeric capabilities, by their index in the standard order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NumberCap {
+    Columns = 0,
+    Lines = 2,
+
+    /// `colors`
+    MaxColors = 13,
+}
+
+/// String capabilities, by their index in the standard order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StringCap {
+    /// `bel`
+    Bell = 1,
+
+    /// `clear`: Clear the screen, and move the cursor home.
+    ClearScreen = 5,
+
+    /// `el`: Clear to the end of the line.
+    ClrEol = 6,
+
+    /// `ed`: Clear to the end of the screen.
+    ClrEos = 7,
+
+    /// `hpa`: Move to a column.
+    ColumnAddress = 8,
+
+    /// `cup`: Move to (row, column), 0-based.
+    CursorAddress = 10,
+
+    /// `home`
+    CursorHome = 12,
+
+    /// `civis`
+    CursorInvisible = 13,
+
+    /// `cnorm`
+    CursorNormal = 16,
+
+    /// `smacs`: Start using the alternate (line-drawing) character set.
+    EnterAltCharsetMode = 25,
+
+    /// `blink`
+    EnterBlinkMode = 26,
+
+    /// `bold`
+    EnterBoldMode = 27,
+
+    /// `smcup`: Enter the alternate screen. (Or "cursor addressing mode")
+    EnterCaMode = 28,
+
+    /// `dim`
+    EnterDimMode = 30,
+
+    /// `invis`
+    EnterSecureMode = 32,
+
+    /// `rev`
+    EnterReverseMode = 34,
+
+    /// `smul`
+    EnterUnderlineMode = 36,
+
+    /// `ech`: Erase some characters.
+    EraseChars = 37,
+
+    /// `rmacs`
+    ExitAltCharsetMode = 38,
+
+    /// `sgr0`: Turn off all attributes and colors.
+    ExitAttributeMode = 39,
+
+    /// `rmcup`: Leave the alternate screen.
+    ExitCaMode = 40,
+
+    /// `rmul`
+    ExitUnderlineMode = 44,
+
+    /// `cuf`: Move right some columns.
+    ParmRightCursor = 112,
+
+    /// `rep`: Repeat a character.
+    RepeatChar = 121,
+
+    /// `acsc`: Which characters in the alternate character set draw which lines.
+    AcsChars = 146,
+
+    /// `enacs`: Enable the alternate character set.
+    EnaAcs = 155,
+
+    /// `el1`: Clear to the start of the line.
+    ClrBol = 269,
+
+    /// `op`: Set the foreground and background back to the default colors.
+    OrigPair = 297,
+
+    /// `sitm`
+    EnterItalicsMode = 311,
+
+    /// `ritm`
+    ExitItalicsMode = 321,
+
+    /// `setaf`: Set the foreground to a color index.
+    SetAForeground = 359,
+
+    /// `setab`: Set the background to a color index.
+    SetABackground = 360,
+}
+
+/// A problem reading a terminfo entry, or expanding a capability string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TerminfoError {
+    pub message: String,
+}
+
+impl TerminfoError {
+    fn new(message: impl Into<String>) -> Self {
+        Self { message: message.into() }
+    }
+}
+
+impl Display for TerminfoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "terminfo: {}", self.message)
+    }
+}
+
+impl Error for TerminfoError {}
+
+/// The magic numbers for 16-bit, and 32-bit ("extended number") entries.
+const MAGIC_16: u16 = 0o432;
+const MAGIC_32: u16 = 0o1036;
+
+impl Terminfo {
+    /// Read a compiled terminfo entry. (ex: the contents of `/usr/share/terminfo/x/xterm`)
+    ///
+    /// Extended (user-defined) capabilities are ignored.
+    pub fn parse(bytes: &[u8]) -> Result<Self, TerminfoError> {
+        let mut reader = Reader { bytes, offset: 0 };
+        let magic = reader.u16()?;
+        let number_size = match magic {
+            MAGIC_16 => 2,
+            MAGIC_32 => 4,
+            _ => return Err(TerminfoError::new(format!("not a compiled terminfo entry (magic {magic:#o})"))),
+        };
+        let names_size = reader.count()?;
+        let boolean_count = reader.count()?;
+        let number_count = reader.count()?;
+        let string_count = reader.count()?;
+        let table_size = reader.count()?;
+
+        let names = reader.take(names_size)?;
+        let names = names.split(|it| *it == 0).next().unwrap_or_default();
+        let names = String::from_utf8_lossy(names).split('|').map(str::to_string).collect();
+
+        let booleans = reader.take(boolean_count)?.iter().map(|it| *it == 1).collect();
+        // Numbers start on an even byte.
+        if (names_size + boolean_count) % 2 == 1 {
+            reader.take(1)?;
+        }
+
+        let mut numbers = Vec::with_capacity(number_count);
+        for _ in 0..number_count {
+            let number = match number_size {
+                2 => reader.u16()? as i16 as i32,
+                _ => reader.u32()? as i32,
+            };
+            // -1 is absent, and -2 is cancelled.
+            numbers.push((number >= 0).then_some(number));
+        }
+
+        let mut offsets = Vec::with_capacity(string_count);
+        for _ in 0..string_count {
+            offsets.push(reader.u16()? as i16);
+        }
+        let table = reader.take(table_size)?;
+        let strings = offsets
+            .into_iter()
+            .map(|offset| {
+                let start = usize::try_from(offset).ok()?;
+                let rest = table.get(start..)?;
+                let end = rest.iter().position(|it| *it == 0).unwrap_or(rest.len());
+                Some(rest[..end].to_vec())
+            })
+            .collect();
+
+        Ok(Self { names, booleans, numbers, strings })
+    }
+
+    /// The embedded entry for `term`. (ex: the value of `TERM`)
+    ///
+    /// If there's no exact match, tries removing `-suffixes`. (ex: `xterm-kitty` gets `xterm`)
+    #[cfg(feature = "terminfo")]
+    pub fn builtin(term: &str) -> Option<Self> {
+        let mut term = term;
+        loop {
+            if let Some(index) = BUILTIN_TERMS.iter().position(|it| *it == term) {
+                // These are checked when we add them, so they always parse.
+                return Self::parse(BUILTIN[index]).ok();
+            }
+            term = &term[..term.rfind('-')?];
+        }
+    }
+
+    /// This entry, without `cap`. (ex: to test terminals that don't have it)
+    #[cfg(all(test, feature = "terminfo", feature = "parser"))]
+    pub(crate) fn without(mut self, cap: StringCap) -> Self {
+        if let Some(string) = self.strings.get_mut(cap as usize) {
+            *string = None;
+        }
+        self
+    }
+
+    /// The terminal's names. The first is the one you'd use for `TERM`, and the last is a description.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn boolean(&self, cap: BooleanCap) -> bool {
+        self.booleans.get(cap as usize).copied().unwrap_or(false)
+    }
+
+    pub fn number(&self, cap: NumberCap) -> Option<i32> {
+        self.numbers.get(cap as usize).copied().flatten()
+    }
+
+    /// A capability's string, as stored. It may have padding, or parameters to fill in with [tparm].
+    pub fn string(&self, cap: StringCap) -> Option<&[u8]> {
+        self.strings.get(cap as usize)?.as_deref()
+    }
+
+    /// A capability's string, with its parameters filled in, and padding removed.
+    ///
+    /// Returns `None` if the terminal doesn't have the capability.
+    pub fn expand(&self, cap: StringCap, params: &[i32]) -> Option<Vec<u8>> {
+        // If the string is malformed, we can't expect it to do what we want.
+        tparm(self.string(cap)?, params).ok()
+    }
+
+    /// The character to print in the alternate character set for a DEC Special Graphics character.
+    ///
+    /// Returns `None` if the terminal doesn't have the character, or an alternate character set.
+    pub(crate) fn acs_char(&self, dec: u8) -> Option<u8> {
+        self.string(StringCap::EnterAltCharsetMode)?;
+        let Some(acsc) = self.string(StringCap::AcsChars) else {
+            // Without a map, it's the same as a VT100.
+            return Some(dec);
+        };
+        acsc.chunks_exact(2).find(|pair| pair[0] == dec).map(|pair| pair[1])
+    }
+
+    /// The sequence to change the terminal's colors and attributes from `from` to `sgr`.
+    ///
+    /// Like [Sgr::transition], but with this terminal's strings. Attributes it doesn't support are skipped,
+    /// and colors are mapped to the nearest one it supports.
+    pub(crate) fn sgr(&self, sgr: &Sgr, from: Option<&Sgr>) -> Vec<u8> {
+        if from == Some(sgr) {
+            return vec![];
+        }
+        let reset = self.reset_sgr(sgr);
+        match from.and_then(|from| self.incremental_sgr(sgr, from)) {
+            Some(incremental) if incremental.len() <= reset.len() => incremental,
+            _ => reset,
+        }
+    }
+
+    /// `sgr0`, then everything in `sgr`.
+    fn reset_sgr(&self, sgr: &Sgr) -> Vec<u8> {
+        let mut out = self.expand(StringCap::ExitAttributeMode, &[]).unwrap_or_default();
+        for (modifier, cap) in ATTRIBUTES {
+            if sgr.modifier.intersects(modifier) {
+                out.extend(self.expand(cap, &[]).unwrap_or_default());
+            }
+        }
+
+        let colors = self.number(NumberCap::MaxColors).unwrap_or(0);
+        for (color, cap) in [(sgr.fg, StringCap::SetAForeground), (sgr.bg, StringCap::SetABackground)] {
+            if let Some(index) = color_index(color, colors) {
+                out.extend(self.expand(cap, &[index]).unwrap_or_default());
+            }
+        }
+        out
+    }
+
+    /// Only what's different between `from` and `sgr`, if this entry can turn off everything that `from` has
+    /// and `sgr` doesn't. (Terminfo only has strings to turn off underline, italics, and colors.)
+    fn incremental_sgr(&self, sgr: &Sgr, from: &Sgr) -> Option<Vec<u8>> {
+        let sgr0 = self.expand(StringCap::ExitAttributeMode, &[]).unwrap_or_default();
+        // Some entries turn off one thing by turning off everything. (ex: vt100's rmul is `\E[m`)
+        let off = |cap: StringCap| {
+            let sequence = self.expand(cap, &[])?;
+            let resets = sequence == b"\x1b[m" || sequence == b"\x1b[0m" || sgr0.starts_with(&sequence);
+            (!resets).then_some(sequence)
+        };
+
+        let mut out = vec![];
+        let mut removed = from.modifier - sgr.modifier;
+        let exits = [
+            (Modifier::ITALIC, StringCap::ExitItalicsMode),
+            (Modifier::UNDERLINED, StringCap::ExitUnderlineMode),
+        ];
+        for (modifier, cap) in exits {
+            if removed.contains(modifier) {
+                out.extend(off(cap)?);
+                removed -= modifier;
+            }
+        }
+        // Only turn off attributes that this entry could have turned on:
+        if ATTRIBUTES.iter().any(|(modifier, cap)| removed.intersects(*modifier) && self.string(*cap).is_some()) {
+            return None;
+        }
+        for (modifier, cap) in ATTRIBUTES {
+            if sgr.modifier.intersects(modifier) && !from.modifier.intersects(modifier) {
+                out.extend(self.expand(cap, &[]).unwrap_or_default());
+            }
+        }
+
+        let colors = self.number(NumberCap::MaxColors).unwrap_or(0);
+        let (fg, bg) = (color_index(sgr.fg, colors), color_index(sgr.bg, colors));
+        let (from_fg, from_bg) = (color_index(from.fg, colors), color_index(from.bg, colors));
+        // There's only a string for both default colors at once. Then set whichever one isn't the default:
+        let default = (fg.is_none() && from_fg.is_some()) || (bg.is_none() && from_bg.is_some());
+        if default {
+            out.extend(off(StringCap::OrigPair)?);
+        }
+        let changes = [(fg, from_fg, StringCap::SetAForeground), (bg, from_bg, StringCap::SetABackground)];
+        for (index, from_index, cap) in changes {
+            if let Some(index) = index
+                && (default || Some(index) != from_index)
+            {
+                out.extend(self.expand(cap, &[index]).unwrap_or_default());
+            }
+        }
+        Some(out)
+    }
+}
+
+/// Modifiers, and the capability that turns each on.
+const ATTRIBUTES: [(Modifier, StringCap); 7] = [
+    (Modifier::BOLD, StringCap::EnterBoldMode),
+    (Modifier::DIM, StringCap::EnterDimMode),
+    (Modifier::ITALIC, StringCap::EnterItalicsMode),
+    (Modifier::UNDERLINED, StringCap::EnterUnderlineMode),
+    (Modifier::SLOW_BLINK.union(Modifier::RAPID_BLINK), StringCap::EnterBlinkMode),
+    (Modifier::REVERSED, StringCap::EnterReverseMode),
+    (Modifier::HIDDEN, StringCap::EnterSecureMode),
+];
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], TerminfoError> {
+        let taken = self
+            .bytes
+            .get(self.offset..self.offset + count)
+            .ok_or_else(|| TerminfoError::new("entry is truncated"))?;
+        self.offset += count;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, TerminfoError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, TerminfoError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// A count in the header. (-1 means 0.)
+    fn count(&mut self) -> Result<usize, TerminfoError> {
+        Ok(usize::try_from(self.u16()? as i16).unwrap_or(0))
+    }
+}
+
+/// The color index for `color`, on a terminal with `colors` colors. `None` for the default color.
+fn color_index(color: Color, colors: i32) -> Option<i32> {
+    let index = match color {
+        Color::Reset => return None,
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::Gray => 7,
+        Color::DarkGray => 8,
+        Color::LightRed => 9,
+        Color::LightGreen => 10,
+        Color::LightYellow => 11,
+        Color::LightBlue => 12,
+        Color::LightMagenta => 13,
+        Color::LightCyan => 14,
+        Color::White => 15,
+        Color::Indexed(index) => index as i32,
+        Color::Rgb(r, g, b) if colors >= 256 => {
+            let level = |it: u8| (it as i32 * 5 + 127) / 255;
+            16 + 36 * level(r) + 6 * level(g) + level(b)
+        }
+        Color::Rgb(r, g, b) => rgb_to_ansi(r, g, b),
+    };
+    if colors <= 0 {
+        return None;
+    }
+    if index < colors {
+        return Some(index);
+    }
+    if index < 16 {
+        // No bright colors. Use the normal ones.
+        return Some(index - 8);
+    }
+    let (r, g, b) = indexed_rgb(index as u8);
+    Some(rgb_to_ansi(r, g, b) % colors)
+}
+
+/// The nearest of the 16 ANSI colors.
+fn rgb_to_ansi(r: u8, g: u8, b: u8) -> i32 {
+    let bit = |it: u8, bit: i32| if it >= 0x80 { bit } else { 0 };
+    let bright = if r.max(g).max(b) >= 0xc0 { 8 } else { 0 };
+    bit(r, 1) + bit(g, 2) + bit(b, 4) + bright
+}
+
+/// The usual color for an index in xterm's 256-color cube or gray ramp.
+fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        16..=231 => {
+            let level = |it: u8| if it == 0 { 0 } else { 55 + it * 40 };
+            let index = index - 16;
+            (level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        232..=255 => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+        // The 16 ANSI colors vary by terminal. This is only used for indexes we've already checked are higher.
+        _ => (0, 0, 0),
+    }
+}
+
+/// Fill in a capability string's parameters, and remove padding.
+///
+/// Supports the terminfo parameter language: `%p1`…`%p9`, `%d`/`%s`/`%c` (and friends, with widths), `%i`,
+/// arithmetic and logic, constants, variables, and `%?`…`%t`…`%e`…`%;` conditionals. String parameters aren't
+/// supported.
+pub fn tparm(cap: &[u8], params: &[i32]) -> Result<Vec<u8>, TerminfoError> {
+    let mut params: [i32; 9] = std::array::from_fn(|i| params.get(i).copied().unwrap_or(0));
+    let mut vars = [0i32; 52];
+    let mut stack: Vec<i32> = vec![];
+    let mut out = vec![];
+    let mut i = 0;
+
+    let pop = |stack: &mut Vec<i32>| stack.pop().unwrap_or(0);
+    let malformed = |what: &str| TerminfoError::new(format!("malformed capability string: {what}"));
+
+    while i < cap.len() {
+        let byte = cap[i];
+        i += 1;
+        // Padding: $<5>, $<5*>, $<5/>
+        if byte == b'$'
+            && cap.get(i) == Some(&b'<')
+            && let Some(end) = cap[i..].iter().position(|it| *it == b'>')
+        {
+            i += end + 1;
+            continue;
+        }
+        if byte != b'%' {
+            out.push(byte);
+            continue;
+        }
+
+        let Some(&op) = cap.get(i) else { return Err(malformed("ends with %")) };
+        i += 1;
+        match op {
+            b'%' => out.push(b'%'),
+            b'c' => out.push(pop(&mut stack) as u8),
+            b'p' => {
+                let n = cap.get(i).and_then(|it| it.checked_sub(b'1')).filter(|it| *it < 9);
+                let Some(n) = n else { return Err(malformed("%p needs 1-9")) };
+                i += 1;
+                stack.push(params[n as usize]);
+            }
+            b'P' | b'g' => {
+                let var = match cap.get(i) {
+                    Some(name @ b'a'..=b'z') => (name - b'a') as usize,
+                    Some(name @ b'A'..=b'Z') => 26 + (name - b'A') as usize,
+                    _ => return Err(malformed("bad variable name")),
+                };
+                i += 1;
+                if op == b'P' {
+                    vars[var] = pop(&mut stack);
+                } else {
+                    stack.push(vars[var]);
+                }
+            }
+            b'\'' => {
+                let Some(&ch) = cap.get(i) else { return Err(malformed("%' needs a character")) };
+                if cap.get(i + 1) != Some(&b'\'') {
+                    return Err(malformed("unterminated %'"));
+                }
+                i += 2;
+                stack.push(ch as i32);
+            }
+            b'{' => {
+                let Some(end) = cap[i..].iter().position(|it| *it == b'}') else {
+                    return Err(malformed("unterminated %{"));
+                };
+                let number = std::str::from_utf8(&cap[i..i + end]).ok().and_then(|it| it.parse().ok());
+                let Some(number) = number else { return Err(malformed("bad %{number}")) };
+                i += end + 1;
+                stack.push(number);
+            }
+            b'l' => {
+                // The length of a string parameter. We don't have those.
+                pop(&mut stack);
+                stack.push(0);
+            }
+            b'+' | b'-' | b'*' | b'/' | b'm' | b'&' | b'|' | b'^' | b'=' | b'>' | b'<' | b'A' | b'O' => {
+                let b = pop(&mut stack);
+                let a = pop(&mut stack);
+                stack.push(match op {
+                    b'+' => a.wrapping_add(b),
+                    b'-' => a.wrapping_sub(b),
+                    b'*' => a.wrapping_mul(b),
+                    b'/' => a.checked_div(b).unwrap_or(0),
+                    b'm' => a.checked_rem(b).unwrap_or(0),
+                    b'&' => a & b,
+                    b'|' => a | b,
+                    b'^' => a ^ b,
+                    b'=' => (a == b) as i32,
+                    b'>' => (a > b) as i32,
+                    b'<' => (a < b) as i32,
+                    b'A' => (a != 0 && b != 0) as i32,
+                    _ => (a != 0 || b != 0) as i32,
+                });
+            }
+            b'!' => {
+                let a = pop(&mut stack);
+                stack.push((a == 0) as i32);
+            }
+            b'~' => {
+                let a = pop(&mut stack);
+                stack.push(!a);
+            }
+            b'i' => {
+                params[0] = params[0].wrapping_add(1);
+                params[1] = params[1].wrapping_add(1);
+            }
+            b'?' | b';' => {}
+            b't' => {
+                if pop(&mut stack) == 0 {
+                    // Skip to this conditional's %e (and run the else), or to its end.
+                    i = skip_conditional(cap, i, true);
+                }
+            }
+            b'e' => {
+                // We ran the "then" part, so skip the rest.
+                i = skip_conditional(cap, i, false);
+            }
+            _ => {
+                // A formatted output: %[[:]flags][width[.precision]][doxXs]
+                let start = i - 1;
+                let end = cap[start..]
+                    .iter()
+                    .position(|it| matches!(it, b'd' | b'o' | b'x' | b'X' | b's'))
+                    .map(|it| start + it)
+                    .ok_or_else(|| malformed("unknown % code"))?;
+                let spec = &cap[start..end];
+                let spec = spec.strip_prefix(b":").unwrap_or(spec);
+                let value = pop(&mut stack);
+                out.extend(format_number(value, spec, cap[end]).ok_or_else(|| malformed("bad format"))?);
+                i = end + 1;
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// The position after the `%e` or `%;` that ends the current part of a conditional, starting at `i`.
+///
+/// If `to_else` is false, only stops at `%;`.
+fn skip_conditional(cap: &[u8], mut i: usize, to_else: bool) -> usize {
+    let mut depth = 0;
+    while i + 1 < cap.len() {
+        if cap[i] != b'%' {
+            i += 1;
+            continue;
+        }
+        let op = cap[i + 1];
+        i += 2;
+        match op {
+            b'?' => depth += 1,
+            b';' if depth == 0 => return i,
+            b';' => depth -= 1,
+            b'e' if depth == 0 && to_else => return i,
+            // Skip the argument of codes that take one, in case it's a '%'.
+            b'p' | b'P' | b'g' => i += 1,
+            b'\'' => i += 2,
+            _ => {}
+        }
+    }
+    cap.len()
+}
+
+/// Format a number like printf would, given the flags, width and precision between the `%` and `conversion`.
+fn format_number(value: i32, spec: &[u8], conversion: u8) -> Option<Vec<u8>> {
+    let spec = std::str::from_utf8(spec).ok()?;
+    let flags_end = spec.find(|it: char| !matches!(it, '-' | '+' | '#' | ' ')).unwrap_or(spec.len());
+    let (flags, rest) = spec.split_at(flags_end);
+    let (width, precision) = match rest.split_once('.') {
+        Some((width, precision)) => (width, Some(precision.parse::<usize>().ok()?)),
+        None => (rest, None),
+    };
+    let zero_pad = width.starts_with('0');
+    let width: usize = if width.is_empty() { 0 } else { width.parse().ok()? };
+
+    let mut digits = match conversion {
+        b'o' => format!("{:o}", value),
+        b'x' => format!("{:x}", value),
+        b'X' => format!("{:X}", value),
+        _ => value.unsigned_abs().to_string(),
+    };
+    if let Some(precision) = precision
+        && digits.len() < precision
+    {
+        digits = "0".repeat(precision - digits.len()) + &digits;
+    }
+    let sign = match () {
+        _ if matches!(conversion, b'd' | b's') && value < 0 => "-",
+        _ if flags.contains('+') && matches!(conversion, b'd' | b's') => "+",
+        _ if flags.contains(' ') && matches!(conversion, b'd' | b's') => " ",
+        _ if flags.contains('#') && conversion == b'o' && !digits.starts_with('0') => "0",
+        _ if flags.contains('#') && conversion == b'x' => "0x",
+        _ if flags.contains('#') && conversion == b'X' => "0X",
+        _ => "",
+    };
+    let len = sign.len() + digits.len();
+    let padding = width.saturating_sub(len);
+    let formatted = if flags.contains('-') {
+        format!("{sign}{digits}{}", " ".repeat(padding))
+    } else if zero_pad && precision.is_none() {
+        format!("{sign}{}{digits}", "0".repeat(padding))
+    } else {
+        format!("{}{sign}{digits}", " ".repeat(padding))
+    };
+    Some(formatted.into_bytes())
+}
+
+#[cfg(all(test, feature = "terminfo"))]
+mod tests {
+    use ratatui::layout::Size;
+
+    use super::*;
+    use crate::vt::VirtualTerminal;
+
+    const STRING_CAPS: [StringCap; 32] = [
+        StringCap::Bell,
+        StringCap::ClearScreen,
+        StringCap::ClrEol,
+        StringCap::ClrEos,
+        StringCap::ColumnAddress,
+        StringCap::CursorAddress,
+        StringCap::CursorHome,
+        StringCap::CursorInvisible,
+        StringCap::CursorNormal,
+        StringCap::EnterAltCharsetMode,
+        StringCap::EnterBlinkMode,
+        StringCap::EnterBoldMode,
+        StringCap::EnterCaMode,
+        StringCap::EnterDimMode,
+        StringCap::EnterSecureMode,
+        StringCap::EnterReverseMode,
+        StringCap::EnterUnderlineMode,
+        StringCap::EraseChars,
+        StringCap::ExitAltCharsetMode,
+        StringCap::ExitAttributeMode,
+        StringCap::ExitCaMode,
+        StringCap::ExitUnderlineMode,
+        StringCap::ParmRightCursor,
+        StringCap::RepeatChar,
+        StringCap::AcsChars,
+        StringCap::EnaAcs,
+        StringCap::ClrBol,
+        StringCap::OrigPair,
+        StringCap::EnterItalicsMode,
+        StringCap::ExitItalicsMode,
+        StringCap::SetAForeground,
+        StringCap::SetABackground,
+    ];
+
+    fn entry(term: &str) -> Terminfo {
+        Terminfo::builtin(term).unwrap()
+    }
+
+    /// A tiny xorshift, so the fuzz tests are repeatable.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    #[test]
+    fn every_builtin_parses() {
+        for (term, bytes) in BUILTIN_TERMS.iter().zip(BUILTIN) {
+            let terminfo = Terminfo::parse(bytes).unwrap_or_else(|err| panic!("{term}: {err}"));
+            // (Debian's rxvt is a link to rxvt-color.)
+            assert!(terminfo.names()[0].starts_with(term), "{term}: {:?}", terminfo.names());
+            assert_eq!(Terminfo::builtin(term), Some(terminfo.clone()));
+            assert!(terminfo.string(StringCap::CursorAddress).is_some(), "{term}");
+            // (Not every string is for tparm. ex: xterm's u9 is a pattern for parsing replies.)
+            for cap in STRING_CAPS {
+                if let Some(string) = terminfo.string(cap) {
+                    let expanded = tparm(string, &[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+                    assert!(expanded.is_ok(), "{term} {cap:?}: {expanded:?}");
+                }
+            }
+        }
+        assert_eq!(Terminfo::builtin("xterm-kitty").unwrap().names()[0], "xterm");
+        assert_eq!(Terminfo::builtin("nonesuch"), None);
+    }
+
+    #[test]
+    fn expansions() {
+        for term in ["xterm", "screen", "linux"] {
+            let terminfo = entry(term);
+            let expand = |cap, params: &[i32]| String::from_utf8(terminfo.expand(cap, params).unwrap()).unwrap();
+            assert_eq!(expand(StringCap::CursorAddress, &[5, 10]), "\x1b[6;11H", "{term}");
+            assert_eq!(expand(StringCap::SetAForeground, &[1]), "\x1b[31m", "{term}");
+            assert_eq!(expand(StringCap::SetABackground, &[3]), "\x1b[43m", "{term}");
+            let sgr0 = if term == "xterm" { "\x1b(B\x1b[m" } else { "\x1b[m\x0f" };
+            assert_eq!(expand(StringCap::ExitAttributeMode, &[]), sgr0, "{term}");
+            assert_eq!(terminfo.number(NumberCap::MaxColors), Some(8), "{term}");
+        }
+
+        let xterm = entry("xterm-256color");
+        assert_eq!(xterm.expand(StringCap::SetAForeground, &[12]).unwrap(), b"\x1b[94m");
+        assert_eq!(xterm.expand(StringCap::SetABackground, &[200]).unwrap(), b"\x1b[48;5;200m");
+        // Padding is removed:
+        assert_eq!(entry("vt100").expand(StringCap::CursorAddress, &[0, 0]).unwrap(), b"\x1b[1;1H");
+    }
+
+    #[test]
+    fn truncated_entries() {
+        for bytes in BUILTIN {
+            for len in 0..bytes.len() {
+                // Most are errors, but some only cut off the extended capabilities, which we ignore:
+                let _ = Terminfo::parse(&bytes[..len]);
+            }
+        }
+        assert!(Terminfo::parse(&BUILTIN[0][..20]).is_err());
+        assert!(Terminfo::parse(b"").is_err());
+        assert!(Terminfo::parse(b"\x1a\x01\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff").is_ok());
+    }
+
+    #[test]
+    fn corrupted_entries() {
+        let mut rng = Rng(0x7e44_1f0f);
+        for _ in 0..2000 {
+            let mut bytes = BUILTIN[rng.below(BUILTIN.len())].to_vec();
+            for _ in 0..1 + rng.below(8) {
+                let index = rng.below(bytes.len());
+                bytes[index] = rng.next() as u8;
+            }
+            if let Ok(terminfo) = Terminfo::parse(&bytes) {
+                for string in terminfo.strings.iter().flatten() {
+                    let _ = tparm(string, &[1, 2]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_strings() {
+        for cap in [
+            "%", "%p", "%p0", "%pa", "%P", "%g1", "%'", "%'a", "%{", "%{x}", "%z", "%5", "%:-", "%.d", "%?%t", "%e%;",
+            "%?%p1%t%e", "%d", "%c%c%c", "%p1%{0}%/%d", "%p1%{0}%m%d", "$<", "$<5", "%{99999999999}",
+        ] {
+            // Errors are fine, as long as there's no panic:
+            let _ = tparm(cap.as_bytes(), &[i32::MAX, i32::MIN]);
+        }
+        assert!(tparm(b"%", &[]).is_err());
+        assert!(tparm(b"%p0", &[]).is_err());
+        assert!(tparm(b"%{12", &[]).is_err());
+        assert_eq!(tparm(b"%p1%{0}%/%d", &[5]).unwrap(), b"0");
+
+        let alphabet = b"%p1234{}'?te;+-*/m&|^=<>!~AOicdsxX:.#0 $<>aP";
+        let mut rng = Rng(0x5eed);
+        for _ in 0..20_000 {
+            let cap: Vec<u8> = (0..rng.below(16)).map(|_| alphabet[rng.below(alphabet.len())]).collect();
+            let _ = tparm(&cap, &[i32::MAX, i32::MIN, -1]);
+        }
+    }
+
+    /// The style a terminal ends up with after `sequence`.
+    fn style_after(vt: &mut VirtualTerminal, sequence: &[u8]) -> Sgr {
+        vt.write(sequence);
+        vt.write(b"x\r");
+        Sgr::of(&vt.buffer().content[0])
+    }
+
+    #[test]
+    fn sgr_is_incremental() {
+        let xterm = entry("xterm");
+        let sgr = |to: Sgr, from: Sgr| String::from_utf8(xterm.sgr(&to, Some(&from))).unwrap();
+        let plain = Sgr::default();
+        let bold = Sgr { modifier: Modifier::BOLD, ..plain };
+        let bold_underlined = Sgr { modifier: Modifier::BOLD | Modifier::UNDERLINED, ..plain };
+        let red = Sgr { fg: Color::Red, ..bold };
+        assert_eq!(sgr(bold, plain), "\x1b[1m");
+        assert_eq!(sgr(bold_underlined, bold), "\x1b[4m");
+        assert_eq!(sgr(bold, bold_underlined), "\x1b[24m");
+        assert_eq!(sgr(red, bold), "\x1b[31m");
+        assert_eq!(sgr(bold, red), "\x1b[39;49m");
+        let blue = Sgr { bg: Color::Blue, ..bold };
+        assert_eq!(sgr(blue, Sgr { fg: Color::Red, ..blue }), "\x1b[39;49m\x1b[44m");
+        // There's no string to turn off bold:
+        assert_eq!(sgr(plain, bold), "\x1b(B\x1b[m");
+        assert_eq!(sgr(bold, bold), "");
+        // Without a previous state, always reset:
+        assert_eq!(xterm.sgr(&bold, None), b"\x1b(B\x1b[m\x1b[1m");
+
+        // vt100's rmul turns off everything, so it's a reset:
+        let vt100 = entry("vt100");
+        let underlined = Sgr { modifier: Modifier::UNDERLINED, ..bold_underlined };
+        assert!(vt100.sgr(&bold, Some(&underlined)).starts_with(b"\x1b[m\x0f"));
+    }
+
+    #[test]
+    fn sgr_transitions() {
+        let colors = [Color::Reset, Color::Red, Color::LightBlue, Color::Rgb(200, 30, 30)];
+        let modifiers = [
+            Modifier::empty(),
+            Modifier::BOLD,
+            Modifier::ITALIC,
+            Modifier::UNDERLINED,
+            Modifier::BOLD | Modifier::UNDERLINED,
+            Modifier::ITALIC | Modifier::REVERSED | Modifier::UNDERLINED,
+        ];
+        let mut styles = vec![];
+        for fg in colors {
+            for bg in colors {
+                for modifier in modifiers {
+                    styles.push(Sgr { fg, bg, modifier });
+                }
+            }
+        }
+
+        for term in ["xterm", "xterm-256color", "screen", "linux", "vt100", "ansi"] {
+            let terminfo = entry(term);
+            let mut vt = VirtualTerminal::new(Size::new(2, 1));
+            for from in &styles {
+                let before = style_after(&mut vt, &terminfo.sgr(from, None));
+                for to in &styles {
+                    // Wherever we start, we should end up where we would have from scratch:
+                    let expected = style_after(&mut vt, &terminfo.sgr(to, None));
+                    style_after(&mut vt, &terminfo.sgr(from, None));
+                    let actual = style_after(&mut vt, &terminfo.sgr(to, Some(from)));
+                    assert_eq!(actual, expected, "{term}: {before:?} -> {expected:?}");
+                    let incremental = terminfo.sgr(to, Some(from));
+                    assert!(incremental.len() <= terminfo.sgr(to, None).len(), "{term}: {incremental:?}");
+                }
+            }
+        }
+    }
+}
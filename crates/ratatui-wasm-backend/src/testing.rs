@@ -0,0 +1,387 @@
+//! Test your app without a real terminal, or JavaScript.
+//!
+//! A [TestHost] stands in for the terminal: backends you get from it write to memory, and it runs what they
+//! wrote through a [VirtualTerminal], so you can check what the user would see. An [AppTest] runs an [App] on
+//! one, with scripted input and a fake clock:
+//!
+//! ```ignore
+//! let mut test = AppTest::new(MyApp::default(), Size::new(40, 10))?;
+//! test.keys("abc<Tab><C-c>")?;
+//! assert_eq!(test.text().lines().next(), Some("abc"));
+//!
+//! test.resize(Size::new(20, 5))?;
+//! assert_eq!(test.screen(), &expected_buffer);
+//! assert!(!test.host().output().windows(4).any(|it| it == b"\x1b[2J"), "shouldn't clear the screen");
+//! ```
+//!
+//! Scripts use [keymap](crate::keymap) syntax.
+
+use std::{
+    cell::{Cell, RefCell},
+    io::{Error as IOError, ErrorKind, Result as IOResult, Write},
+    rc::Rc,
+    time::Duration,
+};
+
+use ratatui::{Terminal, buffer::Buffer, layout::Size};
+
+use crate::{
+    app::{App, Control},
+    backend::AnsiBackend,
+    ctrl::Erase,
+    host::{Clock as _, FakeClock},
+    keymap::parse_keys,
+    run::Runner,
+    vt::VirtualTerminal,
+};
+
+/// The bytes a terminal would send if you typed `script`. (ex: `"abc<Tab><C-c>"`)
+pub fn keys(script: &str) -> IOResult<Vec<u8>> {
+    let keys = parse_keys(script).map_err(|err| IOError::new(ErrorKind::InvalidInput, err))?;
+    Ok(keys.iter().flat_map(|key| key.to_bytes(Erase::default())).collect())
+}
+
+/// An in-memory terminal, of a size you control.
+pub struct TestHost {
+    output: SharedOutput,
+    size: Rc<Cell<Size>>,
+    vt: VirtualTerminal,
+
+    /// How much output we've run through `vt`.
+    seen: usize,
+
+    /// How much output [TestHost::take_output] has returned.
+    taken: usize,
+}
+
+#[derive(Clone, Default)]
+struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        Ok(())
+    }
+}
+
+impl TestHost {
+    pub fn new(size: Size) -> Self {
+        Self {
+            output: SharedOutput::default(),
+            size: Rc::new(Cell::new(size)),
+            vt: VirtualTerminal::new(size),
+            seen: 0,
+            taken: 0,
+        }
+    }
+
+    /// A backend which writes to this terminal.
+    pub fn backend(&self) -> AnsiBackend {
+        AnsiBackend::with_io(self.output.clone(), self.size.clone())
+    }
+
+    pub fn terminal(&self) -> IOResult<Terminal<AnsiBackend>> {
+        Terminal::new(self.backend())
+    }
+
+    pub fn size(&self) -> Size {
+        self.size.get()
+    }
+
+    /// Change the size of the terminal, as if the user resized the window.
+    ///
+    /// Backends see the new size the next time they ask. (ex: on the next [Terminal::draw])
+    pub fn resize(&mut self, size: Size) {
+        self.sync();
+        self.size.set(size);
+        self.vt.resize(size);
+    }
+
+    /// Everything written to the terminal so far.
+    pub fn output(&self) -> Vec<u8> {
+        self.output.0.borrow().clone()
+    }
+
+    /// What's been written to the terminal since the last call.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        let output = self.output.0.borrow();
+        let new = output[self.taken..].to_vec();
+        self.taken = output.len();
+        new
+    }
+
+    /// What the screen shows now.
+    pub fn screen(&mut self) -> &Buffer {
+        self.vt().buffer()
+    }
+
+    /// What the screen shows now, as text. (See [VirtualTerminal::text])
+    pub fn text(&mut self) -> String {
+        self.vt().text()
+    }
+
+    /// The terminal emulator, up to date with all output. (ex: to check the cursor position)
+    pub fn vt(&mut self) -> &mut VirtualTerminal {
+        self.sync();
+        &mut self.vt
+    }
+
+    fn sync(&mut self) {
+        let output = self.output.0.borrow();
+        self.vt.write(&output[self.seen..]);
+        self.seen = output.len();
+    }
+}
+
+/// Runs an [App] on a [TestHost], with a [FakeClock].
+pub struct AppTest<A> {
+    host: TestHost,
+    runner: Runner<A>,
+    clock: FakeClock,
+}
+
+impl<A: App> AppTest<A> {
+    /// Start the app, and draw its first frame.
+    pub fn new(app: A, size: Size) -> IOResult<Self> {
+        let host = TestHost::new(size);
+        let clock = FakeClock::new();
+        let runner = Runner::new(host.backend(), app, clock.clone(), clock.clone())?;
+        Ok(Self { host, runner, clock })
+    }
+
+    /// Type some keys. (ex: `"abc<Tab><C-c>"`)
+    ///
+    /// Each key is sent separately, like a user typing. Returns [Control::Quit] if the app quit.
+    pub fn keys(&mut self, script: &str) -> IOResult<Control> {
+        let keys = parse_keys(script).map_err(|err| IOError::new(ErrorKind::InvalidInput, err))?;
+        let mut control = Control::Continue;
+        for key in keys {
+            control = self.runner.input(&key.to_bytes(Erase::default()))?;
+        }
+        Ok(control)
+    }
+
+    /// Send raw input bytes, all at once. (ex: a paste, or a mouse event)
+    pub fn input(&mut self, bytes: &[u8]) -> IOResult<Control> {
+        self.runner.input(bytes)
+    }
+
+    /// Resize the terminal, and let the app know.
+    pub fn resize(&mut self, size: Size) -> IOResult<Control> {
+        self.host.resize(size);
+        self.runner.resize(size)
+    }
+
+    /// Move the clock forward, and run any timer ticks that are due.
+    ///
+    /// Like a real host, each timeout fires at its own time, so a long jump runs every tick along the way. (Including
+    /// ones the app asks for as it goes.)
+    pub fn advance(&mut self, by: Duration) -> IOResult<Control> {
+        let until = self.clock.now() + by;
+        while let Some(due) = self.clock.next_timeout().filter(|it| *it <= until) {
+            self.clock.set(due.max(self.clock.now()));
+            self.clock.take_due();
+            if self.runner.tick()? == Control::Quit {
+                return Ok(Control::Quit);
+            }
+        }
+        self.clock.set(until);
+        Ok(if self.runner.is_done() { Control::Quit } else { Control::Continue })
+    }
+
+    pub fn screen(&mut self) -> &Buffer {
+        self.host.screen()
+    }
+
+    pub fn text(&mut self) -> String {
+        self.host.text()
+    }
+
+    pub fn host(&mut self) -> &mut TestHost {
+        &mut self.host
+    }
+
+    pub fn clock(&self) -> &FakeClock {
+        &self.clock
+    }
+
+    pub fn runner(&mut self) -> &mut Runner<A> {
+        &mut self.runner
+    }
+
+    pub fn app(&self) -> &A {
+        self.runner.app()
+    }
+
+    pub fn app_mut(&mut self) -> &mut A {
+        self.runner.app_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{Frame, layout::Rect, text::Line, widgets::Paragraph};
+
+    use super::*;
+    use crate::{
+        app::Event,
+        input::{KeyCode, KeyModifiers, Parser},
+        keymap::Key,
+    };
+
+    /// Two text fields. Tab switches between them, and Ctrl-C quits.
+    #[derive(Default)]
+    struct Fields {
+        fields: [String; 2],
+        focus: usize,
+        size: Option<Size>,
+    }
+
+    impl App for Fields {
+        fn handle_event(&mut self, event: Event) -> Control {
+            let key = match event {
+                Event::Input(seq) => Key::from_sequence(&seq, Erase::default()),
+                Event::Resize(size) => {
+                    self.size = Some(size);
+                    return Control::Continue;
+                }
+            };
+            match key.map(|it| (it.code, it.modifiers)) {
+                Some((KeyCode::Char('c'), KeyModifiers::CONTROL)) => return Control::Quit,
+                Some((KeyCode::Tab, _)) => self.focus = 1 - self.focus,
+                Some((KeyCode::Char(ch), modifiers)) if modifiers.is_empty() => self.fields[self.focus].push(ch),
+                _ => return Control::Unchanged,
+            }
+            Control::Continue
+        }
+
+        fn render(&mut self, frame: &mut Frame) {
+            let lines: Vec<Line> = self
+                .fields
+                .iter()
+                .enumerate()
+                .map(|(i, field)| Line::from(format!("{}{field}", if i == self.focus { ">" } else { " " })))
+                .collect();
+            frame.render_widget(Paragraph::new(lines), frame.area());
+        }
+    }
+
+    #[test]
+    fn app() {
+        let mut test = AppTest::new(Fields::default(), Size::new(6, 3)).unwrap();
+        assert_eq!(test.text(), ">\n\n");
+        let output = test.host().take_output();
+        assert!(output.windows(8).any(|it| it == b"\x1b[?1049h"), "{output:?}");
+
+        assert_eq!(test.keys("abc<Tab>").unwrap(), Control::Continue);
+        assert_eq!(test.text(), " abc\n>\n");
+        assert_eq!(test.keys("de").unwrap(), Control::Continue);
+        assert_eq!(test.screen(), &Buffer::with_lines([" abc  ", ">de   ", "      "]));
+        // Only the changes were drawn:
+        let output = String::from_utf8(test.host().take_output()).unwrap();
+        assert!(output.contains('e') && !output.contains("abc"), "{output:?}");
+
+        assert_eq!(test.resize(Size::new(3, 2)).unwrap(), Control::Continue);
+        assert_eq!(test.app().size, Some(Size::new(3, 2)));
+        assert_eq!(test.screen(), &Buffer::with_lines([" ab", ">de"]));
+
+        assert_eq!(test.keys("<C-c>").unwrap(), Control::Quit);
+        assert!(test.runner().is_done());
+        let output = test.host().take_output();
+        assert!(output.windows(8).any(|it| it == b"\x1b[?1049l"), "{output:?}");
+        // Nothing more happens after quitting:
+        assert_eq!(test.keys("x").unwrap(), Control::Quit);
+        assert_eq!(test.app().fields, ["abc", "de"]);
+    }
+
+    #[test]
+    fn keys_round_trip() {
+        let mut codes: Vec<KeyCode> = "aZ1 <~\u{e9}".chars().map(KeyCode::Char).collect();
+        codes.extend([
+            KeyCode::Backspace,
+            KeyCode::Enter,
+            KeyCode::Left,
+            KeyCode::Right,
+            KeyCode::Up,
+            KeyCode::Down,
+            KeyCode::Home,
+            KeyCode::End,
+            KeyCode::PageUp,
+            KeyCode::PageDown,
+            KeyCode::Tab,
+            KeyCode::BackTab,
+            KeyCode::Delete,
+            KeyCode::Insert,
+            KeyCode::Esc,
+        ]);
+        codes.extend((1..=20).map(KeyCode::F));
+        let modifiers = [
+            KeyModifiers::empty(),
+            KeyModifiers::SHIFT,
+            KeyModifiers::ALT,
+            KeyModifiers::CONTROL,
+            KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+            KeyModifiers::CONTROL | KeyModifiers::ALT,
+        ];
+
+        for code in codes {
+            for modifiers in modifiers {
+                let key = Key::new(code, modifiers);
+                let bytes = key.to_bytes(Erase::default());
+                let mut parser = Parser::default();
+                parser.advance(&bytes, false);
+                let seqs: Vec<_> = parser.by_ref().collect();
+                let parsed: Vec<_> = seqs.iter().filter_map(|it| Key::from_sequence(it, Erase::default())).collect();
+                assert_eq!(parsed, [key], "{key} sent {bytes:?}, parsed as {seqs:?}");
+            }
+        }
+    }
+
+    /// Ticks every 100ms.
+    #[derive(Default)]
+    struct Ticker {
+        next: Duration,
+        ticks: Vec<Duration>,
+    }
+
+    impl App for Ticker {
+        fn handle_event(&mut self, _event: Event) -> Control {
+            Control::Unchanged
+        }
+
+        fn update(&mut self, now: Duration) -> Control {
+            self.ticks.push(now);
+            self.next = now + Duration::from_millis(100);
+            Control::Continue
+        }
+
+        fn next_tick(&self) -> Option<Duration> {
+            Some(self.next)
+        }
+
+        fn render(&mut self, frame: &mut Frame) {
+            let text = self.ticks.len().to_string();
+            frame.render_widget(Paragraph::new(text), Rect::new(0, 0, 3, 1));
+        }
+    }
+
+    #[test]
+    fn advance_runs_every_tick() {
+        let mut test = AppTest::new(Ticker::default(), Size::new(3, 1)).unwrap();
+        test.advance(Duration::ZERO).unwrap();
+        assert_eq!(test.app().ticks, [Duration::ZERO]);
+
+        test.advance(Duration::from_millis(350)).unwrap();
+        let millis: Vec<_> = test.app().ticks.iter().map(Duration::as_millis).collect();
+        assert_eq!(millis, [0, 100, 200, 300]);
+        assert_eq!(test.clock().now(), Duration::from_millis(350));
+        assert_eq!(test.text(), "4");
+
+        test.advance(Duration::from_millis(50)).unwrap();
+        assert_eq!(test.app().ticks.len(), 5);
+    }
+}
@@ -1,8 +1,14 @@
 //! Types we expect to receive from JavaScript:
 
+use std::io::Result as IOResult;
+use std::time::Duration;
+
 use ratatui::layout::Size;
 use wasm_bindgen::prelude::*;
 
+use crate::error::{BackendError, JsException};
+use crate::host::{Clock, GetSize, Timer};
+
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen]
@@ -51,6 +57,12 @@ impl JsTermSizeCallback {
     }
 }
 
+impl GetSize for JsTermSizeCallback {
+    fn get_size(&self) -> IOResult<Size> {
+        self.get().map_err(|err| BackendError::SizeCallbackThrew(err.into()).into())
+    }
+}
+
 /// console.log:
 #[wasm_bindgen]
 extern "C" {
@@ -78,4 +90,100 @@ extern "C" {
 
     #[wasm_bindgen(method,catch)]
     pub fn call(this: &JsWriter, value: JsValue, bytes: Box<[u8]>) -> Result<usize, JsValue>;
-}
\ No newline at end of file
+}
+
+impl std::io::Write for JsWriter {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        self.call(JsValue::NULL, buf.into())
+            .map_err(|err| BackendError::WriterThrew(err.into()).into())
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        // The JavaScript side writes synchronously.
+        Ok(())
+    }
+}
+
+/// Exceptions:
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen]
+    type Error;
+
+    #[wasm_bindgen(method, getter)]
+    fn name(this: &Error) -> String;
+
+    #[wasm_bindgen(method, getter)]
+    fn message(this: &Error) -> String;
+
+    #[wasm_bindgen(method, getter)]
+    fn stack(this: &Error) -> Option<String>;
+}
+
+impl From<JsValue> for JsException {
+    fn from(value: JsValue) -> Self {
+        // We usually expect an `Error` to be thrown, but JS doesn't guarantee that.
+        if let Some(error) = value.dyn_ref::<Error>() {
+            return JsException {
+                name: error.name(),
+                message: error.message(),
+                stack: error.stack(),
+            };
+        }
+
+        let message = match value.as_string() {
+            Some(message) => message,
+            None => format!("{value:?}"),
+        };
+        JsException {
+            name: value.js_typeof().as_string().unwrap_or_default(),
+            message,
+            stack: None,
+        }
+    }
+}
+
+/// performance.now():
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
+}
+
+/// A [Clock] that uses JavaScript's `performance.now()`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsClock;
+
+impl Clock for JsClock {
+    fn now(&self) -> Duration {
+        Duration::from_secs_f64(performance_now().max(0.0) / 1000.0)
+    }
+}
+
+#[wasm_bindgen(typescript_custom_section)]
+const TSSetTimeoutCallback: &'static str = r#"
+/**
+ * Asks the host to call back after `delayMs` milliseconds. (ex: `(delayMs) => setTimeout(() => main.tick(), delayMs)`)
+ */
+type SetTimeoutCallback = {
+    (delayMs: number): void
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    /// A [Timer] that calls a JavaScript function, which should call back into your app. (ex: with `setTimeout()`)
+    #[wasm_bindgen(typescript_type = "SetTimeoutCallback")]
+    pub type JsTimer;
+
+    #[wasm_bindgen(method, catch)]
+    fn call(this: &JsTimer, value: JsValue, delay_ms: f64) -> Result<JsValue, JsValue>;
+}
+
+impl Timer for JsTimer {
+    fn set_timeout(&self, delay: Duration) -> IOResult<()> {
+        self.call(JsValue::NULL, delay.as_secs_f64() * 1000.0)
+            .map(drop)
+            .map_err(|err| BackendError::TimerThrew(err.into()).into())
+    }
+}
@@ -0,0 +1,917 @@
+//! A small virtual terminal, which turns ANSI output back into a Ratatui [Buffer].
+//!
+//! This is the inverse of [AnsiBackend](crate::backend::AnsiBackend). It understands the sequences
+//! that the backend outputs, plus the common xterm sequences that other programs use. (Cursor movement,
+//! erasing, scrolling, SGR styles, the alternate screen, and the DEC line-drawing character set.)
+//!
+//! It's not a complete terminal emulator. It doesn't handle things like mouse reporting modes, and it
+//! only answers the queries in [VirtualTerminal::take_responses].
+
+use std::mem;
+
+use ratatui::{
+    buffer::{Buffer, Cell},
+    layout::{Position, Rect, Size},
+    style::{Color, Modifier},
+};
+use unicode_width::UnicodeWidthChar as _;
+
+/// Turns a stream of terminal output into a grid of cells.
+pub struct VirtualTerminal {
+    size: Size,
+    primary: Buffer,
+    alternate: Buffer,
+    alternate_active: bool,
+
+    cursor: Position,
+    saved_cursor: Option<SavedCursor>,
+    cursor_visible: bool,
+
+    /// Set after printing in the last column. The next character will wrap to the next line.
+    pending_wrap: bool,
+
+    /// Current style, applied to printed characters & erased cells.
+    pen: Pen,
+
+    /// Top & bottom rows (inclusive) of the scrolling region.
+    scroll_top: u16,
+    scroll_bottom: u16,
+
+    /// Designated character sets for G0 & G1, and whether G1 is shifted in.
+    charsets: [Charset; 2],
+    shift_out: bool,
+
+    last_char: Option<char>,
+    title: Option<String>,
+
+    state: State,
+    utf8: Vec<u8>,
+
+    responses: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Pen {
+    fg: Color,
+    bg: Color,
+    modifier: Modifier,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SavedCursor {
+    position: Position,
+    pen: Pen,
+    pending_wrap: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Charset {
+    Ascii,
+    /// DEC Special Graphics (line drawing).
+    DecGraphics,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    /// ESC followed by an intermediate byte. ex: `ESC (` to designate a character set.
+    EscapeIntermediate(u8),
+    Csi { params: Vec<u8>, intermediates: Vec<u8> },
+    /// Operating System Command, ex: setting the title.
+    Osc(Vec<u8>),
+    /// DCS, APC, PM, SOS: Read until the string terminator, and ignore.
+    IgnoreString,
+    /// Saw an ESC while in a string, may be the start of a string terminator. (`ESC \`)
+    StringEscape { osc: Option<Vec<u8>> },
+}
+
+impl VirtualTerminal {
+    pub fn new(size: Size) -> Self {
+        let area = Rect::new(0, 0, size.width, size.height);
+        Self {
+            size,
+            primary: Buffer::empty(area),
+            alternate: Buffer::empty(area),
+            alternate_active: false,
+            cursor: Position::ORIGIN,
+            saved_cursor: None,
+            cursor_visible: true,
+            pending_wrap: false,
+            pen: Pen::default(),
+            scroll_top: 0,
+            scroll_bottom: size.height.saturating_sub(1),
+            charsets: [Charset::Ascii; 2],
+            shift_out: false,
+            last_char: None,
+            title: None,
+            state: State::Ground,
+            utf8: vec![],
+            responses: vec![],
+        }
+    }
+
+    /// The cells currently visible on the screen.
+    pub fn buffer(&self) -> &Buffer {
+        if self.alternate_active {
+            &self.alternate
+        } else {
+            &self.primary
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn cursor(&self) -> Position {
+        self.cursor
+    }
+
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    pub fn alternate_screen(&self) -> bool {
+        self.alternate_active
+    }
+
+    /// The window title, if one was set with OSC 0 or 2.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// The visible contents as plain text, one line per row, with trailing spaces trimmed.
+    pub fn text(&self) -> String {
+        let buffer = self.buffer();
+        let mut lines = vec![];
+        for y in 0..self.size.height {
+            let mut line = String::new();
+            let mut to_skip = 0;
+            for x in 0..self.size.width {
+                let symbol = buffer[(x, y)].symbol();
+                if to_skip > 0 {
+                    to_skip -= 1;
+                    continue;
+                }
+                to_skip = unicode_width::UnicodeWidthStr::width(symbol).saturating_sub(1);
+                line.push_str(symbol);
+            }
+            lines.push(line.trim_end().to_string());
+        }
+        lines.join("\n")
+    }
+
+    /// Bytes the terminal would have sent back in response to queries.
+    ///
+    /// Supports cursor position reports (`CSI 6n`) and text area size reports (`CSI 18t`).
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        mem::take(&mut self.responses)
+    }
+
+    /// Resize the screen. Content is kept where it fits.
+    pub fn resize(&mut self, size: Size) {
+        let area = Rect::new(0, 0, size.width, size.height);
+        for buffer in [&mut self.primary, &mut self.alternate] {
+            let mut resized = Buffer::empty(area);
+            let overlap = buffer.area.intersection(area);
+            for y in overlap.top()..overlap.bottom() {
+                for x in overlap.left()..overlap.right() {
+                    resized[(x, y)] = buffer[(x, y)].clone();
+                }
+            }
+            *buffer = resized;
+        }
+        self.size = size;
+        self.scroll_top = 0;
+        self.scroll_bottom = size.height.saturating_sub(1);
+        self.cursor.x = self.cursor.x.min(size.width.saturating_sub(1));
+        self.cursor.y = self.cursor.y.min(size.height.saturating_sub(1));
+        self.pending_wrap = false;
+    }
+
+    /// Process terminal output.
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.advance(byte);
+        }
+    }
+
+    fn advance(&mut self, byte: u8) {
+        match mem::replace(&mut self.state, State::Ground) {
+            State::Ground => self.ground(byte),
+            State::Escape => self.escape(byte),
+            State::EscapeIntermediate(intermediate) => self.escape_intermediate(intermediate, byte),
+            State::Csi { mut params, mut intermediates } => match byte {
+                0x30..=0x3f => {
+                    params.push(byte);
+                    self.state = State::Csi { params, intermediates };
+                }
+                0x20..=0x2f => {
+                    intermediates.push(byte);
+                    self.state = State::Csi { params, intermediates };
+                }
+                0x40..=0x7e => self.csi(&params, &intermediates, byte),
+                0x1b => self.state = State::Escape,
+                // C0 controls are executed even in the middle of a sequence.
+                0x00..=0x1f => {
+                    self.control(byte);
+                    self.state = State::Csi { params, intermediates };
+                }
+                _ => {}
+            },
+            State::Osc(mut data) => match byte {
+                0x07 => self.osc(&data),
+                0x1b => self.state = State::StringEscape { osc: Some(data) },
+                _ => {
+                    data.push(byte);
+                    self.state = State::Osc(data);
+                }
+            },
+            State::IgnoreString => match byte {
+                0x1b => self.state = State::StringEscape { osc: None },
+                0x07 => {}
+                _ => self.state = State::IgnoreString,
+            },
+            State::StringEscape { osc } => {
+                if let Some(data) = &osc {
+                    self.osc(data);
+                }
+                if byte != b'\\' {
+                    // Not a string terminator. Treat it as a new escape sequence.
+                    self.escape(byte);
+                }
+            }
+        }
+    }
+
+    fn ground(&mut self, byte: u8) {
+        if !self.utf8.is_empty() {
+            if byte & 0xc0 == 0x80 {
+                self.utf8.push(byte);
+                let expected = match self.utf8[0] {
+                    0xc0..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    _ => 4,
+                };
+                if self.utf8.len() == expected {
+                    let bytes = mem::take(&mut self.utf8);
+                    let ch = std::str::from_utf8(&bytes)
+                        .ok()
+                        .and_then(|it| it.chars().next())
+                        .unwrap_or(char::REPLACEMENT_CHARACTER);
+                    self.print(ch);
+                }
+                return;
+            }
+            // Incomplete character.
+            self.utf8.clear();
+            self.print(char::REPLACEMENT_CHARACTER);
+        }
+
+        match byte {
+            0x1b => self.state = State::Escape,
+            0x00..=0x1f | 0x7f => self.control(byte),
+            0x20..=0x7e => self.print(byte as char),
+            0xc2..=0xf4 => self.utf8.push(byte),
+            _ => self.print(char::REPLACEMENT_CHARACTER),
+        }
+    }
+
+    fn control(&mut self, byte: u8) {
+        match byte {
+            // BS
+            0x08 => {
+                self.cursor.x = self.cursor.x.saturating_sub(1);
+                self.pending_wrap = false;
+            }
+            // HT
+            0x09 => {
+                let next_stop = (self.cursor.x / 8 + 1) * 8;
+                self.cursor.x = next_stop.min(self.size.width.saturating_sub(1));
+                self.pending_wrap = false;
+            }
+            // LF, VT, FF
+            0x0a..=0x0c => self.line_feed(),
+            // CR
+            0x0d => {
+                self.cursor.x = 0;
+                self.pending_wrap = false;
+            }
+            // SO, SI
+            0x0e => self.shift_out = true,
+            0x0f => self.shift_out = false,
+            _ => {}
+        }
+    }
+
+    fn escape(&mut self, byte: u8) {
+        match byte {
+            b'[' => {
+                self.state = State::Csi {
+                    params: vec![],
+                    intermediates: vec![],
+                }
+            }
+            b']' => self.state = State::Osc(vec![]),
+            b'P' | b'X' | b'^' | b'_' => self.state = State::IgnoreString,
+            0x20..=0x2f => self.state = State::EscapeIntermediate(byte),
+            b'7' => self.save_cursor(),
+            b'8' => self.restore_cursor(),
+            b'D' => self.line_feed(),
+            b'E' => {
+                self.line_feed();
+                self.cursor.x = 0;
+            }
+            b'M' => self.reverse_index(),
+            b'c' => *self = Self::new(self.size),
+            0x1b => self.state = State::Escape,
+            _ => {}
+        }
+    }
+
+    fn escape_intermediate(&mut self, intermediate: u8, byte: u8) {
+        let charset = match byte {
+            b'0' => Charset::DecGraphics,
+            _ => Charset::Ascii,
+        };
+        match intermediate {
+            b'(' => self.charsets[0] = charset,
+            b')' => self.charsets[1] = charset,
+            _ => {}
+        }
+    }
+
+    fn osc(&mut self, data: &[u8]) {
+        let data = String::from_utf8_lossy(data);
+        let Some((command, value)) = data.split_once(';') else {
+            return;
+        };
+        if command == "0" || command == "2" {
+            self.title = Some(value.to_string());
+        }
+    }
+
+    fn csi(&mut self, params: &[u8], intermediates: &[u8], action: u8) {
+        let private = params.first() == Some(&b'?');
+        let params = parse_params(params);
+        let param = |index: usize, default: u16| -> u16 {
+            match params.get(index).copied().flatten() {
+                None | Some(0) => default,
+                Some(value) => value,
+            }
+        };
+
+        if !intermediates.is_empty() {
+            // ex: DECSCUSR (cursor shape). Nothing we track.
+            return;
+        }
+
+        let Size { width, height } = self.size;
+        let max_x = width.saturating_sub(1);
+        let max_y = height.saturating_sub(1);
+
+        if action != b'b' {
+            self.last_char = None;
+        }
+        match action {
+            b'A' => self.move_to(self.cursor.x, self.cursor.y.saturating_sub(param(0, 1))),
+            b'B' | b'e' => self.move_to(self.cursor.x, self.cursor.y.saturating_add(param(0, 1))),
+            b'C' | b'a' => self.move_to(self.cursor.x.saturating_add(param(0, 1)), self.cursor.y),
+            b'D' => self.move_to(self.cursor.x.saturating_sub(param(0, 1)), self.cursor.y),
+            b'E' => self.move_to(0, self.cursor.y.saturating_add(param(0, 1))),
+            b'F' => self.move_to(0, self.cursor.y.saturating_sub(param(0, 1))),
+            b'G' | b'`' => self.move_to(param(0, 1) - 1, self.cursor.y),
+            b'd' => self.move_to(self.cursor.x, param(0, 1) - 1),
+            b'H' | b'f' => self.move_to(param(1, 1) - 1, param(0, 1) - 1),
+            b'J' => match params.first().copied().flatten().unwrap_or(0) {
+                0 => {
+                    self.erase(self.cursor.x, max_x, self.cursor.y);
+                    for y in self.cursor.y + 1..height {
+                        self.erase(0, max_x, y);
+                    }
+                }
+                1 => {
+                    for y in 0..self.cursor.y {
+                        self.erase(0, max_x, y);
+                    }
+                    self.erase(0, self.cursor.x, self.cursor.y);
+                }
+                2 | 3 => {
+                    for y in 0..height {
+                        self.erase(0, max_x, y);
+                    }
+                }
+                _ => {}
+            },
+            b'K' => match params.first().copied().flatten().unwrap_or(0) {
+                0 => self.erase(self.cursor.x, max_x, self.cursor.y),
+                1 => self.erase(0, self.cursor.x, self.cursor.y),
+                2 => self.erase(0, max_x, self.cursor.y),
+                _ => {}
+            },
+            b'X' => {
+                let count = param(0, 1);
+                let end = self.cursor.x.saturating_add(count - 1).min(max_x);
+                self.erase(self.cursor.x, end, self.cursor.y);
+            }
+            b'@' => self.insert_cells(param(0, 1)),
+            b'P' => self.delete_cells(param(0, 1)),
+            b'L' => self.insert_lines(param(0, 1)),
+            b'M' => self.delete_lines(param(0, 1)),
+            b'S' => self.scroll_up(self.scroll_top, self.scroll_bottom, param(0, 1)),
+            b'T' => self.scroll_down(self.scroll_top, self.scroll_bottom, param(0, 1)),
+            b'b' => {
+                if let Some(ch) = self.last_char {
+                    for _ in 0..param(0, 1) {
+                        self.print(ch);
+                    }
+                }
+            }
+            b'm' if !private => self.sgr(&params),
+            b'h' | b'l' if private => {
+                let enable = action == b'h';
+                for mode in params.iter().flatten() {
+                    self.set_private_mode(*mode, enable);
+                }
+            }
+            b'r' if !private => {
+                let top = param(0, 1) - 1;
+                let bottom = param(1, height).saturating_sub(1);
+                if top < bottom && bottom <= max_y {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.move_to(0, 0);
+                }
+            }
+            b's' if !private => self.save_cursor(),
+            b'u' if !private => self.restore_cursor(),
+            b'n' if !private && param(0, 0) == 6 => {
+                let report = format!("\x1b[{};{}R", self.cursor.y + 1, self.cursor.x + 1);
+                self.responses.extend_from_slice(report.as_bytes());
+            }
+            b't' if !private && param(0, 0) == 18 => {
+                let report = format!("\x1b[8;{height};{width}t");
+                self.responses.extend_from_slice(report.as_bytes());
+            }
+            _ => {}
+        }
+    }
+
+    fn set_private_mode(&mut self, mode: u16, enable: bool) {
+        match mode {
+            25 => self.cursor_visible = enable,
+            47 | 1047 | 1049 => {
+                if enable == self.alternate_active {
+                    return;
+                }
+                if enable {
+                    if mode == 1049 {
+                        self.save_cursor();
+                    }
+                    self.alternate_active = true;
+                    self.alternate.reset();
+                } else {
+                    self.alternate_active = false;
+                    if mode == 1049 {
+                        self.restore_cursor();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn sgr(&mut self, params: &[Option<u16>]) {
+        if params.is_empty() {
+            self.pen = Pen::default();
+            return;
+        }
+
+        let mut params = params.iter().map(|it| it.unwrap_or(0));
+        while let Some(param) = params.next() {
+            let modifier = &mut self.pen.modifier;
+            match param {
+                0 => self.pen = Pen::default(),
+                1 => modifier.insert(Modifier::BOLD),
+                2 => modifier.insert(Modifier::DIM),
+                3 => modifier.insert(Modifier::ITALIC),
+                4 => modifier.insert(Modifier::UNDERLINED),
+                5 => modifier.insert(Modifier::SLOW_BLINK),
+                6 => modifier.insert(Modifier::RAPID_BLINK),
+                7 => modifier.insert(Modifier::REVERSED),
+                8 => modifier.insert(Modifier::HIDDEN),
+                9 => modifier.insert(Modifier::CROSSED_OUT),
+                22 => modifier.remove(Modifier::BOLD | Modifier::DIM),
+                23 => modifier.remove(Modifier::ITALIC),
+                24 => modifier.remove(Modifier::UNDERLINED),
+                25 => modifier.remove(Modifier::SLOW_BLINK | Modifier::RAPID_BLINK),
+                27 => modifier.remove(Modifier::REVERSED),
+                28 => modifier.remove(Modifier::HIDDEN),
+                29 => modifier.remove(Modifier::CROSSED_OUT),
+                30..=37 => self.pen.fg = indexed_color((param - 30) as u8),
+                39 => self.pen.fg = Color::Reset,
+                40..=47 => self.pen.bg = indexed_color((param - 40) as u8),
+                49 => self.pen.bg = Color::Reset,
+                90..=97 => self.pen.fg = indexed_color((param - 90 + 8) as u8),
+                100..=107 => self.pen.bg = indexed_color((param - 100 + 8) as u8),
+                38 | 48 => {
+                    let color = match params.next() {
+                        Some(5) => params.next().map(|index| indexed_color(index as u8)),
+                        Some(2) => {
+                            let mut channel = || params.next().unwrap_or(0) as u8;
+                            Some(Color::Rgb(channel(), channel(), channel()))
+                        }
+                        _ => None,
+                    };
+                    if let Some(color) = color {
+                        if param == 38 {
+                            self.pen.fg = color;
+                        } else {
+                            self.pen.bg = color;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn print(&mut self, ch: char) {
+        let charset = self.charsets[self.shift_out as usize];
+        let ch = match charset {
+            Charset::DecGraphics => dec_graphics(ch),
+            Charset::Ascii => ch,
+        };
+        self.last_char = Some(ch);
+
+        let width = ch.width().unwrap_or(0) as u16;
+        let Size { width: cols, height: rows } = self.size;
+        if cols == 0 || rows == 0 {
+            return;
+        }
+
+        if width == 0 {
+            // Combining character. Attach it to the previous cell.
+            let x = if self.pending_wrap { self.cursor.x } else { self.cursor.x.saturating_sub(1) };
+            let y = self.cursor.y;
+            let cell = &mut self.screen_mut()[(x, y)];
+            let symbol = format!("{}{ch}", cell.symbol());
+            cell.set_symbol(&symbol);
+            return;
+        }
+
+        if self.pending_wrap || self.cursor.x + width > cols {
+            self.cursor.x = 0;
+            self.line_feed();
+        }
+
+        let Position { x, y } = self.cursor;
+        let pen = self.pen;
+        let screen = self.screen_mut();
+        let cell = &mut screen[(x, y)];
+        cell.set_char(ch);
+        cell.fg = pen.fg;
+        cell.bg = pen.bg;
+        cell.modifier = pen.modifier;
+        // The glyph covers the following cell(s), in the same colors:
+        for extra in 1..width {
+            if x + extra < cols {
+                let cell = &mut screen[(x + extra, y)];
+                cell.set_char(' ');
+                cell.fg = pen.fg;
+                cell.bg = pen.bg;
+                cell.modifier = pen.modifier;
+            }
+        }
+
+        if x + width >= cols {
+            self.cursor.x = cols - 1;
+            self.pending_wrap = true;
+        } else {
+            self.cursor.x = x + width;
+        }
+    }
+
+    fn screen_mut(&mut self) -> &mut Buffer {
+        if self.alternate_active {
+            &mut self.alternate
+        } else {
+            &mut self.primary
+        }
+    }
+
+    fn move_to(&mut self, x: u16, y: u16) {
+        self.cursor = Position {
+            x: x.min(self.size.width.saturating_sub(1)),
+            y: y.min(self.size.height.saturating_sub(1)),
+        };
+        self.pending_wrap = false;
+    }
+
+    fn line_feed(&mut self) {
+        self.pending_wrap = false;
+        if self.cursor.y == self.scroll_bottom {
+            self.scroll_up(self.scroll_top, self.scroll_bottom, 1);
+        } else if self.cursor.y + 1 < self.size.height {
+            self.cursor.y += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        self.pending_wrap = false;
+        if self.cursor.y == self.scroll_top {
+            self.scroll_down(self.scroll_top, self.scroll_bottom, 1);
+        } else {
+            self.cursor.y = self.cursor.y.saturating_sub(1);
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursor = Some(SavedCursor {
+            position: self.cursor,
+            pen: self.pen,
+            pending_wrap: self.pending_wrap,
+        });
+    }
+
+    fn restore_cursor(&mut self) {
+        let saved = self.saved_cursor.unwrap_or(SavedCursor {
+            position: Position::ORIGIN,
+            pen: Pen::default(),
+            pending_wrap: false,
+        });
+        self.move_to(saved.position.x, saved.position.y);
+        self.pen = saved.pen;
+        self.pending_wrap = saved.pending_wrap;
+    }
+
+    /// There are no cells to change. (ex: the host reported a size of 0x0)
+    fn is_empty(&self) -> bool {
+        self.size.width == 0 || self.size.height == 0
+    }
+
+    /// An empty cell, using the current background color. (Like xterm's "bce".)
+    fn blank(&self) -> Cell {
+        let mut cell = Cell::EMPTY;
+        cell.bg = self.pen.bg;
+        cell
+    }
+
+    /// Erase cells from `start` to `end` (inclusive) on row `y`.
+    fn erase(&mut self, start: u16, end: u16, y: u16) {
+        let width = self.size.width;
+        if self.is_empty() {
+            return;
+        }
+        let blank = self.blank();
+        let screen = self.screen_mut();
+        for x in start..=end.min(width - 1) {
+            screen[(x, y)] = blank.clone();
+        }
+        self.pending_wrap = false;
+    }
+
+    fn insert_cells(&mut self, count: u16) {
+        if self.is_empty() {
+            return;
+        }
+        let blank = self.blank();
+        let Position { x, y } = self.cursor;
+        let width = self.size.width;
+        let screen = self.screen_mut();
+        for col in (x..width).rev() {
+            screen[(col, y)] = match col.checked_sub(count) {
+                Some(from) if from >= x => screen[(from, y)].clone(),
+                _ => blank.clone(),
+            };
+        }
+    }
+
+    fn delete_cells(&mut self, count: u16) {
+        if self.is_empty() {
+            return;
+        }
+        let blank = self.blank();
+        let Position { x, y } = self.cursor;
+        let width = self.size.width;
+        let screen = self.screen_mut();
+        for col in x..width {
+            let from = col.saturating_add(count);
+            screen[(col, y)] = if from < width { screen[(from, y)].clone() } else { blank.clone() };
+        }
+    }
+
+    fn insert_lines(&mut self, count: u16) {
+        if (self.scroll_top..=self.scroll_bottom).contains(&self.cursor.y) {
+            self.scroll_down(self.cursor.y, self.scroll_bottom, count);
+            self.cursor.x = 0;
+        }
+    }
+
+    fn delete_lines(&mut self, count: u16) {
+        if (self.scroll_top..=self.scroll_bottom).contains(&self.cursor.y) {
+            self.scroll_up(self.cursor.y, self.scroll_bottom, count);
+            self.cursor.x = 0;
+        }
+    }
+
+    /// Move rows `top..=bottom` up by `count`, filling in blank rows at the bottom.
+    fn scroll_up(&mut self, top: u16, bottom: u16, count: u16) {
+        if self.is_empty() {
+            return;
+        }
+        let blank = self.blank();
+        let width = self.size.width;
+        let screen = self.screen_mut();
+        for y in top..=bottom {
+            let from = y.saturating_add(count);
+            for x in 0..width {
+                screen[(x, y)] = if from <= bottom { screen[(x, from)].clone() } else { blank.clone() };
+            }
+        }
+    }
+
+    /// Move rows `top..=bottom` down by `count`, filling in blank rows at the top.
+    fn scroll_down(&mut self, top: u16, bottom: u16, count: u16) {
+        if self.is_empty() {
+            return;
+        }
+        let blank = self.blank();
+        let width = self.size.width;
+        let screen = self.screen_mut();
+        for y in (top..=bottom).rev() {
+            for x in 0..width {
+                screen[(x, y)] = match y.checked_sub(count) {
+                    Some(from) if from >= top => screen[(x, from)].clone(),
+                    _ => blank.clone(),
+                };
+            }
+        }
+    }
+}
+
+/// Parse CSI parameters. Missing parameters are `None`. Sub-parameters (after `:`) are ignored.
+fn parse_params(params: &[u8]) -> Vec<Option<u16>> {
+    let params = params.strip_prefix(b"?").unwrap_or(params);
+    if params.is_empty() {
+        return vec![];
+    }
+    params
+        .split(|it| *it == b';')
+        .map(|param| {
+            let param = param.split(|it| *it == b':').next().unwrap_or_default();
+            std::str::from_utf8(param).ok()?.parse().ok()
+        })
+        .collect()
+}
+
+/// The inverse of [ansi_color](crate::backend::ansi_color): Maps a 256-color index back to a Ratatui color.
+pub(crate) fn indexed_color(index: u8) -> Color {
+    match index {
+        0 => Color::Black,
+        1 => Color::Red,
+        2 => Color::Green,
+        3 => Color::Yellow,
+        4 => Color::Blue,
+        5 => Color::Magenta,
+        6 => Color::Cyan,
+        7 => Color::Gray,
+        8 => Color::DarkGray,
+        9 => Color::LightRed,
+        10 => Color::LightGreen,
+        11 => Color::LightYellow,
+        12 => Color::LightBlue,
+        13 => Color::LightMagenta,
+        14 => Color::LightCyan,
+        15 => Color::White,
+        index => Color::Indexed(index),
+    }
+}
+
+/// Maps characters in the DEC Special Graphics character set to their Unicode equivalents.
+pub(crate) fn dec_graphics(ch: char) -> char {
+    match ch {
+        '`' => '◆',
+        'a' => '▒',
+        'f' => '°',
+        'g' => '±',
+        'j' => '┘',
+        'k' => '┐',
+        'l' => '┌',
+        'm' => '└',
+        'n' => '┼',
+        'o' => '⎺',
+        'p' => '⎻',
+        'q' => '─',
+        'r' => '⎼',
+        's' => '⎽',
+        't' => '├',
+        'u' => '┤',
+        'v' => '┴',
+        'w' => '┬',
+        'x' => '│',
+        'y' => '≤',
+        'z' => '≥',
+        '{' => 'π',
+        '|' => '≠',
+        '}' => '£',
+        '~' => '·',
+        ch => ch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vt(size: Size, output: &[u8]) -> VirtualTerminal {
+        let mut vt = VirtualTerminal::new(size);
+        vt.write(output);
+        vt
+    }
+
+    #[test]
+    fn scroll_region() {
+        let mut vt = vt(Size::new(5, 4), b"1\r\n2\r\n3\r\n4\x1b[2;3r\x1b[3;1H\n");
+        assert_eq!(vt.text(), "1\n3\n\n4");
+        vt.write(b"\x1b[r\x1b[4;1H\nx");
+        assert_eq!(vt.text(), "3\n\n4\nx");
+    }
+
+    #[test]
+    fn scroll_region_up_and_down() {
+        let mut vt = vt(Size::new(3, 5), b"a\r\nb\r\nc\r\nd\r\ne\x1b[2;4r\x1b[S");
+        assert_eq!(vt.text(), "a\nc\nd\n\ne");
+        vt.write(b"\x1b[2T");
+        assert_eq!(vt.text(), "a\n\n\nc\ne");
+        // Reverse index at the top of the region scrolls it down:
+        vt.write(b"\x1b[2;1Hx\x1bM");
+        assert_eq!(vt.text(), "a\n\nx\n\ne");
+    }
+
+    #[test]
+    fn erase() {
+        let rows = b"abcde\r\nfghij\r\nklmno";
+        let mut vt = vt(Size::new(5, 3), rows);
+        vt.write(b"\x1b[2;3H\x1b[J");
+        assert_eq!(vt.text(), "abcde\nfg\n");
+        vt.write(b"\x1b[H");
+        vt.write(rows);
+        vt.write(b"\x1b[2;3H\x1b[1J");
+        assert_eq!(vt.text(), "\n   ij\nklmno");
+        vt.write(b"\x1b[2J");
+        assert_eq!(vt.text(), "\n\n");
+
+        vt.write(b"\x1b[H");
+        vt.write(rows);
+        vt.write(b"\x1b[1;3H\x1b[K\x1b[2;3H\x1b[1K\x1b[3;3H\x1b[2K");
+        assert_eq!(vt.text(), "ab\n   ij\n");
+
+        vt.write(b"\x1b[H");
+        vt.write(rows);
+        vt.write(b"\x1b[1;2H\x1b[2X\x1b[2;4H\x1b[9X\x1b[3;1H\x1b[X");
+        assert_eq!(vt.text(), "a  de\nfgh\n lmno");
+    }
+
+    #[test]
+    fn repeat() {
+        let mut vt = vt(Size::new(5, 2), b"ab\x1b[2b");
+        assert_eq!(vt.text(), "abbb\n");
+        // Repeats wrap like the printed characters would:
+        vt.write(b"\x1b[3b");
+        assert_eq!(vt.text(), "abbbb\nbb");
+        // Any other sequence in between forgets the character:
+        vt.write(b"\x1b[1;1H\x1b[3b");
+        assert_eq!(vt.text(), "abbbb\nbb");
+    }
+
+    #[test]
+    fn dec_line_drawing() {
+        let mut vt = vt(Size::new(6, 2), b"\x1b(0lqk\x1b(Bq\r\n\x1b)0\x0ex\x0fx\x0emj");
+        assert_eq!(vt.text(), "┌─┐q\n│x└┘");
+        vt.write(b"\x0f\x1b[1;1Hab");
+        assert_eq!(vt.text(), "ab┐q\n│x└┘");
+    }
+
+    #[test]
+    fn wide_characters_at_the_right_margin() {
+        // Doesn't fit, so wraps to the next line:
+        let mut vt = vt(Size::new(5, 4), "abcd中".as_bytes());
+        assert_eq!(vt.text(), "abcd\n中\n\n");
+        assert_eq!(vt.buffer()[(4, 0)].symbol(), " ");
+        // Fits exactly, then the next character wraps:
+        vt.write("\x1b[3;1Habc中e".as_bytes());
+        assert_eq!(vt.text(), "abcd\n中\nabc中\ne");
+        assert_eq!(vt.buffer()[(4, 2)].symbol(), " ");
+        assert_eq!(vt.cursor(), Position::new(1, 3));
+    }
+
+    #[test]
+    fn empty_terminal() {
+        for size in [Size::new(0, 0), Size::new(5, 0), Size::new(0, 5)] {
+            let vt = vt(size, b"\x1b[r\x1b[0;0r\x1b[2;1r\x1b[5;5Hhello\x1b[2J\x1b[K\x1b[3X\x1b[2b\n\x1b[S\x1b[T");
+            assert_eq!(vt.size(), size);
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Run Ratatui apps under a WASI runtime (ex: wasmtime), without any JavaScript.
+//!
+//! Build for `wasm32-wasip1` or `wasm32-wasip2`. Output goes to WASI stdout, and input comes from stdin:
+//!
+//! ```ignore
+//! let mut input = WasiInput::new(EnvSize::default().get_size()?);
+//! let mut backend = wasi::backend(input.size());
+//! backend.exclusive()?;
+//! let mut terminal = Terminal::new(backend)?;
+//!
+//! input.request_size(&mut std::io::stdout())?;
+//! while let Some(bytes) = input.read()? {
+//!     // handle bytes, then:
+//!     terminal.draw(|frame| render(frame))?;
+//! }
+//! ```
+//!
+//! WASI has no way to put the terminal into raw mode, or to be notified of resizes. So:
+//!  * Run the app with raw mode already set. (ex: `stty raw -echo; wasmtime app.wasm; stty sane`)
+//!  * The size comes from the `COLUMNS`/`LINES` environment variables (which your runtime may need to be told
+//!    to pass through, ex: `wasmtime --env COLUMNS --env LINES`), or from asking the terminal with `CSI 18 t`.
+//!    Ask again whenever you'd like to pick up a resize. (ex: after each keypress)
+
+use std::{
+    cell::Cell,
+    env,
+    io::{self, Read, Result as IOResult, Write},
+    rc::Rc,
+};
+
+use ratatui::layout::Size;
+
+use crate::{backend::AnsiBackend, host::GetSize};
+
+/// An [AnsiBackend] which writes to (WASI) stdout.
+pub fn backend(get_size: impl GetSize + 'static) -> AnsiBackend {
+    AnsiBackend::with_io(io::stdout(), get_size)
+}
+
+/// Gets the terminal size from the `COLUMNS` and `LINES` environment variables.
+#[derive(Debug, Clone, Copy)]
+pub struct EnvSize {
+    /// Used when the variables are missing or invalid.
+    pub fallback: Size,
+}
+
+impl Default for EnvSize {
+    fn default() -> Self {
+        Self { fallback: Size::new(80, 24) }
+    }
+}
+
+impl GetSize for EnvSize {
+    fn get_size(&self) -> IOResult<Size> {
+        let var = |name: &str| env::var(name).ok().and_then(|it| it.trim().parse().ok()).filter(|it| *it > 0);
+        Ok(Size {
+            width: var("COLUMNS").unwrap_or(self.fallback.width),
+            height: var("LINES").unwrap_or(self.fallback.height),
+        })
+    }
+}
+
+/// Reads input from stdin, and picks out the terminal's replies to [WasiInput::request_size].
+pub struct WasiInput {
+    size: Rc<Cell<Size>>,
+
+    /// The start of what may be a size report, waiting for the rest of it.
+    pending: Vec<u8>,
+}
+
+impl WasiInput {
+    pub fn new(initial_size: Size) -> Self {
+        Self {
+            size: Rc::new(Cell::new(initial_size)),
+            pending: vec![],
+        }
+    }
+
+    /// The latest known size. Pass this to [backend].
+    pub fn size(&self) -> Rc<Cell<Size>> {
+        self.size.clone()
+    }
+
+    /// Ask the terminal for its size, in characters. (`CSI 18 t`)
+    ///
+    /// The reply arrives on stdin, and is handled by [WasiInput::read].
+    pub fn request_size(&self, out: &mut impl Write) -> IOResult<()> {
+        out.write_all(b"\x1b[18t")?;
+        out.flush()
+    }
+
+    /// Block until input is available on stdin.
+    ///
+    /// Returns the bytes read, with any size reports removed. This may be empty if we only got a size report,
+    /// in which case you may want to redraw. Returns `None` at the end of input.
+    pub fn read(&mut self) -> IOResult<Option<Vec<u8>>> {
+        let mut buf = [0; 1024];
+        let count = io::stdin().read(&mut buf)?;
+        if count == 0 {
+            if self.pending.is_empty() {
+                return Ok(None);
+            }
+            return Ok(Some(std::mem::take(&mut self.pending)));
+        }
+        Ok(Some(self.push(&buf[..count])))
+    }
+
+    /// Handle bytes read from stdin. Exposed separately from [WasiInput::read] for hosts which read stdin themselves.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<u8> {
+        let mut input = std::mem::take(&mut self.pending);
+        input.extend_from_slice(bytes);
+
+        let mut out = Vec::with_capacity(input.len());
+        let mut rest = input.as_slice();
+        while let Some(start) = rest.iter().position(|it| *it == 0x1b) {
+            out.extend_from_slice(&rest[..start]);
+            rest = &rest[start..];
+            match parse_size_report(rest) {
+                Report::Size(size, len) => {
+                    self.size.set(size);
+                    rest = &rest[len..];
+                }
+                Report::Incomplete => {
+                    self.pending = rest.to_vec();
+                    return out;
+                }
+                Report::No => {
+                    out.push(rest[0]);
+                    rest = &rest[1..];
+                }
+            }
+        }
+        out.extend_from_slice(rest);
+        out
+    }
+}
+
+enum Report {
+    /// The size, and the length of the report in bytes.
+    Size(Size, usize),
+
+    /// Could be a report, once more bytes arrive.
+    Incomplete,
+
+    No,
+}
+
+/// Parses a reply to `CSI 18 t`, which looks like `CSI 8 ; rows ; columns t`.
+fn parse_size_report(bytes: &[u8]) -> Report {
+    const PREFIX: &[u8] = b"\x1b[8;";
+    if bytes.len() < PREFIX.len() {
+        // Don't hold onto a lone ESC (or Alt-[), though. That's probably a keypress.
+        return if bytes.len() > 2 && PREFIX.starts_with(bytes) { Report::Incomplete } else { Report::No };
+    }
+    if !bytes.starts_with(PREFIX) {
+        return Report::No;
+    }
+
+    let mut numbers = [0u16; 2];
+    let mut index = 0;
+    for (offset, byte) in bytes[PREFIX.len()..].iter().enumerate() {
+        match byte {
+            b'0'..=b'9' => {
+                numbers[index] = numbers[index].saturating_mul(10).saturating_add((byte - b'0') as u16);
+            }
+            b';' if index == 0 => index = 1,
+            b't' if index == 1 => {
+                let [rows, columns] = numbers;
+                return Report::Size(Size::new(columns, rows), PREFIX.len() + offset + 1);
+            }
+            _ => return Report::No,
+        }
+    }
+    Report::Incomplete
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_reports() {
+        assert!(matches!(parse_size_report(b"\x1b[8;24;80t"), Report::Size(Size { width: 80, height: 24 }, 10)));
+        assert!(matches!(parse_size_report(b"\x1b[8;24;80tab"), Report::Size(_, 10)));
+        assert!(matches!(parse_size_report(b"\x1b[8;24;80"), Report::Incomplete));
+        assert!(matches!(parse_size_report(b"\x1b[8"), Report::Incomplete));
+        assert!(matches!(parse_size_report(b"\x1b["), Report::No));
+        assert!(matches!(parse_size_report(b"\x1b"), Report::No));
+        assert!(matches!(parse_size_report(b"\x1b[8;24t"), Report::No));
+        assert!(matches!(parse_size_report(b"\x1b[A"), Report::No));
+        assert!(matches!(parse_size_report(b"\x1b[8;2x;80t"), Report::No));
+    }
+
+    #[test]
+    fn reports_are_removed_from_input() {
+        let mut input = WasiInput::new(Size::new(10, 5));
+        assert_eq!(input.push(b"ab\x1b[8;24;80tc\x1b[Ad"), b"abc\x1b[Ad");
+        assert_eq!(input.size().get(), Size::new(80, 24));
+    }
+
+    #[test]
+    fn reports_split_across_reads() {
+        let bytes = b"a\x1b[8;30;100tb";
+        // Splitting right after the ESC or `ESC [` looks like Esc or Alt-[, so the report isn't picked out.
+        for split in 4..bytes.len() {
+            let mut input = WasiInput::new(Size::new(10, 5));
+            let mut out = input.push(&bytes[..split]);
+            out.extend(input.push(&bytes[split..]));
+            assert_eq!(out, b"ab", "split at {split}");
+            assert_eq!(input.size().get(), Size::new(100, 30), "split at {split}");
+        }
+    }
+
+    #[test]
+    fn lone_esc() {
+        let mut input = WasiInput::new(Size::new(10, 5));
+        assert_eq!(input.push(b"\x1b"), b"\x1b");
+        assert_eq!(input.push(b"x\x1b"), b"x\x1b");
+        assert_eq!(input.push(b"\x1b["), b"\x1b[");
+
+        // Input that only looked like the start of a report comes out with the next read:
+        assert_eq!(input.push(b"\x1b[8"), b"");
+        assert_eq!(input.push(b"~"), b"\x1b[8~");
+        assert_eq!(input.size().get(), Size::new(10, 5));
+    }
+}
@@ -0,0 +1,213 @@
+//! How wide the terminal draws characters that terminals disagree about.
+//!
+//! Ratatui lays out text with the `unicode-width` crate, but terminals don't all agree with it, or with each
+//! other. The usual suspects:
+//!
+//!  * East Asian Ambiguous characters (ex: `±`, `①`, and the `─` in borders), which CJK terminals draw 2 columns
+//!    wide.
+//!  * Emoji made wide with a variation selector. (ex: `❤️`, which is `❤` + U+FE0F)
+//!  * Emoji ZWJ sequences (ex: `👩‍💻`), which some terminals draw as one emoji, and some as each of its parts.
+//!
+//! When they disagree, the cursor ends up somewhere other than where the backend thinks it is, and the rest of
+//! the line is drawn in the wrong place. With a [WidthPolicy] that matches the terminal, the backend knows where
+//! the cursor is, so a glyph that's wider than Ratatui thinks only covers the cell after it. To find out what
+//! the terminal does, call
+//! [AnsiBackend::probe_widths](crate::backend::AnsiBackend::probe_widths), and pass the reply to
+//! [WidthPolicy::from_reply]. Then give the policy to
+//! [AnsiBackend::set_width_policy](crate::backend::AnsiBackend::set_width_policy). If you already know (ex: from
+//! a user setting), set [WidthPolicy::ambiguous] yourself.
+//!
+//! Widgets that measure text themselves can use the backend's [WidthPolicy::width].
+
+use std::mem;
+
+use unicode_width::{UnicodeWidthChar as _, UnicodeWidthStr as _};
+
+/// Sample glyphs to measure, in the order [WidthPolicy::from_reply] expects them.
+const SAMPLES: [&str; 3] = [
+    // East Asian Ambiguous:
+    "\u{b1}",
+    // Emoji presentation: ❤️
+    "\u{2764}\u{fe0f}",
+    // ZWJ sequence: 👩‍💻
+    "\u{1f469}\u{200d}\u{1f4bb}",
+];
+
+/// Draws each of the [SAMPLES] at the top left of the screen, and asks where the cursor ended up after each.
+///
+/// There's no drawing off-screen, since the cursor stops at the edges. So unless we're already there (`alternate`),
+/// the samples go on the alternate screen, which leaves the normal one (ex: an inline app's lines) alone. On the
+/// alternate screen, the samples are concealed, and erased afterward, but whatever was on that row needs to be
+/// drawn again. Either way, it's a synchronized update, so terminals that support those don't show it at all.
+pub(crate) fn query(alternate: bool) -> String {
+    let mut query = String::from("\x1b[?2026h");
+    if !alternate {
+        query.push_str("\x1b[?1049h");
+    }
+    query.push_str("\x1b7\x1b[0;8m");
+    for sample in SAMPLES {
+        query.push_str("\x1b[1;1H");
+        query.push_str(sample);
+        query.push_str("\x1b[6n");
+    }
+    query.push_str("\x1b[1;1H\x1b[2K\x1b[0m\x1b8");
+    if !alternate {
+        query.push_str("\x1b[?1049l");
+    }
+    query.push_str("\x1b[?2026l");
+    query
+}
+
+/// How wide the terminal draws characters that terminals disagree about. See the [module docs](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WidthPolicy {
+    /// How many columns East Asian Ambiguous characters take. 1 or 2.
+    pub ambiguous: u8,
+
+    /// How many columns a character followed by U+FE0F (emoji presentation) takes. 1 or 2.
+    pub emoji_presentation: u8,
+
+    /// The terminal draws an emoji ZWJ sequence as one emoji. Otherwise, it draws each of its parts.
+    pub zwj_sequences: bool,
+}
+
+impl Default for WidthPolicy {
+    /// The widths Ratatui assumes.
+    fn default() -> Self {
+        Self {
+            ambiguous: 1,
+            emoji_presentation: 2,
+            zwj_sequences: true,
+        }
+    }
+}
+
+impl WidthPolicy {
+    /// Ratatui's widths, with East Asian Ambiguous characters `ambiguous` columns wide. (1 or 2)
+    pub fn with_ambiguous(ambiguous: u8) -> Self {
+        Self {
+            ambiguous: ambiguous.clamp(1, 2),
+            ..Self::default()
+        }
+    }
+
+    /// Work out the policy from the terminal's reply to the query written by
+    /// [AnsiBackend::probe_widths](crate::backend::AnsiBackend::probe_widths).
+    ///
+    /// Returns `None` until the reply is complete.
+    pub fn from_reply(reply: &[u8]) -> Option<Self> {
+        let reply = String::from_utf8_lossy(reply);
+        // Each sample starts at column 1, so the cursor is one past its width:
+        let mut widths = reply.split("\x1b[").filter_map(|report| {
+            let (_row, column) = report.split_once('R')?.0.split_once(';')?;
+            Some(column.parse::<u16>().ok()?.saturating_sub(1))
+        });
+
+        let ambiguous = widths.next()?.clamp(1, 2) as u8;
+        let emoji_presentation = widths.next()?.clamp(1, 2) as u8;
+        // Drawn separately, the parts would be 4 columns wide:
+        let zwj_sequences = widths.next()? == 2;
+        Some(Self { ambiguous, emoji_presentation, zwj_sequences })
+    }
+
+    /// How many columns the terminal uses to draw `text`.
+    pub fn width(&self, text: &str) -> usize {
+        if *self == Self::default() {
+            return text.width();
+        }
+
+        let mut width = 0;
+        // The width of the last character, which a variation selector might change:
+        let mut last = 0;
+        let mut joined = false;
+        for ch in text.chars() {
+            match ch {
+                '\u{fe0f}' => {
+                    if self.emoji_presentation == 2 && last == 1 {
+                        width += 1;
+                        last = 2;
+                    }
+                }
+                '\u{200d}' => joined = self.zwj_sequences && last > 0,
+                ch => {
+                    let ch_width = match self.ambiguous {
+                        2 => ch.width_cjk(),
+                        _ => ch.width(),
+                    };
+                    let ch_width = ch_width.unwrap_or(0);
+                    // Joined characters are drawn as part of the one before:
+                    if !mem::take(&mut joined) {
+                        width += ch_width;
+                        last = ch_width;
+                    }
+                }
+            }
+        }
+        width
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::layout::{Position, Size};
+
+    use super::*;
+    use crate::vt::VirtualTerminal;
+
+    #[test]
+    fn from_reply() {
+        let narrow = b"\x1b[1;2R\x1b[1;2R\x1b[1;5R";
+        let policy = WidthPolicy { ambiguous: 1, emoji_presentation: 1, zwj_sequences: false };
+        assert_eq!(WidthPolicy::from_reply(narrow), Some(policy));
+
+        let cjk = b"\x1b[1;3R\x1b[1;3R\x1b[1;3R";
+        let policy = WidthPolicy { ambiguous: 2, emoji_presentation: 2, zwj_sequences: true };
+        assert_eq!(WidthPolicy::from_reply(cjk), Some(policy));
+
+        // Incomplete, or with other input mixed in:
+        for len in 0..narrow.len() {
+            assert_eq!(WidthPolicy::from_reply(&narrow[..len]), None, "{len}");
+        }
+        let mixed = b"x\x1b[1;2R\x1b[Ay\x1b[1;3R\x1b[1;3R";
+        assert_eq!(WidthPolicy::from_reply(mixed), Some(WidthPolicy::with_ambiguous(1)));
+        // Nonsense is clamped:
+        let policy = WidthPolicy::from_reply(b"\x1b[1;1R\x1b[1;99R\x1b[1;3R").unwrap();
+        assert_eq!((policy.ambiguous, policy.emoji_presentation), (1, 2));
+    }
+
+    #[test]
+    fn width() {
+        let default = WidthPolicy::default();
+        let cjk = WidthPolicy::with_ambiguous(2);
+        let narrow_emoji = WidthPolicy { emoji_presentation: 1, ..default };
+        let no_zwj = WidthPolicy { zwj_sequences: false, ..default };
+        for (text, widths) in [
+            ("abc", [3, 3, 3, 3]),
+            ("\u{b1}1", [2, 3, 2, 2]),
+            ("\u{2500}\u{2500}", [2, 4, 2, 2]),
+            ("\u{2764}\u{fe0f}", [2, 2, 1, 2]),
+            ("\u{1f469}\u{200d}\u{1f4bb}", [2, 2, 2, 4]),
+            ("\u{65e5}\u{672c}", [4, 4, 4, 4]),
+        ] {
+            let actual = [default, cjk, narrow_emoji, no_zwj].map(|policy| policy.width(text));
+            assert_eq!(actual, widths, "{text:?}");
+        }
+        assert_eq!(WidthPolicy::with_ambiguous(7).ambiguous, 2);
+    }
+
+    #[test]
+    fn query_leaves_the_normal_screen_alone() {
+        let mut vt = VirtualTerminal::new(Size::new(10, 3));
+        vt.write(b"prompt $\r\nline 2");
+        vt.write(query(false).as_bytes());
+        assert_eq!(vt.text(), "prompt $\nline 2\n");
+        assert_eq!(vt.cursor(), Position::new(6, 1));
+        assert!(!vt.alternate_screen());
+
+        // Already on the alternate screen, the top row is erased:
+        vt.write(b"\x1b[?1049h\x1b[1;1Htop\r\nsecond");
+        vt.write(query(true).as_bytes());
+        assert_eq!(vt.text(), "\nsecond\n");
+        assert_eq!(vt.cursor(), Position::new(6, 1));
+    }
+}
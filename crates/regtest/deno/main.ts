@@ -1,17 +1,25 @@
 #!/usr/bin/env -S deno run --check
 
 // @ts-types="../pkg/regtest.d.ts"
-import { Main, type Writer, type TerminalSizeCallback } from "../pkg/regtest.js"
+import { Main, type Writer, type TerminalSizeCallback, type SaveFileCallback } from "../pkg/regtest.js"
 
 
 async function main() {
     using cleanup = new DisposableStack()
-    
+
+    const recordTo = recordingWriter(cleanup)
+
     Deno.stdin.setRaw(true)
     cleanup.defer(() => Deno.stdin.setRaw(false))
 
-    const ui = new Main(size, out)
+    const ui = new Main(size, out, recordTo)
     cleanup.defer(() => ui.free())
+    ui.set_save_file_callback(saveFile)
+
+    // Lets the UI skip frames while input is arriving quickly, and come back for the last one.
+    let timer: number | undefined
+    cleanup.defer(() => clearTimeout(timer))
+    ui.set_timer((delayMs: number) => { timer = setTimeout(() => ui.tick(), delayMs) })
 
     ui.render()
     
@@ -43,6 +51,33 @@ const out: Writer = (bytes: Uint8Array) => {
     return written
 }
 
+/**
+ * `--record=session.cast` records the session in asciicast format.
+ */
+function recordingWriter(cleanup: DisposableStack): Writer | undefined {
+    const arg = Deno.args.find(it => it.startsWith("--record="))
+    if (!arg) { return undefined }
+
+    const file = Deno.openSync(arg.substring("--record=".length), { write: true, create: true, truncate: true })
+    cleanup.defer(() => file.close())
+    return (bytes: Uint8Array) => {
+        let written = 0
+        while (written < bytes.length) {
+            written += file.writeSync(bytes.slice(written))
+        }
+        return written
+    }
+}
+
+const saveFile: SaveFileCallback = (fileName: string, contents: string) => {
+    // Don't prompt for permission, it would mess up the TUI.
+    const status = Deno.permissions.querySync({ name: "write", path: fileName })
+    if (status.state !== "granted") {
+        throw new Error(`No permission to write ${fileName}`)
+    }
+    Deno.writeTextFileSync(fileName, contents)
+}
+
 if (import.meta.main) {
     await main()
 }
\ No newline at end of file
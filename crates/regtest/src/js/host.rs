@@ -0,0 +1,33 @@
+//! Callbacks provided by the host (JavaScript) side of the app.
+
+use wasm_bindgen::prelude::*;
+
+use super::error::MaybeError;
+
+#[wasm_bindgen(typescript_custom_section)]
+const TSSaveFileCallback: &'static str = r#"
+/**
+ * Saves a file on behalf of the app. (ex: screen snapshots)
+ *
+ * May throw if the file can't be written.
+ */
+type SaveFileCallback = {
+    (fileName: string, contents: string): void
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "SaveFileCallback")]
+    pub type JsSaveFileCallback;
+
+    #[wasm_bindgen(method, catch)]
+    fn call(this: &JsSaveFileCallback, value: JsValue, file_name: &str, contents: &str) -> Result<JsValue, MaybeError>;
+}
+
+impl JsSaveFileCallback {
+    pub fn save(&self, file_name: &str, contents: &str) -> Result<(), MaybeError> {
+        self.call(JsValue::NULL, file_name, contents)?;
+        Ok(())
+    }
+}
@@ -1,2 +1,3 @@
 pub mod error;
-pub mod regexp;
\ No newline at end of file
+pub mod host;
+pub mod regexp;
@@ -2,25 +2,25 @@ mod widgets;
 mod js;
 mod texts;
 
-use std::{collections::VecDeque, rc::Rc};
+use std::{collections::VecDeque, rc::Rc, time::Duration};
 
-use js::regexp::{Match, RegExp};
+use js::{host::JsSaveFileCallback, regexp::{Match, RegExp}};
 use ratatui_wasm_backend::{
-    anes::parser::{KeyCode, KeyModifiers, Parser, Sequence}, backend::{ AnsiBackend, AnsiBackendOptions }, ctrl::{Ctrl, GetCtrl as _}, ratatui:: {
+    backend::{ AnsiBackend, AnsiBackendOptions }, ctrl::GetCtrl as _, frame::FrameScheduler, input::{KeyCode, Parser, Sequence}, keymap::{Keymap, Matched}, ratatui:: {
         self,
         border,
         layout::{Constraint, Direction, Layout},
         style::Color,
         text::{ToLine as _, ToText},
         widgets::{Borders, Padding, Wrap},
-    }, types
+    }, host::{Clock as _, GetSize as _}, logging::{self, LogOptions}, record::{Recording, RecordingOptions}, snapshot::{self, SnapshotOptions}, types
 };
 
 use ratatui::{
     buffer::Buffer, layout::Rect, prelude::Backend, style::Stylize, text::{Line, Text}, widgets::{Block, Paragraph, Widget, WidgetRef}
 };
 use texts::SAMPLE;
-use types::{JsTermSizeCallback, JsWriter, log};
+use types::{JsClock, JsTermSizeCallback, JsTimer, JsWriter, log};
 use wasm_bindgen::prelude::*;
 use widgets::{utils::ref_or_dyn::RefOrDyn, Blocked, TextBox, ToDynLayout};
 
@@ -36,13 +36,38 @@ pub struct Main {
     term: ratatui::Terminal<AnsiBackend>,
     parser: Parser,
     app: App,
+
+    /// Where to save snapshots, if the host supports it.
+    save_file: Option<JsSaveFileCallback>,
+    snapshot_count: usize,
+
+    recording: Option<Recording>,
+
+    /// Limits how often we draw, if the host gave us a timer to call back for the last frame.
+    frames: FrameScheduler,
+    timer: Option<JsTimer>,
 }
 
 #[wasm_bindgen]
 impl Main {
+    /// If `record_to` is given, the session (including input) is recorded to it in asciicast format.
     #[wasm_bindgen(constructor)]
-    pub fn new(get_size: JsTermSizeCallback, stdout_writer: JsWriter) -> Result<Main> {
-        let mut be = AnsiBackend::new(AnsiBackendOptions { get_size, stdout_writer });
+    pub fn new(get_size: JsTermSizeCallback, stdout_writer: JsWriter, record_to: Option<JsWriter>) -> Result<Main> {
+        let (mut be, recording) = match record_to {
+            None => (AnsiBackend::new(AnsiBackendOptions { get_size, stdout_writer }), None),
+            Some(record_to) => {
+                let size = get_size.get_size().map_err(|err| err.to_string())?;
+                let mut options = RecordingOptions::new(record_to, Box::new(JsClock), size);
+                options.record_input = true;
+                options.title = Some("regtest".into());
+                let recording = Recording::start(options).map_err(|err| err.to_string())?;
+                let be = AnsiBackend::with_io(recording.tee(stdout_writer), recording.watch_size(get_size));
+                (be, Some(recording))
+            }
+        };
+
+        // Warnings are printed after we leave the alternate screen. (Fails if a previous Main already did this.)
+        let _ = logging::init(LogOptions::default());
 
         be.exclusive().map_err(|err| err.to_string())?;
         be.clear().map_err(|err| err.to_string())?;
@@ -51,19 +76,52 @@ impl Main {
         Ok(Self { 
             term, 
             app: App::default(),
-            parser: Parser::default()
+            parser: Parser::default(),
+            save_file: None,
+            snapshot_count: 0,
+            recording,
+            frames: FrameScheduler::default(),
+            timer: None,
         })
     }
 
+    /// Enables saving snapshots of the screen with Alt-S.
+    pub fn set_save_file_callback(&mut self, save_file: JsSaveFileCallback) {
+        self.save_file = Some(save_file);
+        self.app.can_snapshot = true;
+    }
+
     pub fn push_stdin_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        if let Some(recording) = &self.recording {
+            recording.input(bytes).map_err(|err| err.to_string())?;
+        }
         self.parser.advance(bytes, false);
 
-        let mut got_token = false;
         while let Some(seq) = self.parser.next() {
-            self.app.recv_sequence(seq)?;
-            got_token = true;
+            self.app.recv_sequence(seq, JsClock.now())?;
+            self.frames.mark_dirty();
         }
-        if got_token {
+        self.render_if_due()
+    }
+
+    /// Limits drawing to 60 frames per second. `timer` should call `tick()` after the given delay.
+    pub fn set_timer(&mut self, timer: JsTimer) {
+        self.timer = Some(timer);
+    }
+
+    /// Called by the host when the timer is up.
+    pub fn tick(&mut self) -> Result<()> {
+        self.frames.timeout();
+        self.render_if_due()
+    }
+
+    fn render_if_due(&mut self) -> Result<()> {
+        let due = match &self.timer {
+            Some(timer) => self.frames.poll(JsClock.now(), timer).map_err(|err| err.to_string())?,
+            // We can't come back later for the last frame, so draw every time.
+            None => self.frames.is_dirty(),
+        };
+        if due {
             self.render()?;
         }
         Ok(())
@@ -71,14 +129,42 @@ impl Main {
 
 
     pub fn render(&mut self) -> Result<()> {
+        self.frames.drew(JsClock.now());
+
         if self.app.beep {
             self.term.backend_mut().beep().map_err(|e| format!("{e}"))?;
             self.app.beep = false;
         }
 
-        self.term.draw( |frame| {
+        if self.app.redraw {
+            self.term.backend_mut().invalidate();
+            self.app.redraw = false;
+        }
+
+        let frame = self.term.draw( |frame| {
             self.app.render(frame.area(), frame.buffer_mut())
         }).map_err(|err| err.to_string())?;
+
+        if self.app.snapshot {
+            self.app.snapshot = false;
+            let svg = snapshot::to_svg(frame.buffer, &SnapshotOptions::default());
+            self.snapshot_count += 1;
+            let file_name = format!("regtest-snapshot-{}.svg", self.snapshot_count);
+            let saved = match &self.save_file {
+                None => false,
+                Some(save_file) => match save_file.save(&file_name, &svg) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        log::warn!("Couldn't save {file_name}: {err}");
+                        false
+                    }
+                },
+            };
+            if !saved {
+                self.term.backend_mut().beep().map_err(|e| format!("{e}"))?;
+                self.term.backend_mut().flush().map_err(|e| format!("{e}"))?;
+            }
+        }
         Ok(())
     }
 }
@@ -96,6 +182,11 @@ impl Drop for Main {
         if let Err(err) = self.term.backend_mut().flush() {
             log(err.to_string());
         }
+        if let Some(recording) = &self.recording
+            && let Err(err) = recording.finish()
+        {
+            log(err.to_string());
+        }
     }
 }
 
@@ -112,6 +203,15 @@ pub struct App {
     // Should we beep the terminal on the next render?
     beep: bool,
 
+    // Should we redraw the whole screen on the next render?
+    redraw: bool,
+
+    // Should we save a snapshot of the screen on the next render?
+    snapshot: bool,
+
+    // Whether the host has given us somewhere to save snapshots.
+    can_snapshot: bool,
+
     /// Was there an error compiling the regex or making the match?
     error: Option<String>,
 
@@ -124,9 +224,29 @@ pub struct App {
     seqs: VecDeque<Sequence>,
 
     active_widget: ActiveWidget,
-    
+
+    keymap: Keymap<Action>,
+}
+
+/// Things you can do with a key binding, anywhere in the app.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Quit,
+    NextWidget,
+    Redraw,
+    ToggleDebug,
+    Snapshot,
 }
 
+const BINDINGS: [(&str, Action); 6] = [
+    ("<Esc>", Action::Quit),
+    ("<C-c>", Action::Quit),
+    ("<Tab>", Action::NextWidget),
+    ("<C-l>", Action::Redraw),
+    ("<A-d>", Action::ToggleDebug),
+    ("<A-s>", Action::Snapshot),
+];
+
 impl Default for App {
     fn default() -> Self {
         let mut new_self = Self { 
@@ -136,36 +256,63 @@ impl Default for App {
             body: SAMPLE.into(),
             debug: false,
             beep: false,
+            redraw: false,
+            snapshot: false,
+            can_snapshot: false,
             error: None,
             matches: vec![],
             active_widget: Default::default(),
+            keymap: Keymap::new(),
         };
+        for (binding, action) in BINDINGS {
+            // These are constants, so we know they parse:
+            let _ = new_self.keymap.bind(binding, action);
+        }
         new_self.calc_matches();
         new_self
     }
 }
 
 impl App {
-    fn recv_sequence(&mut self, seq: Sequence) -> Result<()> {
+    fn recv_sequence(&mut self, seq: Sequence, now: Duration) -> Result<()> {
+        for matched in self.keymap.feed(&seq, now) {
+            match matched {
+                Matched::Action(action) => self.run(action)?,
+                Matched::Pending => {},
+                Matched::Unmatched(keys) if keys.is_empty() => self.recv_unbound(seq.clone()),
+                // Every key in a broken chord, not just the last one:
+                Matched::Unmatched(keys) => for key in keys {
+                    self.recv_unbound(Sequence::Key(key.code, key.modifiers));
+                },
+            }
+        }
+
+        self.add_debug_seq(seq);
+
+        Ok(())
+    }
+
+    fn run(&mut self, action: Action) -> Result<()> {
+        match action {
+            Action::Quit => Err("quit")?,
+            Action::NextWidget => self.active_widget.next(),
+            Action::Redraw => self.redraw = true,
+            Action::ToggleDebug => self.toggle_debug(),
+            Action::Snapshot => self.snapshot = true,
+        }
+        Ok(())
+    }
+
+    /// Handle input that isn't bound in the keymap.
+    fn recv_unbound(&mut self, seq: Sequence) {
         match seq {
             Sequence::Mouse(_,_) => {},
             Sequence::CursorPosition(_,_) => {},
+            Sequence::Raw(_) => {},
             Sequence::Key(code, modifiers) => match code {
-                KeyCode::Esc => {
-                    Err("quit")?;
-                },
-                KeyCode::Tab => {
-                    self.active_widget.next()
-                },
-                seq if seq.ctrl() == Some(Ctrl::C) => {
-                    Err("quit")?;
-                },
                 seq if seq.ctrl().is_some() => {
                     self.beep = true;
                 }
-                KeyCode::Char('d') if modifiers == KeyModifiers::ALT => {
-                    self.toggle_debug();
-                },
                 seq if self.delegate_input(seq) => {
                     // Input was handled by another widget.
                 },
@@ -184,10 +331,6 @@ impl App {
             },
 
         };
-
-        self.add_debug_seq(seq);
-
-        Ok(())
     }
 
     fn add_debug_seq(&mut self, seq: Sequence) {
@@ -298,16 +441,26 @@ impl Widget for &mut App {
             let match_txt = if matches == 0 { "".to_string() } else {
                 format!(" {matches} Matches ")
             };
-            let footer = Line::from(vec![
+            let keys = |action| self.keymap.keys_for(&action).unwrap_or_default();
+            let mut footer = vec![
                 " ".into(),
                 "Quit ".into(),
-                "<Esc>".fg(Color::Yellow).bold(),
+                keys(Action::Quit).fg(Color::Yellow).bold(),
                 " ".into(),
                 "Debug".into(),
                 " ".into(),
-                "<Alt-D>".fg(Color::Yellow).bold(),
+                keys(Action::ToggleDebug).fg(Color::Yellow).bold(),
                 " ".into(),
-            ]).fg(Color::White).centered();
+            ];
+            if self.can_snapshot {
+                footer.extend([
+                    "Snapshot".into(),
+                    " ".into(),
+                    keys(Action::Snapshot).fg(Color::Yellow).bold(),
+                    " ".into(),
+                ]);
+            }
+            let footer = Line::from(footer).fg(Color::White).centered();
     
             let match_txt = Line::from(match_txt).centered();
 
@@ -34,8 +34,8 @@ impl TextBox {
         self.text.as_str()
     }
     
-    pub fn handle_input(&mut self, seq: ratatui_wasm_backend::anes::parser::KeyCode) {
-        use ratatui_wasm_backend::anes::parser::KeyCode as Code;
+    pub fn handle_input(&mut self, seq: ratatui_wasm_backend::input::KeyCode) {
+        use ratatui_wasm_backend::input::KeyCode as Code;
         match seq {
             Code::Backspace => {
                 self.backspace()